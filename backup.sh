@@ -1,15 +1,12 @@
 #!/bin/bash
 
-HOST="localhost"
-PORT="54320"
-USER="ascii"
-PW="ascii"
-DB="ascii-pay"
+# Create a full backup of the database and all product images.
+# The connection settings are read from the environment or the `.env` file.
+# Restore the backup into an empty database with `ascii-pay-server import <file>`.
 
-PGPASSWORD=$PW pg_dump -h $HOST -p $PORT -U $USER -d $DB > db.sql 
+SERVER=${SERVER:-"./target/release/ascii-pay-server"}
 
 DATE=`date +"%Y-%m-%d_%H-%M-%S"`
 
-tar czf backup/backup_$DATE.tar.gz db.sql -C ./dist/ img
-
-rm db.sql
+mkdir -p backup
+$SERVER export backup/backup_$DATE.json
@@ -1,4 +1,5 @@
 use sass_rs;
+use std::env;
 use std::fs::{self, File};
 use std::io;
 use std::io::Write;
@@ -49,7 +50,49 @@ fn process_files(path: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// Write the versions of all migrations to `$OUT_DIR/migrations.rs`,
+/// so the server can check if the database schema is up to date.
+fn list_migrations(path: &Path) -> io::Result<()> {
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    let mut versions = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.path().join("up.sql").exists() {
+            // diesel uses the digits of the directory name before the first '_' as version
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let version: String = name
+                .split('_')
+                .next()
+                .unwrap_or("")
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .collect();
+            versions.push(version);
+        }
+    }
+    versions.sort();
+
+    let out_path =
+        Path::new(&env::var("OUT_DIR").expect("OUT_DIR must be set")).join("migrations.rs");
+    let mut file = File::create(out_path)?;
+    writeln!(file, "/// Versions of all migrations known to this binary")?;
+    writeln!(file, "pub const MIGRATIONS: &[&str] = &{:?};", versions)?;
+
+    Ok(())
+}
+
 fn main() {
+    // sqlite needs its own migrations, they must have the same versions as the postgres ones
+    let migrations_path = if env::var_os("CARGO_FEATURE_SQLITE").is_some() {
+        Path::new("migrations_sqlite/")
+    } else {
+        Path::new("migrations/")
+    };
+    if let Err(e) = list_migrations(migrations_path) {
+        panic!("{}", e);
+    }
+
     // build any sass files found in the stylesheets folder
     let sass_path = Path::new("static/stylesheets/");
     if !sass_path.exists() {
@@ -0,0 +1,9 @@
+ALTER TABLE "product" ADD COLUMN "active" BOOLEAN DEFAULT 't' NOT NULL;
+
+CREATE TABLE "product_availability" (
+  "id" UUID PRIMARY KEY NOT NULL,
+  "product_id" UUID NOT NULL,
+  "date_start" DATE,
+  "date_end" DATE,
+  "weekdays" SMALLINT DEFAULT 127 NOT NULL
+);
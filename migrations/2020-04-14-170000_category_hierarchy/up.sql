@@ -0,0 +1 @@
+ALTER TABLE "category" ADD COLUMN "parent" UUID;
@@ -0,0 +1,3 @@
+ALTER TABLE "transaction" DROP COLUMN "terminal_id";
+
+DROP TABLE "terminal";
@@ -0,0 +1,13 @@
+CREATE TABLE "auth_failure" (
+  "id" UUID PRIMARY KEY NOT NULL,
+  "created" TIMESTAMP NOT NULL,
+  "kind" VARCHAR NOT NULL,
+  "ip" VARCHAR NOT NULL,
+  "login" VARCHAR,
+  "account_id" UUID,
+  "terminal_id" UUID,
+  "unlocked" TIMESTAMP
+);
+
+CREATE INDEX "auth_failure_ip" ON "auth_failure" ("ip", "created");
+CREATE INDEX "auth_failure_account" ON "auth_failure" ("account_id", "created");
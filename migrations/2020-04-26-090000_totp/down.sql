@@ -0,0 +1,3 @@
+ALTER TABLE "session" DROP COLUMN "second_factor_pending";
+DROP TABLE "authentication_totp_recovery";
+DROP TABLE "authentication_totp";
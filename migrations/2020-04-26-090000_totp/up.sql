@@ -0,0 +1,15 @@
+CREATE TABLE "authentication_totp" (
+  "account_id" UUID PRIMARY KEY NOT NULL,
+  "secret" VARCHAR NOT NULL,
+  "confirmed" BOOLEAN DEFAULT FALSE NOT NULL,
+  "last_step" BIGINT,
+  "created" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE "authentication_totp_recovery" (
+  "account_id" UUID NOT NULL,
+  "code_hash" VARCHAR NOT NULL,
+  PRIMARY KEY ("account_id", "code_hash")
+);
+
+ALTER TABLE "session" ADD COLUMN "second_factor_pending" BOOLEAN DEFAULT FALSE NOT NULL;
@@ -0,0 +1,14 @@
+DROP TABLE "account";
+DROP TABLE "authentication_barcode";
+DROP TABLE "authentication_password";
+DROP TABLE "authentication_password_invitation";
+DROP TABLE "authentication_nfc";
+DROP TABLE "authentication_nfc_write_key";
+DROP TABLE "transaction";
+DROP TABLE "category";
+DROP TABLE "category_price";
+DROP TABLE "product";
+DROP TABLE "product_barcode";
+DROP TABLE "product_price";
+DROP TABLE "transaction_product";
+DROP TABLE "session";
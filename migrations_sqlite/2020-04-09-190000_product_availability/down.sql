@@ -0,0 +1,2 @@
+DROP TABLE "product_availability";
+ALTER TABLE "product" DROP COLUMN "active";
@@ -0,0 +1,9 @@
+ALTER TABLE "product" ADD COLUMN "active" BOOLEAN DEFAULT 1 NOT NULL;
+
+CREATE TABLE "product_availability" (
+  "id" TEXT PRIMARY KEY NOT NULL,
+  "product_id" TEXT NOT NULL,
+  "date_start" DATE,
+  "date_end" DATE,
+  "weekdays" SMALLINT DEFAULT 127 NOT NULL
+);
@@ -0,0 +1,4 @@
+ALTER TABLE "product" DROP COLUMN "icon";
+ALTER TABLE "product" DROP COLUMN "color";
+ALTER TABLE "product" DROP COLUMN "quick_key";
+ALTER TABLE "product" DROP COLUMN "sort_order";
@@ -0,0 +1,4 @@
+ALTER TABLE "product" ADD COLUMN "sort_order" INTEGER DEFAULT 0 NOT NULL;
+ALTER TABLE "product" ADD COLUMN "quick_key" BOOLEAN DEFAULT 0 NOT NULL;
+ALTER TABLE "product" ADD COLUMN "color" VARCHAR;
+ALTER TABLE "product" ADD COLUMN "icon" VARCHAR;
@@ -0,0 +1,11 @@
+CREATE TABLE "terminal" (
+  "id" TEXT PRIMARY KEY NOT NULL,
+  "name" VARCHAR(64) NOT NULL,
+  "location" VARCHAR,
+  "key_hash" VARCHAR NOT NULL UNIQUE,
+  "created" TIMESTAMP NOT NULL,
+  "last_seen" TIMESTAMP,
+  "revoked" TIMESTAMP
+);
+
+ALTER TABLE "transaction" ADD COLUMN "terminal_id" TEXT;
@@ -0,0 +1,2 @@
+DROP INDEX "terminal_certificate_subject";
+ALTER TABLE "terminal" DROP COLUMN "certificate_subject";
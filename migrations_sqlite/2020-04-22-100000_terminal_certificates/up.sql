@@ -0,0 +1,2 @@
+ALTER TABLE "terminal" ADD COLUMN "certificate_subject" VARCHAR;
+CREATE UNIQUE INDEX "terminal_certificate_subject" ON "terminal" ("certificate_subject");
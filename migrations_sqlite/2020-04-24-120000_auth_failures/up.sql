@@ -0,0 +1,13 @@
+CREATE TABLE "auth_failure" (
+  "id" TEXT PRIMARY KEY NOT NULL,
+  "created" TIMESTAMP NOT NULL,
+  "kind" VARCHAR NOT NULL,
+  "ip" VARCHAR NOT NULL,
+  "login" VARCHAR,
+  "account_id" TEXT,
+  "terminal_id" TEXT,
+  "unlocked" TIMESTAMP
+);
+
+CREATE INDEX "auth_failure_ip" ON "auth_failure" ("ip", "created");
+CREATE INDEX "auth_failure_account" ON "auth_failure" ("account_id", "created");
@@ -0,0 +1,15 @@
+CREATE TABLE "authentication_totp" (
+  "account_id" TEXT PRIMARY KEY NOT NULL,
+  "secret" VARCHAR NOT NULL,
+  "confirmed" BOOLEAN DEFAULT 0 NOT NULL,
+  "last_step" BIGINT,
+  "created" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE "authentication_totp_recovery" (
+  "account_id" TEXT NOT NULL,
+  "code_hash" VARCHAR NOT NULL,
+  PRIMARY KEY ("account_id", "code_hash")
+);
+
+ALTER TABLE "session" ADD COLUMN "second_factor_pending" BOOLEAN DEFAULT 0 NOT NULL;
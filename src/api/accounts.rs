@@ -1,13 +1,12 @@
 use crate::core::{
     authentication_barcode, authentication_nfc, Account, Permission, Pool, ServiceError,
-    ServiceResult,
+    ServiceResult, Session, Uuid,
 };
 use crate::identity_policy::{Action, RetrievedAccount};
-use crate::login_or_client_cert_required;
+use crate::login_or_terminal_required;
 use crate::web::admin::accounts::SearchAccount;
 use crate::web::utils::Search;
 use actix_web::{web, HttpRequest, HttpResponse};
-use uuid::Uuid;
 
 /// GET route for `/api/v1/accounts`
 pub async fn get_accounts(
@@ -16,7 +15,7 @@ pub async fn get_accounts(
     query: web::Query<Search>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -46,7 +45,7 @@ pub async fn put_accounts(
     account: web::Json<Account>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -78,7 +77,7 @@ pub async fn get_account(
     account_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -100,7 +99,7 @@ pub async fn post_account(
     account_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -108,8 +107,8 @@ pub async fn post_account(
     );
 
     if *account_id != account.id {
-        return Err(ServiceError::BadRequest(
-            "Id missmage",
+        return Err(ServiceError::invalid_field(
+            "id",
             "The account id of the url and the json do not match!".to_owned(),
         ));
     }
@@ -123,10 +122,16 @@ pub async fn post_account(
     server_account.mail = account.mail.clone();
     server_account.username = account.username.clone();
     server_account.account_number = account.account_number.clone();
+    let promoted = !server_account.permission.is_admin() && account.permission.is_admin();
     server_account.permission = account.permission;
 
     server_account.update(&conn)?;
 
+    // Sessions of the old permission did not need a second factor
+    if promoted {
+        Session::delete_by_account(&conn, &server_account.id, None)?;
+    }
+
     Ok(HttpResponse::Ok().finish())
 }
 
@@ -137,16 +142,16 @@ pub async fn delete_account(
     _account_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
         Action::FORBIDDEN
     );
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
-    Ok(HttpResponse::MethodNotAllowed().finish())
+    Err(ServiceError::NotSupported)
 }
 
 #[derive(Debug, Deserialize)]
@@ -167,7 +172,7 @@ pub async fn put_account_barcode(
     account_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -189,7 +194,7 @@ pub async fn delete_account_barcode(
     account_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -212,7 +217,7 @@ pub async fn put_account_nfc(
     account_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -234,7 +239,7 @@ pub async fn delete_account_nfc(
     account_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
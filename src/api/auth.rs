@@ -1,12 +1,15 @@
-use crate::core::{authentication_password, Pool, ServiceResult};
-use crate::identity_policy::{LoggedAccount, RetrievedAccount};
+use crate::core::{auth_failures, authentication_totp, Pool, ServiceError, ServiceResult};
+use crate::identity_policy::{client_ip, LoggedAccount, RetrievedAccount};
 use actix_identity::Identity;
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 
 #[derive(Serialize, Deserialize)]
 pub struct LoginForm {
     username: String,
     password: String,
+    /// Code of the authenticator app or a recovery code, if the account has a second factor
+    #[serde(default)]
+    code: Option<String>,
 }
 
 /// GET route for `/api/v1/auth`
@@ -15,7 +18,7 @@ pub async fn get_auth(logged_account: RetrievedAccount) -> ServiceResult<HttpRes
         RetrievedAccount::Acc(logged_account) => {
             Ok(HttpResponse::Ok().json(logged_account.account))
         }
-        RetrievedAccount::Nothing => Ok(HttpResponse::Unauthorized().finish()),
+        RetrievedAccount::Nothing => Err(ServiceError::Unauthorized),
     }
 }
 
@@ -24,17 +27,38 @@ pub async fn post_auth(
     pool: web::Data<Pool>,
     id: Identity,
     params: web::Json<LoginForm>,
+    request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
     let conn = &pool.get()?;
+    let ip = client_ip(&request);
 
-    let login_result = authentication_password::get(conn, &params.username, &params.password);
+    let login_result =
+        auth_failures::authenticate_password(conn, &ip, &params.username, &params.password);
     match login_result {
         Ok(account) => {
-            LoggedAccount::new(&conn, account)?.save(id)?;
+            if authentication_totp::is_required(&conn, &account)? {
+                // Accounts without an authenticator app have to set it up at the web login first
+                match &params.code {
+                    Some(code) if authentication_totp::is_enabled(&conn, &account)? => {
+                        match auth_failures::authenticate_second_factor(conn, &ip, &account, code) {
+                            Err(ServiceError::NotFound) => return Err(ServiceError::Unauthorized),
+                            result => result?,
+                        }
+                    }
+                    _ => return Err(ServiceError::SecondFactorRequired),
+                }
+            }
+
+            let mut logged_account = LoggedAccount::new(&conn, account)?;
+            if logged_account.second_factor_pending {
+                logged_account.verify_second_factor(&conn)?;
+            }
+            logged_account.save(id)?;
 
             Ok(HttpResponse::Ok().finish())
         }
-        Err(_) => Ok(HttpResponse::Unauthorized().finish()),
+        Err(e @ ServiceError::TooManyRequests(_)) => Err(e),
+        Err(_) => Err(ServiceError::Unauthorized),
     }
 }
 
@@ -1,10 +1,9 @@
-use crate::core::{Category, Permission, Pool, ServiceError, ServiceResult};
+use crate::core::{Category, Permission, Pool, ServiceError, ServiceResult, Uuid};
 use crate::identity_policy::{Action, RetrievedAccount};
-use crate::login_or_client_cert_required;
+use crate::login_or_terminal_required;
 use crate::web::admin::categories::SearchCategory;
 use crate::web::utils::Search;
 use actix_web::{web, HttpRequest, HttpResponse};
-use uuid::Uuid;
 
 /// GET route for `/api/v1/categories`
 pub async fn get_categories(
@@ -13,7 +12,7 @@ pub async fn get_categories(
     query: web::Query<Search>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -28,9 +27,9 @@ pub async fn get_categories(
     };
 
     let lower_search = search.trim().to_ascii_lowercase();
-    let search_categories: Vec<SearchCategory> = Category::all(&conn)?
+    let search_categories: Vec<SearchCategory> = Category::tree(&conn)?
         .into_iter()
-        .filter_map(|c| SearchCategory::wrap(c, &lower_search))
+        .filter_map(|c| SearchCategory::wrap_tree(c, &lower_search, 0))
         .collect();
 
     Ok(HttpResponse::Ok().json(&search_categories))
@@ -43,7 +42,7 @@ pub async fn put_categories(
     category: web::Json<Category>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -54,6 +53,9 @@ pub async fn put_categories(
 
     let mut server_category = Category::create(&conn, &category.name)?;
 
+    server_category.parent = category.parent;
+    server_category.update(&conn)?;
+
     server_category.update_prices(&conn, &category.prices)?;
 
     Ok(HttpResponse::Created().json(json!({
@@ -68,7 +70,7 @@ pub async fn get_category(
     category_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -90,7 +92,7 @@ pub async fn post_category(
     category_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -98,8 +100,8 @@ pub async fn post_category(
     );
 
     if *category_id != category.id {
-        return Err(ServiceError::BadRequest(
-            "Id missmage",
+        return Err(ServiceError::invalid_field(
+            "id",
             "The category id of the url and the json do not match!".to_owned(),
         ));
     }
@@ -109,6 +111,7 @@ pub async fn post_category(
     let mut server_category = Category::get(&conn, &category_id)?;
 
     server_category.name = category.name.clone();
+    server_category.parent = category.parent;
     server_category.update(&conn)?;
 
     server_category.update_prices(&conn, &category.prices)?;
@@ -122,14 +125,14 @@ pub async fn delete_category(
     _category_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
         Action::FORBIDDEN
     );
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
-    Ok(HttpResponse::MethodNotAllowed().finish())
+    Err(ServiceError::NotSupported)
 }
@@ -1,9 +1,11 @@
-use crate::client_cert_required;
+use crate::core::auth_failures::{self, FailureKind};
 use crate::core::authentication_nfc::NfcResult;
 use crate::core::{
-    authentication_barcode, authentication_nfc, Account, Pool, Product, ServiceResult,
+    authentication_barcode, authentication_nfc, Account, DbConnection, Pool, Product, ServiceError,
+    ServiceResult, Uuid,
 };
-use crate::identity_policy::Action;
+use crate::identity_policy::{client_ip, Action};
+use crate::terminal_required;
 
 use actix_web::{web, HttpRequest, HttpResponse};
 
@@ -33,6 +35,8 @@ pub enum IdentificationResponse {
     },
     Product {
         product: Product,
+        /// The id of the identified variant if the barcode belongs to a variant of `product`
+        variant: Option<Uuid>,
     },
     AuthenticationNeeded {
         id: String,
@@ -47,33 +51,70 @@ pub enum IdentificationResponse {
 }
 
 /// POST route for `/api/v1/identify`
+///
+/// Unknown barcodes and nfc cards are recorded, so a client ip that tries too many of them is
+/// locked out.
 pub async fn post_identify(
     pool: web::Data<Pool>,
     identification_request: web::Json<IdentificationRequest>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    client_cert_required!(request, Action::FORBIDDEN);
+    let terminal = terminal_required!(request, Action::FORBIDDEN);
 
     let conn = &pool.get()?;
+    let ip = client_ip(&request);
+    auth_failures::check_ip(&conn, FailureKind::Identify, &ip)?;
+
     let identification_request = identification_request.into_inner();
+    let code = match &identification_request {
+        IdentificationRequest::Barcode { code } => code.clone(),
+        IdentificationRequest::Nfc { id } | IdentificationRequest::NfcSecret { id, .. } => {
+            id.clone()
+        }
+    };
+
+    match identify(&conn, identification_request) {
+        Ok(response) => Ok(HttpResponse::Ok().json(&response)),
+        Err(ServiceError::NotFound) => {
+            auth_failures::record(
+                &conn,
+                FailureKind::Identify,
+                &ip,
+                Some(&code),
+                None,
+                Some(terminal.id),
+            )?;
+            Err(ServiceError::NotFound)
+        }
+        Err(e) => Err(e),
+    }
+}
 
+fn identify(
+    conn: &DbConnection,
+    identification_request: IdentificationRequest,
+) -> ServiceResult<IdentificationResponse> {
     match identification_request {
         IdentificationRequest::Barcode { code } => {
             if let Ok(product) = Product::get_by_barcode(&conn, &code) {
-                return Ok(HttpResponse::Ok().json(&IdentificationResponse::Product { product }));
+                // Always respond with the parent product so the terminal can offer all variants
+                let (product, variant) = match product.parent {
+                    Some(parent_id) => (Product::get(&conn, &parent_id)?, Some(product.id)),
+                    None => (product, None),
+                };
+                return Ok(IdentificationResponse::Product { product, variant });
             }
 
             let account = authentication_barcode::get(&conn, &code)?;
-            Ok(HttpResponse::Ok().json(&IdentificationResponse::Account { account }))
+            Ok(IdentificationResponse::Account { account })
         }
         IdentificationRequest::Nfc { id } => match authentication_nfc::get(&conn, &id)? {
-            NfcResult::Ok { account } => {
-                Ok(HttpResponse::Ok().json(&IdentificationResponse::Account { account }))
+            NfcResult::Ok { account } => Ok(IdentificationResponse::Account { account }),
+            NfcResult::AuthenticationRequested { key, challenge } => {
+                Ok(IdentificationResponse::AuthenticationNeeded { id, key, challenge })
             }
-            NfcResult::AuthenticationRequested { key, challenge } => Ok(HttpResponse::Ok()
-                .json(&IdentificationResponse::AuthenticationNeeded { id, key, challenge })),
             NfcResult::WriteKey { key, secret } => {
-                Ok(HttpResponse::Ok().json(&IdentificationResponse::WriteKey { id, key, secret }))
+                Ok(IdentificationResponse::WriteKey { id, key, secret })
             }
         },
         IdentificationRequest::NfcSecret {
@@ -83,7 +124,7 @@ pub async fn post_identify(
         } => {
             let account =
                 authentication_nfc::get_challenge_response(&conn, &id, &challenge, &response)?;
-            Ok(HttpResponse::Ok().json(&IdentificationResponse::Account { account }))
+            Ok(IdentificationResponse::Account { account })
         }
     }
 }
@@ -0,0 +1,26 @@
+use crate::core::{layouts, Permission, Pool, ServiceResult};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_or_terminal_required;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Local;
+
+/// GET route for `/api/v1/layout`
+///
+/// Lists the currently available products grouped by category with their quick keys
+pub async fn get_layout(
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_or_terminal_required!(
+        request,
+        logged_account,
+        Permission::MEMBER,
+        Action::FORBIDDEN
+    );
+    let conn = &pool.get()?;
+
+    let layout = layouts::get_layout_at(&conn, &Local::now().naive_local())?;
+
+    Ok(HttpResponse::Ok().json(&layout))
+}
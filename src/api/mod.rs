@@ -2,77 +2,182 @@ pub mod accounts;
 pub mod auth;
 pub mod categories;
 pub mod identification;
+pub mod layout;
+pub mod openapi;
 pub mod products;
+pub mod stats;
 pub mod transactions;
 
-use actix_web::web;
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
+use actix_web::http::Method;
+use actix_web::{web, Error, HttpRequest, HttpResponse, Route};
+
+use crate::core::{ServiceError, ServiceResult};
+use crate::web::metrics::{resource, scope};
+
+/// Respond with a structured error if the json body cannot be parsed
+fn json_error_handler(error: JsonPayloadError, _: &HttpRequest) -> Error {
+    match error {
+        JsonPayloadError::ContentType => ServiceError::UnsupportedMediaType(
+            "Invalid content type",
+            "The request body has to be 'application/json'".to_owned(),
+        ),
+        error => ServiceError::BadRequest("Invalid json", format!("{}", error)),
+    }
+    .into()
+}
+
+/// Respond with a structured error if the path parameters cannot be parsed, eg. invalid uuids
+fn path_error_handler(error: PathError, _: &HttpRequest) -> Error {
+    ServiceError::BadRequest("Invalid path", format!("{}", error)).into()
+}
+
+/// Respond with a structured error if the query string cannot be parsed
+fn query_error_handler(error: QueryPayloadError, _: &HttpRequest) -> Error {
+    ServiceError::BadRequest("Invalid query", format!("{}", error)).into()
+}
+
+/// Respond with a structured error for all unknown api routes
+async fn not_found() -> ServiceResult<HttpResponse> {
+    Err(ServiceError::NotFound)
+}
+
+/// All routes of the api as `(path, method, route)`
+///
+/// Routes with the same path are registered as one resource, resources are matched in the order
+/// of their first route.
+pub fn routes() -> Vec<(&'static str, Method, Route)> {
+    vec![
+        (
+            "/identify",
+            Method::POST,
+            web::to(identification::post_identify),
+        ),
+        ("/auth", Method::GET, web::to(auth::get_auth)),
+        ("/auth", Method::POST, web::to(auth::post_auth)),
+        ("/auth", Method::DELETE, web::to(auth::delete_auth)),
+        // Setup account mangement related routes
+        ("/accounts", Method::GET, web::to(accounts::get_accounts)),
+        ("/accounts", Method::PUT, web::to(accounts::put_accounts)),
+        (
+            "/account/{account_id}/barcode",
+            Method::PUT,
+            web::to(accounts::put_account_barcode),
+        ),
+        (
+            "/account/{account_id}/barcode",
+            Method::DELETE,
+            web::to(accounts::delete_account_barcode),
+        ),
+        (
+            "/account/{account_id}/nfc",
+            Method::PUT,
+            web::to(accounts::put_account_nfc),
+        ),
+        (
+            "/account/{account_id}/nfc",
+            Method::DELETE,
+            web::to(accounts::delete_account_nfc),
+        ),
+        (
+            "/account/{account_id}",
+            Method::GET,
+            web::to(accounts::get_account),
+        ),
+        (
+            "/account/{account_id}",
+            Method::POST,
+            web::to(accounts::post_account),
+        ),
+        (
+            "/account/{account_id}",
+            Method::DELETE,
+            web::to(accounts::delete_account),
+        ),
+        (
+            "/transaction/token",
+            Method::POST,
+            web::to(transactions::post_transaction_token),
+        ),
+        (
+            "/transaction/payment",
+            Method::POST,
+            web::to(transactions::post_transaction_payment),
+        ),
+        // Setup product mangement related routes
+        ("/products", Method::GET, web::to(products::get_products)),
+        ("/products", Method::PUT, web::to(products::put_products)),
+        (
+            "/product/{product_id}",
+            Method::GET,
+            web::to(products::get_product),
+        ),
+        (
+            "/product/{product_id}",
+            Method::POST,
+            web::to(products::post_product),
+        ),
+        (
+            "/product/{product_id}",
+            Method::DELETE,
+            web::to(products::delete_product),
+        ),
+        ("/layout", Method::GET, web::to(layout::get_layout)),
+        ("/stats", Method::GET, web::to(stats::get_stats)),
+        // Setup categories mangement related routes
+        (
+            "/categories",
+            Method::GET,
+            web::to(categories::get_categories),
+        ),
+        (
+            "/categories",
+            Method::PUT,
+            web::to(categories::put_categories),
+        ),
+        (
+            "/category/{category_id}",
+            Method::GET,
+            web::to(categories::get_category),
+        ),
+        (
+            "/category/{category_id}",
+            Method::POST,
+            web::to(categories::post_category),
+        ),
+        (
+            "/category/{category_id}",
+            Method::DELETE,
+            web::to(categories::delete_category),
+        ),
+        // Setup api documentation routes
+        ("/openapi.json", Method::GET, web::to(openapi::get_openapi)),
+        ("/docs", Method::GET, web::to(openapi::get_docs)),
+    ]
+}
 
 /// Setup routes for admin ui
 pub fn init(config: &mut web::ServiceConfig) {
-    config.service(
-        web::scope("/api/v1")
-            .service(
-                web::resource("/identify").route(web::post().to(identification::post_identify)),
-            )
-            .service(
-                web::resource("/auth")
-                    .route(web::get().to(auth::get_auth))
-                    .route(web::post().to(auth::post_auth))
-                    .route(web::delete().to(auth::delete_auth)),
-            )
-            // Setup account mangement related routes
-            .service(
-                web::resource("/accounts")
-                    .route(web::get().to(accounts::get_accounts))
-                    .route(web::put().to(accounts::put_accounts)),
-            )
-            .service(
-                web::resource("/account/{account_id}/barcode")
-                    .route(web::put().to(accounts::put_account_barcode))
-                    .route(web::delete().to(accounts::delete_account_barcode)),
-            )
-            .service(
-                web::resource("/account/{account_id}/nfc")
-                    .route(web::put().to(accounts::put_account_nfc))
-                    .route(web::delete().to(accounts::delete_account_nfc)),
-            )
-            .service(
-                web::resource("/account/{account_id}")
-                    .route(web::get().to(accounts::get_account))
-                    .route(web::post().to(accounts::post_account))
-                    .route(web::delete().to(accounts::delete_account)),
-            )
-            .service(
-                web::resource("/transaction/token")
-                    .route(web::post().to(transactions::post_transaction_token)),
-            )
-            .service(
-                web::resource("/transaction/payment")
-                    .route(web::post().to(transactions::post_transaction_payment)),
-            )
-            // Setup product mangement related routes
-            .service(
-                web::resource("/products")
-                    .route(web::get().to(products::get_products))
-                    .route(web::put().to(products::put_products)),
-            )
-            .service(
-                web::resource("/product/{product_id}")
-                    .route(web::get().to(products::get_product))
-                    .route(web::post().to(products::post_product))
-                    .route(web::delete().to(products::delete_product)),
-            )
-            // Setup categories mangement related routes
-            .service(
-                web::resource("/categories")
-                    .route(web::get().to(categories::get_categories))
-                    .route(web::put().to(categories::put_categories)),
-            )
-            .service(
-                web::resource("/category/{category_id}")
-                    .route(web::get().to(categories::get_category))
-                    .route(web::post().to(categories::post_category))
-                    .route(web::delete().to(categories::delete_category)),
-            ),
-    );
+    let mut resources: Vec<(&str, Vec<Route>)> = Vec::new();
+    for (path, method, route) in routes() {
+        let route = route.method(method);
+        match resources.iter_mut().find(|(p, _)| *p == path) {
+            Some((_, routes)) => routes.push(route),
+            None => resources.push((path, vec![route])),
+        }
+    }
+
+    let mut api = scope("/api/v1")
+        .app_data(web::JsonConfig::default().error_handler(json_error_handler))
+        .app_data(web::PathConfig::default().error_handler(path_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(query_error_handler));
+    for (path, routes) in resources {
+        api = api.service(
+            routes
+                .into_iter()
+                .fold(resource(path), |resource, route| resource.route(route)),
+        );
+    }
+
+    config.service(api.default_service(web::route().to(not_found)));
 }
@@ -0,0 +1,1366 @@
+//! OpenAPI 3 description of all `/api/v1` routes.
+//!
+//! The document is written by hand next to the routes in `api::init`. The tests of this module
+//! fail if a route is added to `api::init` without documenting it here.
+use actix_web::{web, HttpRequest, HttpResponse};
+use handlebars::Handlebars;
+use serde_json::Value;
+
+use crate::core::{config, ErrorCode, ServiceResult, AUTH_COOKIE_NAME};
+use crate::web::utils::HbData;
+
+/// Reference to a schema of the `components` section
+fn schema(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+/// Array of the given schema
+fn array(items: Value) -> Value {
+    json!({"type": "array", "items": items})
+}
+
+/// Required json request body with the given schema
+fn json_body(schema: Value) -> Value {
+    json!({
+        "required": true,
+        "content": {"application/json": {"schema": schema}}
+    })
+}
+
+/// Response with a json body with the given schema
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": schema}}
+    })
+}
+
+/// Response without a body
+fn empty_response(description: &str) -> Value {
+    json!({ "description": description })
+}
+
+/// Uuid path parameter
+fn id_parameter(name: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "path",
+        "required": true,
+        "schema": {"type": "string", "format": "uuid"}
+    })
+}
+
+/// Optional search query parameter
+fn search_parameter() -> Value {
+    json!({
+        "name": "search",
+        "in": "query",
+        "description": "Only list entries that contain the search term",
+        "schema": {"type": "string"}
+    })
+}
+
+/// Error response with the json body of `ServiceError`
+fn error_response(description: &str) -> Value {
+    json_response(description, schema("Error"))
+}
+
+/// Add the error responses every route can return to the `responses` of `operation`
+fn with_errors(mut operation: Value) -> Value {
+    let responses = operation["responses"]
+        .as_object_mut()
+        .expect("Operation without responses");
+    let errors = [
+        ("400", "Malformed request, eg. invalid json"),
+        ("401", "Not logged in or authentication failed"),
+        (
+            "403",
+            "Insufficient privileges, missing terminal key or client certificate",
+        ),
+        ("422", "Some fields have invalid values"),
+        ("500", "The request could not be processed"),
+        (
+            "503",
+            "The database is not available, the request can be retried later",
+        ),
+    ];
+    for (status, description) in &errors {
+        responses
+            .entry(*status)
+            .or_insert_with(|| error_response(description));
+    }
+    operation
+}
+
+fn auth_paths() -> Value {
+    json!({
+        "/api/v1/auth": {
+            "get": with_errors(json!({
+                "tags": ["auth"],
+                "summary": "Get the logged in account",
+                "security": [{"session": []}],
+                "responses": {"200": json_response("The logged in account", schema("Account"))}
+            })),
+            "post": with_errors(json!({
+                "tags": ["auth"],
+                "summary": "Login with username and password",
+                "description": "Sets the session cookie on success. Accounts with an authenticator app need the `code`, without it the login fails with `second_factor_required`. Repeated failures lock out the client ip and the account for some time.",
+                "security": [],
+                "requestBody": json_body(schema("LoginForm")),
+                "responses": {
+                    "200": empty_response("Logged in"),
+                    "401": error_response("Wrong login, password or code, or `second_factor_required`"),
+                    "429": error_response("Too many failed logins, retry after the `Retry-After` header")
+                }
+            })),
+            "delete": with_errors(json!({
+                "tags": ["auth"],
+                "summary": "Logout",
+                "security": [{"session": []}],
+                "responses": {"200": empty_response("Logged out")}
+            }))
+        }
+    })
+}
+
+fn account_paths() -> Value {
+    json!({
+        "/api/v1/accounts": {
+            "get": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "List all accounts",
+                "parameters": [search_parameter()],
+                "responses": {"200": json_response("All matching accounts", array(schema("SearchAccount")))}
+            })),
+            "put": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Create a new account",
+                "requestBody": json_body(schema("Account")),
+                "responses": {"201": json_response("The account was created", schema("Created"))}
+            }))
+        },
+        "/api/v1/account/{account_id}": {
+            "parameters": [id_parameter("account_id")],
+            "get": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Get an account",
+                "responses": {
+                    "200": json_response("The account", schema("Account")),
+                    "404": error_response("Unknown account")
+                }
+            })),
+            "post": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Update an account",
+                "description": "The `credit` is ignored, it can only be changed by transactions.",
+                "requestBody": json_body(schema("Account")),
+                "responses": {
+                    "200": empty_response("The account was updated"),
+                    "404": error_response("Unknown account")
+                }
+            })),
+            "delete": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Delete an account",
+                "description": "Accounts cannot be deleted yet.",
+                "responses": {"405": error_response("Not supported")}
+            }))
+        },
+        "/api/v1/account/{account_id}/barcode": {
+            "parameters": [id_parameter("account_id")],
+            "put": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Set the barcode of an account",
+                "requestBody": json_body(schema("AccountBarcode")),
+                "responses": {"200": empty_response("The barcode was set")}
+            })),
+            "delete": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Remove the barcode of an account",
+                "responses": {"200": empty_response("The barcode was removed")}
+            }))
+        },
+        "/api/v1/account/{account_id}/nfc": {
+            "parameters": [id_parameter("account_id")],
+            "put": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Set the nfc card of an account",
+                "requestBody": json_body(schema("AccountNfc")),
+                "responses": {"200": empty_response("The nfc card was set")}
+            })),
+            "delete": with_errors(json!({
+                "tags": ["accounts"],
+                "summary": "Remove the nfc card of an account",
+                "responses": {"200": empty_response("The nfc card was removed")}
+            }))
+        }
+    })
+}
+
+fn terminal_paths() -> Value {
+    json!({
+        "/api/v1/identify": {
+            "post": with_errors(json!({
+                "tags": ["terminal"],
+                "summary": "Identify a scanned barcode or nfc card",
+                "description": "Barcodes of products return the product, all other barcodes and nfc cards return the account. \
+                    Secure nfc cards first return a challenge that has to be answered with a `nfc-secret` request.",
+                "security": [{"terminal_key": []}],
+                "requestBody": json_body(schema("IdentificationRequest")),
+                "responses": {
+                    "200": json_response("The identified account or product", schema("IdentificationResponse")),
+                    "404": error_response("Unknown barcode or nfc card"),
+                    "429": error_response("Too many unknown barcodes or nfc cards, retry after the `Retry-After` header")
+                }
+            }))
+        },
+        "/api/v1/transaction/token": {
+            "post": with_errors(json!({
+                "tags": ["terminal"],
+                "summary": "Authenticate an account for a payment",
+                "description": "The returned token can be used once for a payment of exactly `amount`.",
+                "security": [{"terminal_key": []}],
+                "requestBody": json_body(schema("TokenRequest")),
+                "responses": {
+                    "200": json_response("The payment token or a nfc challenge", schema("TokenResponse")),
+                    "404": error_response("Unknown barcode or nfc card")
+                }
+            }))
+        },
+        "/api/v1/transaction/payment": {
+            "post": with_errors(json!({
+                "tags": ["terminal"],
+                "summary": "Execute a payment",
+                "description": "Fails if the credit of the account would drop below its `minimum_credit`.",
+                "security": [{"terminal_key": []}],
+                "requestBody": json_body(schema("PaymentRequest")),
+                "responses": {
+                    "200": json_response("The executed transaction", schema("PaymentResponse")),
+                    "401": error_response("The token is invalid or was already used (`invalid_token`)"),
+                    "402": error_response("The credit of the account is not sufficient (`insufficient_credit`)")
+                }
+            }))
+        },
+        "/api/v1/layout": {
+            "get": with_errors(json!({
+                "tags": ["terminal"],
+                "summary": "Get the product layout of the terminal",
+                "description": "Lists the currently available products grouped by category with their quick keys.",
+                "responses": {"200": json_response("The layout", array(schema("CategoryLayout")))}
+            }))
+        }
+    })
+}
+
+fn product_paths() -> Value {
+    json!({
+        "/api/v1/products": {
+            "get": with_errors(json!({
+                "tags": ["products"],
+                "summary": "List all currently available products",
+                "parameters": [
+                    search_parameter(),
+                    {
+                        "name": "all",
+                        "in": "query",
+                        "description": "Include unavailable products",
+                        "schema": {"type": "boolean", "default": false}
+                    }
+                ],
+                "responses": {"200": json_response("All matching products", array(schema("SearchProduct")))}
+            })),
+            "put": with_errors(json!({
+                "tags": ["products"],
+                "summary": "Create a new product",
+                "requestBody": json_body(schema("Product")),
+                "responses": {"201": json_response("The product was created", schema("Created"))}
+            }))
+        },
+        "/api/v1/product/{product_id}": {
+            "parameters": [id_parameter("product_id")],
+            "get": with_errors(json!({
+                "tags": ["products"],
+                "summary": "Get a product",
+                "responses": {
+                    "200": json_response("The product", schema("Product")),
+                    "404": error_response("Unknown product")
+                }
+            })),
+            "post": with_errors(json!({
+                "tags": ["products"],
+                "summary": "Update a product",
+                "requestBody": json_body(schema("Product")),
+                "responses": {
+                    "200": empty_response("The product was updated"),
+                    "404": error_response("Unknown product")
+                }
+            })),
+            "delete": with_errors(json!({
+                "tags": ["products"],
+                "summary": "Delete a product",
+                "description": "Products cannot be deleted yet, deactivate them instead.",
+                "responses": {"405": error_response("Not supported")}
+            }))
+        }
+    })
+}
+
+fn category_paths() -> Value {
+    json!({
+        "/api/v1/categories": {
+            "get": with_errors(json!({
+                "tags": ["categories"],
+                "summary": "List the category tree",
+                "parameters": [search_parameter()],
+                "responses": {"200": json_response("All matching root categories", array(schema("SearchCategory")))}
+            })),
+            "put": with_errors(json!({
+                "tags": ["categories"],
+                "summary": "Create a new category",
+                "requestBody": json_body(schema("Category")),
+                "responses": {"201": json_response("The category was created", schema("Created"))}
+            }))
+        },
+        "/api/v1/category/{category_id}": {
+            "parameters": [id_parameter("category_id")],
+            "get": with_errors(json!({
+                "tags": ["categories"],
+                "summary": "Get a category",
+                "responses": {
+                    "200": json_response("The category", schema("Category")),
+                    "404": error_response("Unknown category")
+                }
+            })),
+            "post": with_errors(json!({
+                "tags": ["categories"],
+                "summary": "Update a category",
+                "requestBody": json_body(schema("Category")),
+                "responses": {
+                    "200": empty_response("The category was updated"),
+                    "404": error_response("Unknown category")
+                }
+            })),
+            "delete": with_errors(json!({
+                "tags": ["categories"],
+                "summary": "Delete a category",
+                "description": "Categories cannot be deleted yet.",
+                "responses": {"405": error_response("Not supported")}
+            }))
+        }
+    })
+}
+
+fn stats_paths() -> Value {
+    json!({
+        "/api/v1/stats": {
+            "get": with_errors(json!({
+                "tags": ["stats"],
+                "summary": "Get sales statistics",
+                "description": "Returns revenue, top-ups and item counts per group. Only for admins.",
+                "security": [{"session": []}],
+                "parameters": [
+                    {
+                        "name": "group",
+                        "in": "query",
+                        "schema": {
+                            "type": "string",
+                            "enum": ["product", "category", "day", "weekday", "hour"],
+                            "default": "day"
+                        }
+                    },
+                    {
+                        "name": "from",
+                        "in": "query",
+                        "description": "First day of the range, defaults to 30 days ago",
+                        "schema": {"type": "string", "format": "date"}
+                    },
+                    {
+                        "name": "to",
+                        "in": "query",
+                        "description": "Last day of the range, defaults to today",
+                        "schema": {"type": "string", "format": "date"}
+                    }
+                ],
+                "responses": {"200": json_response("The statistic", array(schema("StatsEntry")))}
+            }))
+        }
+    })
+}
+
+fn docs_paths() -> Value {
+    json!({
+        "/api/v1/openapi.json": {
+            "get": {
+                "tags": ["docs"],
+                "summary": "Get this document",
+                "security": [],
+                "responses": {"200": empty_response("The OpenAPI document")}
+            }
+        },
+        "/api/v1/docs": {
+            "get": {
+                "tags": ["docs"],
+                "summary": "Show this document in the browser",
+                "security": [],
+                "responses": {"200": empty_response("Html page")}
+            }
+        }
+    })
+}
+
+fn model_schemas() -> Value {
+    json!({
+        "Uuid": {"type": "string", "format": "uuid"},
+        "Money": {"type": "integer", "format": "int32", "description": "Amount in cents"},
+        "Permission": {"type": "string", "enum": ["DEFAULT", "MEMBER", "ADMIN"]},
+        "Created": {
+            "type": "object",
+            "required": ["id"],
+            "properties": {"id": schema("Uuid")}
+        },
+        "Account": {
+            "type": "object",
+            "required": ["id", "credit", "minimum_credit", "name", "permission", "receives_monthly_report"],
+            "properties": {
+                "id": schema("Uuid"),
+                "credit": schema("Money"),
+                "minimum_credit": schema("Money"),
+                "name": {"type": "string"},
+                "mail": {"type": "string", "nullable": true},
+                "username": {"type": "string", "nullable": true},
+                "account_number": {"type": "string", "nullable": true},
+                "permission": schema("Permission"),
+                "receives_monthly_report": {"type": "boolean"}
+            }
+        },
+        "SearchAccount": {
+            "description": "Account with the search term highlighted in its fields",
+            "allOf": [
+                schema("Account"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "id_search": {"type": "string"},
+                        "name_search": {"type": "string"},
+                        "mail_search": {"type": "string"},
+                        "username_search": {"type": "string"},
+                        "account_number_search": {"type": "string"},
+                        "permission_search": {"type": "string"}
+                    }
+                }
+            ]
+        },
+        "AccountBarcode": {
+            "type": "object",
+            "required": ["barcode"],
+            "properties": {"barcode": {"type": "string"}}
+        },
+        "AccountNfc": {
+            "type": "object",
+            "required": ["nfc", "writeable"],
+            "properties": {
+                "nfc": {"type": "string", "description": "Id of the nfc card"},
+                "writeable": {"type": "boolean", "description": "Write a new key and secret to the card on the next read"}
+            }
+        },
+        "LoginForm": {
+            "type": "object",
+            "required": ["username", "password"],
+            "properties": {
+                "username": {"type": "string"},
+                "password": {"type": "string", "format": "password"},
+                "code": {"type": "string", "description": "Code of the authenticator app or a recovery code"}
+            }
+        },
+        "Price": {
+            "type": "object",
+            "required": ["validity_start", "value"],
+            "properties": {
+                "validity_start": {"type": "string", "format": "date"},
+                "value": schema("Money")
+            }
+        },
+        "Availability": {
+            "type": "object",
+            "description": "Sales window, the product is available between both dates on the given weekdays",
+            "properties": {
+                "id": schema("Uuid"),
+                "date_start": {"type": "string", "format": "date", "nullable": true},
+                "date_end": {"type": "string", "format": "date", "nullable": true},
+                "weekdays": array(json!({
+                    "type": "string",
+                    "enum": ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]
+                }))
+            }
+        },
+        "Category": {
+            "type": "object",
+            "required": ["id"],
+            "properties": {
+                "id": schema("Uuid"),
+                "name": {"type": "string"},
+                "prices": array(schema("Price")),
+                "current_price": {"allOf": [schema("Money")], "nullable": true},
+                "parent": {"allOf": [schema("Uuid")], "nullable": true}
+            }
+        },
+        "SearchCategory": {
+            "description": "Category with the search term highlighted and its child categories",
+            "allOf": [
+                schema("Category"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "name_search": {"type": "string"},
+                        "current_price_search": {"type": "string"},
+                        "depth": {"type": "integer"},
+                        "children": array(schema("SearchCategory"))
+                    }
+                }
+            ]
+        },
+        "Product": {
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {
+                "id": schema("Uuid"),
+                "name": {"type": "string"},
+                "category": {"allOf": [schema("Category")], "nullable": true},
+                "image": {"type": "string", "nullable": true},
+                "image_urls": {
+                    "type": "object",
+                    "readOnly": true,
+                    "description": "Urls of the image and its thumbnails by size, eg. `original`, `small` or `small_webp`",
+                    "additionalProperties": {"type": "string"}
+                },
+                "prices": array(schema("Price")),
+                "current_price": {"allOf": [schema("Money")], "nullable": true},
+                "barcode": {"type": "string", "nullable": true},
+                "parent": {"allOf": [schema("Uuid")], "nullable": true},
+                "variants": array(schema("Product")),
+                "active": {"type": "boolean", "default": true},
+                "availabilities": {
+                    "type": "array",
+                    "items": schema("Availability"),
+                    "description": "Sales schedule, create and update requests only replace it if it is present"
+                },
+                "available": {"type": "boolean", "readOnly": true},
+                "sort_order": {"type": "integer", "default": 0},
+                "quick_key": {"type": "boolean", "default": false},
+                "color": {"type": "string", "nullable": true, "example": "#ff8800"},
+                "icon": {"type": "string", "nullable": true}
+            }
+        },
+        "SearchProduct": {
+            "description": "Product with the search term highlighted in its fields",
+            "allOf": [
+                schema("Product"),
+                {
+                    "type": "object",
+                    "properties": {
+                        "name_search": {"type": "string"},
+                        "category_search": {"type": "string"},
+                        "current_price_search": {"type": "string"},
+                        "barcode_search": {"type": "string"}
+                    }
+                }
+            ]
+        },
+        "CategoryLayout": {
+            "type": "object",
+            "required": ["depth", "quick_keys", "products"],
+            "properties": {
+                "category": {"allOf": [schema("Category")], "nullable": true},
+                "depth": {"type": "integer"},
+                "quick_keys": array(schema("Product")),
+                "products": array(schema("Product"))
+            }
+        },
+        "Transaction": {
+            "type": "object",
+            "required": ["id", "account_id", "total", "before_credit", "after_credit", "date"],
+            "properties": {
+                "id": schema("Uuid"),
+                "account_id": schema("Uuid"),
+                "cashier_id": {"allOf": [schema("Uuid")], "nullable": true},
+                "total": schema("Money"),
+                "before_credit": schema("Money"),
+                "after_credit": schema("Money"),
+                "date": {"type": "string", "example": "2020-01-31T12:00:00.000"},
+                "terminal_id": {
+                    "allOf": [schema("Uuid")],
+                    "nullable": true,
+                    "description": "The terminal that created the transaction"
+                }
+            }
+        },
+        "StatsEntry": {
+            "type": "object",
+            "required": ["key", "label", "revenue", "top_ups", "items"],
+            "properties": {
+                "key": {"type": "string"},
+                "label": {"type": "string"},
+                "revenue": schema("Money"),
+                "top_ups": schema("Money"),
+                "items": {"type": "integer"}
+            }
+        },
+        "Error": {
+            "type": "object",
+            "required": ["code", "message"],
+            "properties": {
+                "code": {
+                    "type": "string",
+                    "enum": ErrorCode::ALL,
+                    "description": "Stable code of the error, the message may change at any time"
+                },
+                "message": {"type": "string"},
+                "detail": {"type": "string"},
+                "fields": array(schema("FieldError"))
+            }
+        },
+        "FieldError": {
+            "type": "object",
+            "required": ["field", "message"],
+            "properties": {
+                "field": {"type": "string"},
+                "message": {"type": "string"}
+            }
+        }
+    })
+}
+
+/// Schemas of a tagged enum, every variant is an object with a `type` property
+fn tagged(variants: &[(&str, Value)]) -> Value {
+    let one_of: Vec<Value> = variants
+        .iter()
+        .map(|(tag, properties)| {
+            let mut required = vec![json!("type")];
+            let mut all = json!({"type": {"type": "string", "enum": [tag]}});
+            for (name, value) in properties.as_object().expect("Variant without properties") {
+                required.push(json!(name));
+                all[name] = value.clone();
+            }
+            json!({
+                "type": "object",
+                "required": required,
+                "properties": all
+            })
+        })
+        .collect();
+
+    json!({ "oneOf": one_of })
+}
+
+fn terminal_schemas() -> Value {
+    let authentication = || {
+        vec![
+            ("barcode", json!({"code": {"type": "string"}})),
+            ("nfc", json!({"id": {"type": "string"}})),
+            (
+                "nfc-secret",
+                json!({
+                    "id": {"type": "string"},
+                    "challenge": {"type": "string", "description": "The challenge of the `authentication-needed` response"},
+                    "response": {"type": "string", "description": "The response of the nfc card"}
+                }),
+            ),
+        ]
+    };
+    let authentication_needed = (
+        "authentication-needed",
+        json!({
+            "id": {"type": "string"},
+            "key": {"type": "string"},
+            "challenge": {"type": "string"}
+        }),
+    );
+
+    json!({
+        "IdentificationRequest": tagged(&authentication()),
+        "IdentificationResponse": tagged(&[
+            ("account", json!({"account": schema("Account")})),
+            ("product", json!({
+                "product": schema("Product"),
+                "variant": {
+                    "allOf": [schema("Uuid")],
+                    "nullable": true,
+                    "description": "The id of the identified variant if the barcode belongs to a variant of `product`"
+                }
+            })),
+            authentication_needed.clone(),
+            ("write-key", json!({
+                "id": {"type": "string"},
+                "key": {"type": "string"},
+                "secret": {"type": "string"}
+            }))
+        ]),
+        "TokenRequest": {
+            "type": "object",
+            "required": ["amount", "method"],
+            "properties": {
+                "amount": schema("Money"),
+                "method": tagged(&authentication())
+            }
+        },
+        "TokenResponse": tagged(&[
+            ("authorized", json!({"token": {"type": "string"}})),
+            authentication_needed
+        ]),
+        "PaymentRequest": {
+            "type": "object",
+            "required": ["amount", "token", "products"],
+            "properties": {
+                "amount": {
+                    "allOf": [schema("Money")],
+                    "description": "Has to match the amount of the token, negative for payments"
+                },
+                "token": {"type": "string"},
+                "products": {
+                    "type": "object",
+                    "description": "Number of sold items by product id",
+                    "additionalProperties": {"type": "integer"}
+                }
+            }
+        },
+        "PaymentResponse": {
+            "type": "object",
+            "required": ["account", "transaction"],
+            "properties": {
+                "account": schema("Account"),
+                "transaction": schema("Transaction")
+            }
+        }
+    })
+}
+
+/// Merge the entries of all given objects
+fn merge(objects: Vec<Value>) -> Value {
+    let mut result = serde_json::Map::new();
+    for object in objects {
+        if let Value::Object(map) = object {
+            result.extend(map);
+        }
+    }
+    Value::Object(result)
+}
+
+/// Create the OpenAPI document
+pub fn document() -> Value {
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "ascii pay",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Api of the ascii pay server for payment terminals and other clients. \
+                All amounts are in cents."
+        },
+        "servers": [{"url": config::get().base_url}],
+        "tags": [
+            {"name": "terminal", "description": "Payment flow of a terminal: identify, token, payment"},
+            {"name": "auth"},
+            {"name": "accounts"},
+            {"name": "products"},
+            {"name": "categories"},
+            {"name": "stats"},
+            {"name": "docs"}
+        ],
+        "security": [{"terminal_key": []}, {"session": []}],
+        "paths": merge(vec![
+            terminal_paths(),
+            auth_paths(),
+            account_paths(),
+            product_paths(),
+            category_paths(),
+            stats_paths(),
+            docs_paths(),
+        ]),
+        "components": {
+            "securitySchemes": {
+                "terminal_key": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "Api key of a terminal registered at `/admin/terminals`. \
+                        If the server enables `client_cert_mode`, the `terminal` routes require the client certificate \
+                        of the terminal instead and reject other requests with `client_cert_required`."
+                },
+                "session": {
+                    "type": "apiKey",
+                    "in": "cookie",
+                    "name": AUTH_COOKIE_NAME,
+                    "description": "Session cookie of `POST /api/v1/auth`"
+                }
+            },
+            "schemas": merge(vec![model_schemas(), terminal_schemas()])
+        }
+    })
+}
+
+/// GET route for `/api/v1/openapi.json`
+pub async fn get_openapi() -> ServiceResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(document()))
+}
+
+/// GET route for `/api/v1/docs`
+///
+/// Renders the OpenAPI document in the browser
+pub async fn get_docs(
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let body = HbData::new(&request).render(&hb, "api_docs")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::identification::IdentificationResponse;
+    use crate::api::transactions::{PaymentResponse, TokenResponse};
+    use crate::core::layouts::CategoryLayout;
+    use crate::core::stats::StatsEntry;
+    use crate::core::{
+        Account, Availability, Category, FieldError, Permission, Price, Product, ServiceError,
+        Transaction, Uuid,
+    };
+    use crate::web::admin::accounts::SearchAccount;
+    use crate::web::admin::categories::SearchCategory;
+    use crate::web::admin::products::SearchProduct;
+    use chrono::{NaiveDate, Weekday};
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use std::collections::BTreeSet;
+
+    /// Collect all `(path, method)` pairs that are registered in `api::init`
+    fn registered_routes() -> BTreeSet<(String, String)> {
+        crate::api::routes()
+            .into_iter()
+            .map(|(path, method, _)| {
+                (
+                    format!("/api/v1{}", path),
+                    method.as_str().to_ascii_lowercase(),
+                )
+            })
+            .collect()
+    }
+
+    /// Collect all `(path, method)` pairs of the document
+    fn documented_routes(document: &Value) -> BTreeSet<(String, String)> {
+        let mut routes = BTreeSet::new();
+        for (path, item) in document["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                if method != "parameters" {
+                    routes.insert((path.clone(), method.clone()));
+                }
+            }
+        }
+        routes
+    }
+
+    /// Collect all `$ref` values of the document
+    fn references(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match value {
+                        Value::String(s) if key == "$ref" => refs.push(s.clone()),
+                        _ => references(value, refs),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|v| references(v, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_all_routes_documented() {
+        config::init_test();
+        let document = document();
+
+        let registered = registered_routes();
+        let documented = documented_routes(&document);
+        assert!(registered.len() > 20, "Cannot find the routes of api::init");
+
+        let undocumented: Vec<_> = registered.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "Routes are missing in the OpenAPI document: {:?}",
+            undocumented
+        );
+
+        let unknown: Vec<_> = documented.difference(&registered).collect();
+        assert!(
+            unknown.is_empty(),
+            "The OpenAPI document contains unknown routes: {:?}",
+            unknown
+        );
+    }
+
+    #[test]
+    fn test_all_references_exist() {
+        config::init_test();
+        let document = document();
+
+        let mut refs = Vec::new();
+        references(&document, &mut refs);
+        assert!(!refs.is_empty());
+
+        for reference in refs {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(
+                document["components"]["schemas"].get(name).is_some(),
+                "Unknown schema {}",
+                reference
+            );
+        }
+    }
+
+    /// Resolve a `$ref` of the document
+    fn resolve<'a>(document: &'a Value, schema: &'a Value) -> &'a Value {
+        match schema["$ref"].as_str() {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/components/schemas/");
+                resolve(document, &document["components"]["schemas"][name])
+            }
+            None => schema,
+        }
+    }
+
+    /// Collect the properties of an object schema and of all schemas of its `allOf`
+    fn properties(document: &Value, schema: &Value, names: &mut BTreeSet<String>) {
+        let schema = resolve(document, schema);
+        if let Some(properties) = schema["properties"].as_object() {
+            names.extend(properties.keys().cloned());
+        }
+        for schema in schema["allOf"].as_array().into_iter().flatten() {
+            properties(document, schema, names);
+        }
+    }
+
+    /// Check `value` against the subset of the OpenAPI schema that the document uses
+    ///
+    /// Objects must not have undocumented properties unless `additionalProperties` is set.
+    /// `check_unknown` is false for the parts of an `allOf`, the union of their properties is
+    /// checked instead.
+    fn validate(
+        document: &Value,
+        schema: &Value,
+        value: &Value,
+        path: &str,
+        check_unknown: bool,
+        errors: &mut Vec<String>,
+    ) {
+        let schema = resolve(document, schema);
+
+        if value.is_null() {
+            if schema["nullable"] != Value::Bool(true) {
+                errors.push(format!("{}: null is not nullable", path));
+            }
+            return;
+        }
+
+        if let Some(all_of) = schema["allOf"].as_array() {
+            for part in all_of {
+                validate(document, part, value, path, false, errors);
+            }
+        }
+
+        if let Some(one_of) = schema["oneOf"].as_array() {
+            let matches = one_of
+                .iter()
+                .filter(|variant| {
+                    let mut variant_errors = Vec::new();
+                    validate(document, variant, value, path, true, &mut variant_errors);
+                    variant_errors.is_empty()
+                })
+                .count();
+            if matches != 1 {
+                errors.push(format!("{}: matches {} variants of oneOf", path, matches));
+            }
+        }
+
+        if let Some(values) = schema["enum"].as_array() {
+            if !values.contains(value) {
+                errors.push(format!("{}: {} is not in the enum", path, value));
+            }
+        }
+
+        let type_matches = match schema["type"].as_str() {
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            Some("array") => value.is_array(),
+            Some("object") => value.is_object(),
+            Some(other) => panic!("Unknown type {} at {}", other, path),
+            None => true,
+        };
+        if !type_matches {
+            errors.push(format!(
+                "{}: {} is not of type {}",
+                path, value, schema["type"]
+            ));
+            return;
+        }
+
+        match (schema["format"].as_str(), value.as_str()) {
+            (Some("uuid"), Some(s)) if Uuid::parse_str(s).is_err() => {
+                errors.push(format!("{}: {} is not a uuid", path, s))
+            }
+            (Some("date"), Some(s)) if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_err() => {
+                errors.push(format!("{}: {} is not a date", path, s))
+            }
+            _ => {}
+        }
+
+        if let Some(items) = value.as_array() {
+            for (i, item) in items.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                validate(document, &schema["items"], item, &path, true, errors);
+            }
+        }
+
+        if let Some(object) = value.as_object() {
+            for name in schema["required"].as_array().into_iter().flatten() {
+                if !object.contains_key(name.as_str().unwrap()) {
+                    errors.push(format!("{}: required property {} is missing", path, name));
+                }
+            }
+
+            // `oneOf` schemas document the properties in their variants
+            let mut known = BTreeSet::new();
+            properties(document, schema, &mut known);
+            let check_unknown = check_unknown && schema.get("oneOf").is_none();
+
+            for (name, value) in object {
+                let path = format!("{}.{}", path, name);
+                if let Some(property) = schema["properties"].get(name) {
+                    validate(document, property, value, &path, true, errors);
+                } else if schema["additionalProperties"].is_object() {
+                    let additional = &schema["additionalProperties"];
+                    validate(document, additional, value, &path, true, errors);
+                } else if check_unknown && !known.contains(name) {
+                    errors.push(format!("{}: property is not documented", path));
+                }
+            }
+        }
+    }
+
+    /// Assert that `value` matches the schema `name` of the document
+    fn assert_schema(document: &Value, name: &str, value: &Value) {
+        let mut errors = Vec::new();
+        let schema = json!({ "$ref": format!("#/components/schemas/{}", name) });
+        validate(document, &schema, value, name, true, &mut errors);
+        assert!(
+            errors.is_empty(),
+            "{} does not match its schema: {:#?}",
+            value,
+            errors
+        );
+    }
+
+    /// Assert that the serialized `value` matches the schema `name`
+    fn assert_serialized<T: Serialize>(document: &Value, name: &str, value: &T) {
+        let value = serde_json::to_value(value).expect("Cannot serialize fixture");
+        assert_schema(document, name, &value);
+    }
+
+    /// Assert that the request body `value` matches the schema `name` and can be parsed as `T`
+    fn assert_deserialized<T: DeserializeOwned>(document: &Value, name: &str, value: Value) {
+        assert_schema(document, name, &value);
+        if let Err(e) = serde_json::from_value::<T>(value.clone()) {
+            panic!("{} does not match {}: {}", value, name, e);
+        }
+    }
+
+    fn account() -> Account {
+        Account {
+            id: Uuid::parse_str("4ad7cc4d-b3b6-4f3c-9c2e-2a5c6b1b0e83").unwrap(),
+            credit: 1250,
+            minimum_credit: -500,
+            name: "Alice".to_owned(),
+            mail: Some("alice@example.org".to_owned()),
+            username: None,
+            account_number: Some("1001".to_owned()),
+            permission: Permission::MEMBER,
+            receives_monthly_report: true,
+        }
+    }
+
+    fn category() -> Category {
+        Category {
+            id: Uuid::parse_str("9c1f4ab1-9bc6-4f39-8dcb-1f2a9c6c7d2e").unwrap(),
+            name: "Drinks".to_owned(),
+            prices: vec![Price {
+                validity_start: NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0),
+                value: 150,
+            }],
+            current_price: Some(150),
+            parent: None,
+            ancestors: Vec::new(),
+        }
+    }
+
+    fn product() -> Product {
+        let mut image_urls = std::collections::BTreeMap::new();
+        image_urls.insert("original".to_owned(), "/product/image/a.png".to_owned());
+        image_urls.insert("small".to_owned(), "/product/image/a-small.png".to_owned());
+
+        let variant = Product {
+            id: Uuid::parse_str("0f3c2f52-0f6e-4a63-8d0c-6c4f5e3f1b9a").unwrap(),
+            name: "Mate 0.33l".to_owned(),
+            category: None,
+            image: None,
+            image_urls: Default::default(),
+            prices: Vec::new(),
+            current_price: None,
+            barcode: Some("4029764001807".to_owned()),
+            parent: Some(Uuid::parse_str("b6b1d3d5-5d6c-4b0e-9f6e-6c2d8e9c1a7f").unwrap()),
+            variants: Vec::new(),
+            active: true,
+            availabilities: Vec::new(),
+            available: true,
+            sort_order: 0,
+            quick_key: false,
+            color: None,
+            icon: None,
+        };
+
+        Product {
+            id: Uuid::parse_str("b6b1d3d5-5d6c-4b0e-9f6e-6c2d8e9c1a7f").unwrap(),
+            name: "Mate".to_owned(),
+            category: Some(category()),
+            image: Some("a.png".to_owned()),
+            image_urls,
+            prices: vec![Price {
+                validity_start: NaiveDate::from_ymd(2020, 6, 1).and_hms(0, 0, 0),
+                value: 200,
+            }],
+            current_price: Some(200),
+            barcode: None,
+            parent: None,
+            variants: vec![variant],
+            active: true,
+            availabilities: vec![Availability {
+                id: Uuid::parse_str("5e0e2b8a-7a1d-4a4e-8f59-2d7c9b3e6f10").unwrap(),
+                date_start: Some(NaiveDate::from_ymd(2020, 1, 1)),
+                date_end: None,
+                weekdays: vec![Weekday::Mon, Weekday::Fri],
+            }],
+            available: true,
+            sort_order: 3,
+            quick_key: true,
+            color: Some("#ff8800".to_owned()),
+            icon: Some("bottle".to_owned()),
+        }
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            id: Uuid::parse_str("7c5b1a3e-2f4d-4e6a-9b8c-1d2e3f4a5b6c").unwrap(),
+            account_id: account().id,
+            cashier_id: None,
+            total: -200,
+            before_credit: 1450,
+            after_credit: 1250,
+            date: NaiveDate::from_ymd(2020, 1, 31).and_hms(12, 0, 0),
+            terminal_id: Some(Uuid::parse_str("3a4b5c6d-7e8f-4a0b-9c1d-2e3f4a5b6c7d").unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_responses_match_schemas() {
+        config::init_test();
+        let document = document();
+
+        assert_serialized(&document, "Account", &account());
+        assert_serialized(
+            &document,
+            "SearchAccount",
+            &SearchAccount::wrap(account(), "").unwrap(),
+        );
+        assert_serialized(&document, "Category", &category());
+        assert_serialized(
+            &document,
+            "SearchCategory",
+            &SearchCategory::wrap(category(), "").unwrap(),
+        );
+        assert_serialized(&document, "Product", &product());
+        assert_serialized(
+            &document,
+            "SearchProduct",
+            &SearchProduct::wrap(product(), "").unwrap(),
+        );
+        assert_serialized(
+            &document,
+            "CategoryLayout",
+            &CategoryLayout {
+                category: Some(category()),
+                depth: 0,
+                quick_keys: vec![product()],
+                products: vec![product()],
+            },
+        );
+        assert_serialized(&document, "Transaction", &transaction());
+        assert_serialized(
+            &document,
+            "StatsEntry",
+            &StatsEntry {
+                key: "2020-01-31".to_owned(),
+                label: "31.01.2020".to_owned(),
+                revenue: 2000,
+                top_ups: 5000,
+                items: 12,
+            },
+        );
+
+        assert_serialized(
+            &document,
+            "IdentificationResponse",
+            &IdentificationResponse::Account { account: account() },
+        );
+        assert_serialized(
+            &document,
+            "IdentificationResponse",
+            &IdentificationResponse::Product {
+                product: product(),
+                variant: None,
+            },
+        );
+        assert_serialized(
+            &document,
+            "IdentificationResponse",
+            &IdentificationResponse::WriteKey {
+                id: "04:a2:3b".to_owned(),
+                key: "key".to_owned(),
+                secret: "secret".to_owned(),
+            },
+        );
+        assert_serialized(
+            &document,
+            "TokenResponse",
+            &TokenResponse::Authorized {
+                token: "token".to_owned(),
+            },
+        );
+        assert_serialized(
+            &document,
+            "TokenResponse",
+            &TokenResponse::AuthenticationNeeded {
+                id: "04:a2:3b".to_owned(),
+                key: "key".to_owned(),
+                challenge: "challenge".to_owned(),
+            },
+        );
+        assert_serialized(
+            &document,
+            "PaymentResponse",
+            &PaymentResponse {
+                account: account(),
+                transaction: transaction(),
+            },
+        );
+
+        assert_serialized(&document, "Error", &ServiceError::NotFound.to_response());
+        assert_serialized(
+            &document,
+            "Error",
+            &ServiceError::InvalidFields(vec![FieldError {
+                field: "name".to_owned(),
+                message: "Name is required".to_owned(),
+            }])
+            .to_response(),
+        );
+    }
+
+    #[test]
+    fn test_requests_match_schemas() {
+        use crate::api::accounts::{AccountBarcode, AccountNfc};
+        use crate::api::auth::LoginForm;
+        use crate::api::identification::IdentificationRequest;
+        use crate::api::products::ProductInput;
+        use crate::api::transactions::{PaymentRequest, TokenRequest};
+
+        config::init_test();
+        let document = document();
+
+        assert_deserialized::<IdentificationRequest>(
+            &document,
+            "IdentificationRequest",
+            json!({"type": "nfc-secret", "id": "04:a2:3b", "challenge": "c", "response": "r"}),
+        );
+        assert_deserialized::<TokenRequest>(
+            &document,
+            "TokenRequest",
+            json!({"amount": -200, "method": {"type": "barcode", "code": "4029764001807"}}),
+        );
+        assert_deserialized::<PaymentRequest>(
+            &document,
+            "PaymentRequest",
+            json!({
+                "amount": -200,
+                "token": "token",
+                "products": {"b6b1d3d5-5d6c-4b0e-9f6e-6c2d8e9c1a7f": 1}
+            }),
+        );
+        assert_deserialized::<LoginForm>(
+            &document,
+            "LoginForm",
+            json!({"username": "alice", "password": "secret", "code": "123456"}),
+        );
+        assert_deserialized::<AccountBarcode>(
+            &document,
+            "AccountBarcode",
+            json!({"barcode": "4029764001807"}),
+        );
+        assert_deserialized::<AccountNfc>(
+            &document,
+            "AccountNfc",
+            json!({"nfc": "04:a2:3b", "writeable": true}),
+        );
+        assert_deserialized::<Category>(
+            &document,
+            "Category",
+            json!({
+                "id": "9c1f4ab1-9bc6-4f39-8dcb-1f2a9c6c7d2e",
+                "name": "Drinks",
+                "prices": [{"validity_start": "2020-01-01", "value": 150}],
+                "current_price": null,
+                "parent": null
+            }),
+        );
+        assert_deserialized::<ProductInput>(
+            &document,
+            "Product",
+            json!({
+                "id": "b6b1d3d5-5d6c-4b0e-9f6e-6c2d8e9c1a7f",
+                "name": "Mate",
+                "category": null,
+                "image": null,
+                "prices": [{"validity_start": "2020-06-01", "value": 200}],
+                "current_price": null,
+                "barcode": "4029764001807",
+                "parent": null,
+                "availabilities": [{"date_start": "2020-01-01", "weekdays": ["Mon", "Fri"]}],
+                "color": "#ff8800",
+                "icon": null
+            }),
+        );
+
+        // The schemas must reject invalid bodies
+        let mut errors = Vec::new();
+        let schema = json!({"$ref": "#/components/schemas/PaymentRequest"});
+        let value = json!({"amount": "-200", "token": "token", "product": {}});
+        validate(
+            &document,
+            &schema,
+            &value,
+            "PaymentRequest",
+            true,
+            &mut errors,
+        );
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+    }
+}
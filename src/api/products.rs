@@ -1,19 +1,47 @@
-use crate::core::{Category, Permission, Pool, Product, ServiceError, ServiceResult};
+use crate::core::{
+    Availability, Category, Permission, Pool, Product, ServiceError, ServiceResult, Uuid,
+};
 use crate::identity_policy::{Action, RetrievedAccount};
-use crate::login_or_client_cert_required;
+use crate::login_or_terminal_required;
 use crate::web::admin::products::SearchProduct;
-use crate::web::utils::Search;
 use actix_web::{web, HttpRequest, HttpResponse};
-use uuid::Uuid;
+use chrono::Local;
+use diesel::Connection;
+
+/// Helper to deserialize product list queries
+#[derive(Deserialize)]
+pub struct ProductSearch {
+    pub search: Option<String>,
+    /// Include unavailable products, eg. for admin tools
+    #[serde(default = "get_false")]
+    pub all: bool,
+}
+
+fn get_false() -> bool {
+    false
+}
+
+/// Helper to deserialize the product of create and update requests
+///
+/// The sales schedule is only replaced if `availabilities` is present, so clients that do not
+/// know about it keep the existing schedule.
+#[derive(Deserialize)]
+pub struct ProductInput {
+    #[serde(flatten)]
+    pub product: Product,
+    pub availabilities: Option<Vec<Availability>>,
+}
 
 /// GET route for `/api/v1/products`
+///
+/// Only lists currently available products unless `all=true` is set
 pub async fn get_products(
     pool: web::Data<Pool>,
     logged_account: RetrievedAccount,
-    query: web::Query<Search>,
+    query: web::Query<ProductSearch>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -26,8 +54,14 @@ pub async fn get_products(
         None => "".to_owned(),
     };
 
+    let products = if query.all {
+        Product::all(&conn)?
+    } else {
+        Product::all_available_at(&conn, &Local::now().naive_local())?
+    };
+
     let lower_search = search.trim().to_ascii_lowercase();
-    let search_products: Vec<SearchProduct> = Product::all(&conn)?
+    let search_products: Vec<SearchProduct> = products
         .into_iter()
         .filter_map(|p| SearchProduct::wrap(p, &lower_search))
         .collect();
@@ -39,30 +73,45 @@ pub async fn get_products(
 pub async fn put_products(
     logged_account: RetrievedAccount,
     pool: web::Data<Pool>,
-    product: web::Json<Product>,
+    input: web::Json<ProductInput>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
         Action::FORBIDDEN
     );
 
+    let product = &input.product;
     let conn = &pool.get()?;
 
-    let category = if let Some(x) = &product.category {
-        Some(Category::get(&conn, &x.id)?)
-    } else {
-        None
-    };
-
-    let mut server_product = Product::create(&conn, &product.name, category)?;
-
-    server_product.barcode = product.barcode.clone();
-    server_product.update(&conn)?;
-
-    server_product.update_prices(&conn, &product.prices)?;
+    // An invalid parent must not leave the new product behind
+    let server_product = conn.transaction::<_, ServiceError, _>(|| {
+        let category = if let Some(x) = &product.category {
+            Some(Category::get(&conn, &x.id)?)
+        } else {
+            None
+        };
+
+        let mut server_product = Product::create(&conn, &product.name, category)?;
+
+        server_product.barcode = product.barcode.clone();
+        server_product.parent = product.parent;
+        server_product.active = product.active;
+        server_product.sort_order = product.sort_order;
+        server_product.quick_key = product.quick_key;
+        server_product.color = product.color.clone();
+        server_product.icon = product.icon.clone();
+        server_product.update(&conn)?;
+
+        server_product.update_prices(&conn, &product.prices)?;
+        if let Some(availabilities) = &input.availabilities {
+            server_product.update_availabilities(&conn, availabilities)?;
+        }
+
+        Ok(server_product)
+    })?;
 
     Ok(HttpResponse::Created().json(json!({
         "id": server_product.id
@@ -76,7 +125,7 @@ pub async fn get_product(
     product_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
@@ -93,41 +142,56 @@ pub async fn get_product(
 pub async fn post_product(
     logged_account: RetrievedAccount,
     pool: web::Data<Pool>,
-    product: web::Json<Product>,
+    input: web::Json<ProductInput>,
     product_id: web::Path<Uuid>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
         Action::FORBIDDEN
     );
 
+    let product = &input.product;
     if *product_id != product.id {
-        return Err(ServiceError::BadRequest(
-            "Id missmage",
+        return Err(ServiceError::invalid_field(
+            "id",
             "The product id of the url and the json do not match!".to_owned(),
         ));
     }
 
     let conn = &pool.get()?;
 
-    let mut server_product = Product::get(&conn, &product_id)?;
+    // A failing barcode, price or availability must not leave a partial update behind
+    conn.transaction::<_, ServiceError, _>(|| {
+        let mut server_product = Product::get(&conn, &product_id)?;
 
-    let category = if let Some(x) = &product.category {
-        Some(Category::get(&conn, &x.id)?)
-    } else {
-        None
-    };
+        let category = if let Some(x) = &product.category {
+            Some(Category::get(&conn, &x.id)?)
+        } else {
+            None
+        };
+
+        server_product.name = product.name.clone();
+        server_product.barcode = product.barcode.clone();
+        server_product.category = category;
+        server_product.parent = product.parent;
+        server_product.active = product.active;
+        server_product.sort_order = product.sort_order;
+        server_product.quick_key = product.quick_key;
+        server_product.color = product.color.clone();
+        server_product.icon = product.icon.clone();
 
-    server_product.name = product.name.clone();
-    server_product.barcode = product.barcode.clone();
-    server_product.category = category;
+        server_product.update(&conn)?;
 
-    server_product.update(&conn)?;
+        server_product.update_prices(&conn, &product.prices)?;
+        if let Some(availabilities) = &input.availabilities {
+            server_product.update_availabilities(&conn, availabilities)?;
+        }
 
-    server_product.update_prices(&conn, &product.prices)?;
+        Ok(())
+    })?;
 
     Ok(HttpResponse::Ok().finish())
 }
@@ -138,14 +202,14 @@ pub async fn delete_product(
     _product_id: web::Path<String>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let _logged_account = login_or_client_cert_required!(
+    let _logged_account = login_or_terminal_required!(
         request,
         logged_account,
         Permission::MEMBER,
         Action::FORBIDDEN
     );
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
-    Ok(HttpResponse::MethodNotAllowed().finish())
+    Err(ServiceError::NotSupported)
 }
@@ -0,0 +1,62 @@
+use crate::core::{stats, Permission, Pool, ServiceError, ServiceResult};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_required;
+use actix_web::{web, HttpResponse};
+use chrono::{Duration, Local, NaiveDate};
+
+/// Helper to deserialize stats queries
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    pub group: Option<stats::StatsGrouping>,
+    /// First day of the range as `yyyy-mm-dd`, defaults to 30 days ago
+    pub from: Option<String>,
+    /// Last day of the range as `yyyy-mm-dd`, defaults to today
+    pub to: Option<String>,
+}
+
+fn parse_date(field: &str, value: &Option<String>, default: NaiveDate) -> ServiceResult<NaiveDate> {
+    match value.as_deref() {
+        Some(value) if !value.is_empty() => {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+                ServiceError::invalid_field(
+                    field,
+                    format!("'{}' does not match the format 'yyyy-mm-dd'", value),
+                )
+            })
+        }
+        _ => Ok(default),
+    }
+}
+
+/// GET route for `/api/v1/stats`
+///
+/// Returns revenue, top-ups and item counts grouped by `product`, `category`, `day`, `weekday` or `hour`
+pub async fn get_stats(
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+    query: web::Query<StatsQuery>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::FORBIDDEN);
+
+    let today = Local::now().naive_local().date();
+    let from = parse_date("from", &query.from, today - Duration::days(30))?;
+    let to = parse_date("to", &query.to, today)?;
+
+    if from > to {
+        return Err(ServiceError::invalid_field(
+            "from",
+            "The start date has to be before the end date".to_owned(),
+        ));
+    }
+
+    let conn = &pool.get()?;
+
+    let entries = stats::get_stats(
+        &conn,
+        &from.and_hms(0, 0, 0),
+        &to.and_hms(23, 59, 59),
+        query.group.unwrap_or(stats::StatsGrouping::Day),
+    )?;
+
+    Ok(HttpResponse::Ok().json(&entries))
+}
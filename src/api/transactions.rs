@@ -1,13 +1,12 @@
-use crate::client_cert_required;
 use crate::core::{
     authentication_barcode, authentication_nfc, generate_uuid, transactions, Account, DbConnection,
-    Pool, Product, ServiceError, ServiceResult, Session, Transaction,
+    Pool, Product, ServiceError, ServiceResult, Session, Transaction, Uuid,
 };
 use crate::identity_policy::Action;
+use crate::terminal_required;
 
 use actix_web::{web, HttpRequest, HttpResponse};
 use std::collections::HashMap;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Token {
@@ -34,13 +33,13 @@ impl Token {
     }
 
     pub fn from_str(s: &str) -> ServiceResult<Self> {
-        let s = base64::decode(s)?;
-        let s = String::from_utf8(s)?;
-        Ok(serde_json::from_str(&s)?)
+        let s = base64::decode(s).map_err(|_| ServiceError::InvalidToken)?;
+        let s = String::from_utf8(s).map_err(|_| ServiceError::InvalidToken)?;
+        serde_json::from_str(&s).map_err(|_| ServiceError::InvalidToken)
     }
 
     pub fn parse(conn: &DbConnection, s: &str) -> ServiceResult<Self> {
-        let session = Session::get(&conn, s)?;
+        let session = Session::get(&conn, s).map_err(|_| ServiceError::InvalidToken)?;
         session.delete(&conn)?;
 
         Self::from_str(s)
@@ -103,7 +102,7 @@ pub async fn post_transaction_token(
     token_request: web::Json<TokenRequest>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    client_cert_required!(request, Action::FORBIDDEN);
+    terminal_required!(request, Action::FORBIDDEN);
 
     let conn = &pool.get()?;
 
@@ -154,7 +153,7 @@ pub async fn post_transaction_payment(
     payment_request: web::Json<PaymentRequest>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    client_cert_required!(request, Action::FORBIDDEN);
+    let terminal = terminal_required!(request, Action::FORBIDDEN);
 
     let conn = &pool.get()?;
 
@@ -163,10 +162,19 @@ pub async fn post_transaction_payment(
     let mut account = Account::get(&conn, &token.account_id)?;
 
     if payment_request.amount != token.amount {
-        return Err(ServiceError::Unauthorized);
+        return Err(ServiceError::invalid_field(
+            "amount",
+            "The amount does not match the amount of the token".to_owned(),
+        ));
     }
 
-    let transaction = transactions::execute(&conn, &mut account, None, payment_request.amount)?;
+    let transaction = transactions::execute(
+        &conn,
+        &mut account,
+        None,
+        Some(&terminal),
+        payment_request.amount,
+    )?;
 
     let mut products: Vec<(Product, i32)> = Vec::new();
 
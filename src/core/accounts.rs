@@ -4,10 +4,9 @@ use diesel::prelude::*;
 use diesel::serialize::{self, Output, ToSql};
 use diesel::sql_types::*;
 use std::io;
-use uuid::Uuid;
 
 use crate::core::schema::account;
-use crate::core::{generate_uuid, DbConnection, Money, ServiceError, ServiceResult};
+use crate::core::{generate_uuid, DbConnection, Money, ServiceError, ServiceResult, Uuid};
 
 /// Represent a account
 #[derive(
@@ -125,6 +124,7 @@ where
 
 impl Account {
     /// Create a new account with the given permission level
+    #[tracing::instrument(level = "debug", skip_all, fields(name))]
     pub fn create(
         conn: &DbConnection,
         name: &str,
@@ -157,6 +157,7 @@ impl Account {
     }
 
     /// Save the current account data to the database
+    #[tracing::instrument(level = "debug", skip_all, fields(account_id = %self.id))]
     pub fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::account::dsl;
 
@@ -175,6 +176,7 @@ impl Account {
     }
 
     /// List all accounts
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn all(conn: &DbConnection) -> ServiceResult<Vec<Account>> {
         use crate::core::schema::account::dsl;
 
@@ -184,6 +186,7 @@ impl Account {
     }
 
     /// Get an account by the `id`
+    #[tracing::instrument(level = "debug", skip_all, fields(account_id = %id))]
     pub fn get(conn: &DbConnection, id: &Uuid) -> ServiceResult<Account> {
         use crate::core::schema::account::dsl;
 
@@ -193,6 +196,7 @@ impl Account {
     }
 
     /// Get an account by the `id`
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn find_by_login(conn: &DbConnection, login: &str) -> ServiceResult<Account> {
         use crate::core::schema::account::dsl;
 
@@ -0,0 +1,464 @@
+//! Audit of failed authentications and the lockouts based on them.
+//!
+//! Every failed password login, wrong second factor and unknown identification is stored with the
+//! client ip. A client ip or an account is locked out while it has too many failures within the
+//! last `lockout_minutes`, so the lockouts survive restarts. Admins can unlock them early.
+//! Failures are deleted after `auth_failure_retention_days`.
+//!
+//! The check of a lockout and the record of a failure are not atomic. Concurrent requests of a
+//! client may all pass the check before the first failure is recorded, so a client can exceed a
+//! limit by the number of its concurrent requests once. All of its failures are recorded, so it is
+//! locked out afterwards.
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use crate::core::schema::auth_failure;
+use crate::core::{
+    authentication_password, authentication_totp, config, generate_uuid, Account, DbConnection,
+    ServiceError, ServiceResult, Uuid,
+};
+
+/// Maximal length of a stored login, longer input is truncated
+const MAX_LOGIN_LENGTH: usize = 64;
+
+/// Kind of a failed authentication, every kind has its own limit per client ip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// Wrong login, password or second factor at `/login` or `/api/v1/auth`
+    Password,
+    /// Unknown barcode or nfc card at `/api/v1/identify`
+    Identify,
+}
+
+impl FailureKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            FailureKind::Password => "password",
+            FailureKind::Identify => "identify",
+        }
+    }
+
+    /// Allowed failures of a client ip within `lockout_minutes`
+    fn max_per_ip(self) -> u32 {
+        let config = config::get();
+        match self {
+            FailureKind::Password => config.max_failures_per_ip,
+            FailureKind::Identify => config.max_identify_failures_per_ip,
+        }
+    }
+}
+
+/// Represent a failed authentication
+#[derive(Debug, Queryable, Insertable, Serialize, Clone)]
+#[table_name = "auth_failure"]
+pub struct AuthFailure {
+    pub id: Uuid,
+    pub created: NaiveDateTime,
+    pub kind: String,
+    pub ip: String,
+    /// The entered login, or the barcode / nfc card id of an identification
+    pub login: Option<String>,
+    pub account_id: Option<Uuid>,
+    pub terminal_id: Option<Uuid>,
+    /// Set if an admin lifted the lockout, the failure is kept for the audit
+    pub unlocked: Option<NaiveDateTime>,
+}
+
+/// An account or client ip that is locked out
+#[derive(Debug, Serialize)]
+pub struct Lockout {
+    pub kind: String,
+    /// Locked client ip, `None` for account lockouts
+    pub ip: Option<String>,
+    /// Locked account, `None` for ip lockouts
+    pub account: Option<Account>,
+    pub failures: usize,
+    pub locked_until: NaiveDateTime,
+}
+
+fn window() -> Duration {
+    Duration::minutes(i64::from(config::get().lockout_minutes))
+}
+
+/// Record a failed authentication
+#[tracing::instrument(level = "debug", skip_all, fields(kind = kind.as_str(), ip))]
+pub fn record(
+    conn: &DbConnection,
+    kind: FailureKind,
+    ip: &str,
+    login: Option<&str>,
+    account_id: Option<Uuid>,
+    terminal_id: Option<Uuid>,
+) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let failure = AuthFailure {
+        id: generate_uuid(),
+        created: Local::now().naive_local(),
+        kind: kind.as_str().to_owned(),
+        ip: ip.to_owned(),
+        login: login.map(|l| l.chars().take(MAX_LOGIN_LENGTH).collect()),
+        account_id,
+        terminal_id,
+        unlocked: None,
+    };
+
+    cleanup(conn)?;
+
+    diesel::insert_into(dsl::auth_failure)
+        .values(&failure)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Delete all failures older than `auth_failure_retention_days`
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn cleanup(conn: &DbConnection) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let retention = Duration::days(i64::from(config::get().auth_failure_retention_days));
+
+    diesel::delete(
+        dsl::auth_failure.filter(dsl::created.lt(Local::now().naive_local() - retention)),
+    )
+    .execute(conn)?;
+
+    Ok(())
+}
+
+/// Reject the client ip with `TooManyRequests` if it is locked out for `kind`
+#[tracing::instrument(level = "debug", skip_all, fields(kind = kind.as_str(), ip))]
+pub fn check_ip(conn: &DbConnection, kind: FailureKind, ip: &str) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let max = kind.max_per_ip();
+    if max == 0 {
+        return Ok(());
+    }
+
+    let failures = dsl::auth_failure
+        .select(dsl::created)
+        .filter(dsl::kind.eq(kind.as_str()))
+        .filter(dsl::ip.eq(ip))
+        .filter(dsl::unlocked.is_null())
+        .filter(dsl::created.gt(Local::now().naive_local() - window()))
+        .order(dsl::created.desc())
+        .load::<NaiveDateTime>(conn)?;
+
+    check_failures(&failures, max)
+}
+
+/// Reject the account with `TooManyRequests` if it is locked out
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account_id))]
+pub fn check_account(conn: &DbConnection, account_id: &Uuid) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let max = config::get().max_failures_per_account;
+    if max == 0 {
+        return Ok(());
+    }
+
+    let failures = dsl::auth_failure
+        .select(dsl::created)
+        .filter(dsl::kind.eq(FailureKind::Password.as_str()))
+        .filter(dsl::account_id.eq(account_id))
+        .filter(dsl::unlocked.is_null())
+        .filter(dsl::created.gt(Local::now().naive_local() - window()))
+        .order(dsl::created.desc())
+        .load::<NaiveDateTime>(conn)?;
+
+    check_failures(&failures, max)
+}
+
+/// Whether the `failures` reach the limit `max`, 0 disables the limit
+fn is_locked(failures: &[NaiveDateTime], max: u32) -> bool {
+    max > 0 && failures.len() >= max as usize
+}
+
+/// Fail if the `failures`, sorted from the latest to the oldest, reach the limit `max`
+fn check_failures(failures: &[NaiveDateTime], max: u32) -> ServiceResult<()> {
+    if !is_locked(failures, max) {
+        return Ok(());
+    }
+
+    let locked_until = failures[0] + window();
+    let seconds = (locked_until - Local::now().naive_local()).num_seconds();
+    Err(ServiceError::TooManyRequests(seconds.max(1)))
+}
+
+/// Get the account of a login and password with the lockouts of the client ip and the account
+///
+/// Locked out clients are rejected before the password is checked, so they cannot continue to
+/// guess while they are locked out. Wrong logins and passwords are recorded.
+pub fn authenticate_password(
+    conn: &DbConnection,
+    ip: &str,
+    login: &str,
+    password: &str,
+) -> ServiceResult<Account> {
+    check_ip(conn, FailureKind::Password, ip)?;
+
+    let account_id = Account::find_by_login(conn, login).ok().map(|a| a.id);
+    if let Some(account_id) = &account_id {
+        check_account(conn, account_id)?;
+    }
+
+    match authentication_password::get(conn, login, password) {
+        Err(ServiceError::NotFound) => {
+            record(
+                conn,
+                FailureKind::Password,
+                ip,
+                Some(login),
+                account_id,
+                None,
+            )?;
+            Err(ServiceError::NotFound)
+        }
+        result => result,
+    }
+}
+
+/// Verify the second factor of an account with the lockouts of the client ip and the account
+///
+/// Wrong codes are recorded like wrong passwords, so they share the same limits.
+pub fn authenticate_second_factor(
+    conn: &DbConnection,
+    ip: &str,
+    account: &Account,
+    code: &str,
+) -> ServiceResult<()> {
+    check_ip(conn, FailureKind::Password, ip)?;
+    check_account(conn, &account.id)?;
+
+    match authentication_totp::verify(conn, account, code) {
+        Err(ServiceError::NotFound) => {
+            record(
+                conn,
+                FailureKind::Password,
+                ip,
+                None,
+                Some(account.id),
+                None,
+            )?;
+            Err(ServiceError::NotFound)
+        }
+        result => result,
+    }
+}
+
+/// List all current lockouts of accounts and client ips
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn lockouts(conn: &DbConnection) -> ServiceResult<Vec<Lockout>> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let failures = dsl::auth_failure
+        .filter(dsl::unlocked.is_null())
+        .filter(dsl::created.gt(Local::now().naive_local() - window()))
+        .order(dsl::created.desc())
+        .load::<AuthFailure>(conn)?;
+
+    let mut by_ip: HashMap<(&str, &str), Vec<NaiveDateTime>> = HashMap::new();
+    let mut by_account: HashMap<Uuid, Vec<NaiveDateTime>> = HashMap::new();
+    for failure in &failures {
+        by_ip
+            .entry((&failure.kind, &failure.ip))
+            .or_default()
+            .push(failure.created);
+        if let (Some(account_id), "password") = (failure.account_id, failure.kind.as_str()) {
+            by_account
+                .entry(account_id)
+                .or_default()
+                .push(failure.created);
+        }
+    }
+
+    let mut lockouts = Vec::new();
+    for ((kind, ip), created) in by_ip {
+        let kind = match kind {
+            "identify" => FailureKind::Identify,
+            _ => FailureKind::Password,
+        };
+        if is_locked(&created, kind.max_per_ip()) {
+            lockouts.push(Lockout {
+                kind: kind.as_str().to_owned(),
+                ip: Some(ip.to_owned()),
+                account: None,
+                failures: created.len(),
+                locked_until: created[0] + window(),
+            });
+        }
+    }
+    let max_per_account = config::get().max_failures_per_account;
+    let locked_accounts: HashMap<Uuid, Vec<NaiveDateTime>> = by_account
+        .into_iter()
+        .filter(|(_, created)| is_locked(created, max_per_account))
+        .collect();
+    if !locked_accounts.is_empty() {
+        use crate::core::schema::account::dsl as account_dsl;
+
+        let accounts = account_dsl::account
+            .filter(account_dsl::id.eq_any(locked_accounts.keys()))
+            .load::<Account>(conn)?;
+        for account in accounts {
+            let created = &locked_accounts[&account.id];
+            lockouts.push(Lockout {
+                kind: FailureKind::Password.as_str().to_owned(),
+                ip: None,
+                account: Some(account),
+                failures: created.len(),
+                locked_until: created[0] + window(),
+            });
+        }
+    }
+
+    lockouts.sort_by_key(|lockout| Reverse(lockout.locked_until));
+    Ok(lockouts)
+}
+
+/// List the latest failed authentications for the audit
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn latest(conn: &DbConnection, limit: i64) -> ServiceResult<Vec<AuthFailure>> {
+    use crate::core::schema::auth_failure::dsl;
+
+    let results = dsl::auth_failure
+        .order(dsl::created.desc())
+        .limit(limit)
+        .load::<AuthFailure>(conn)?;
+
+    Ok(results)
+}
+
+/// Lift the lockout of an account
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account_id))]
+pub fn unlock_account(conn: &DbConnection, account_id: &Uuid) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    diesel::update(
+        dsl::auth_failure
+            .filter(dsl::account_id.eq(account_id))
+            .filter(dsl::unlocked.is_null()),
+    )
+    .set(dsl::unlocked.eq(Local::now().naive_local()))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+/// Lift all lockouts of a client ip
+#[tracing::instrument(level = "debug", skip_all, fields(ip))]
+pub fn unlock_ip(conn: &DbConnection, ip: &str) -> ServiceResult<()> {
+    use crate::core::schema::auth_failure::dsl;
+
+    diesel::update(
+        dsl::auth_failure
+            .filter(dsl::ip.eq(ip))
+            .filter(dsl::unlocked.is_null()),
+    )
+    .set(dsl::unlocked.eq(Local::now().naive_local()))
+    .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_connection;
+
+    fn create_account(conn: &DbConnection) -> ServiceResult<Account> {
+        let mut account = Account::create(conn, "Alice", crate::core::Permission::DEFAULT)?;
+        account.username = Some("alice".to_owned());
+        account.update(conn)?;
+        authentication_password::register(conn, &account, "secret")?;
+        Ok(account)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_account_lockout() -> ServiceResult<()> {
+        let conn = test_connection();
+        let account = create_account(&conn)?;
+        let max = config::get().max_failures_per_account;
+
+        for i in 0..max {
+            // Different ips, so only the account limit applies
+            let ip = format!("10.0.0.{}", i);
+            assert!(matches!(
+                authenticate_password(&conn, &ip, "alice", "wrong"),
+                Err(ServiceError::NotFound)
+            ));
+        }
+
+        // The correct password is rejected during the lockout
+        assert!(matches!(
+            authenticate_password(&conn, "10.0.1.1", "alice", "secret"),
+            Err(ServiceError::TooManyRequests(_))
+        ));
+        assert_eq!(lockouts(&conn)?.len(), 1);
+
+        unlock_account(&conn, &account.id)?;
+        assert!(lockouts(&conn)?.is_empty());
+        assert_eq!(
+            authenticate_password(&conn, "10.0.1.1", "alice", "secret")?.id,
+            account.id
+        );
+        assert_eq!(latest(&conn, 100)?.len(), max as usize);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_ip_lockout() -> ServiceResult<()> {
+        let conn = test_connection();
+        let max = FailureKind::Identify.max_per_ip();
+
+        for _ in 0..max {
+            check_ip(&conn, FailureKind::Identify, "10.0.0.1")?;
+            record(
+                &conn,
+                FailureKind::Identify,
+                "10.0.0.1",
+                Some("unknown"),
+                None,
+                None,
+            )?;
+        }
+
+        assert!(check_ip(&conn, FailureKind::Identify, "10.0.0.1").is_err());
+        // Other kinds and ips are not affected
+        assert!(check_ip(&conn, FailureKind::Password, "10.0.0.1").is_ok());
+        assert!(check_ip(&conn, FailureKind::Identify, "10.0.0.2").is_ok());
+
+        unlock_ip(&conn, "10.0.0.1")?;
+        assert!(check_ip(&conn, FailureKind::Identify, "10.0.0.1").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_cleanup() -> ServiceResult<()> {
+        use crate::core::schema::auth_failure::dsl;
+
+        let conn = test_connection();
+        let retention = i64::from(config::get().auth_failure_retention_days);
+
+        record(&conn, FailureKind::Password, "10.0.0.1", None, None, None)?;
+        diesel::update(dsl::auth_failure)
+            .set(dsl::created.eq(Local::now().naive_local() - Duration::days(retention + 1)))
+            .execute(&conn)?;
+        record(&conn, FailureKind::Password, "10.0.0.2", None, None, None)?;
+
+        // The expired failure is removed by the next record
+        let failures = latest(&conn, 100)?;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].ip, "10.0.0.2");
+
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use diesel::prelude::*;
-use uuid::Uuid;
 
+use crate::core::metrics::{self, AuthMethod};
 use crate::core::schema::authentication_barcode;
-use crate::core::{Account, DbConnection, ServiceError, ServiceResult};
+use crate::core::{Account, DbConnection, ServiceError, ServiceResult, Uuid};
 
 /// Represent a barcode - barcode authentication for the given account
 #[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset)]
@@ -52,6 +52,7 @@ pub fn get_barcodes(conn: &DbConnection, account: &Account) -> ServiceResult<Vec
 
 /// Get account by barcode.
 /// Return `ServiceError` if no account is registered for given barcode.
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn get(conn: &DbConnection, barcode: &str) -> ServiceResult<Account> {
     use crate::core::schema::authentication_barcode::dsl;
 
@@ -60,7 +61,10 @@ pub fn get(conn: &DbConnection, barcode: &str) -> ServiceResult<Account> {
         .limit(1)
         .load::<AuthenticationBarcode>(conn)?;
 
-    let entry = results.pop().ok_or_else(|| ServiceError::NotFound)?;
+    let entry = results.pop().ok_or_else(|| {
+        metrics::count_failed_authentication(AuthMethod::Barcode);
+        ServiceError::NotFound
+    })?;
 
     let a = Account::get(conn, &entry.account_id)?;
 
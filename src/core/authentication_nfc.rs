@@ -1,5 +1,4 @@
 use diesel::prelude::*;
-use uuid::Uuid;
 
 use aes::Aes128;
 use block_modes::block_padding::Pkcs7;
@@ -9,9 +8,10 @@ use chrono::{Local, NaiveDateTime};
 use rand_core::RngCore;
 use std::io::Cursor;
 
+use crate::core::metrics::{self, AuthMethod};
 use crate::core::schema::authentication_nfc;
 use crate::core::schema::authentication_nfc_write_key;
-use crate::core::{Account, DbConnection, ServiceError, ServiceResult};
+use crate::core::{Account, DbConnection, ServiceError, ServiceResult, Uuid};
 
 /// Represent a nfc tag - nfc authentication for the given account
 #[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset)]
@@ -228,7 +228,7 @@ fn verify_challenge(challenge: &str) -> ServiceResult<bool> {
 /// If the challenge is longer than the secret, than the secret will repeat itself.
 /// 
 /// The result is base64 encoded.
-fn create_response(secret: &[u8], challenge: &str) -> ServiceResult<String> {
+pub fn create_response(secret: &[u8], challenge: &str) -> ServiceResult<String> {
     let challenge = base64::decode(challenge)?;
 
     let mut response: Vec<u8> = Vec::with_capacity(challenge.len());
@@ -256,7 +256,7 @@ fn bytes_to_string(bytes: &[u8]) -> String {
 }
 
 /// Convert space separate hex string to byte vector.
-fn str_to_bytes(s: &str) -> ServiceResult<Vec<u8>> {
+pub fn str_to_bytes(s: &str) -> ServiceResult<Vec<u8>> {
     fn m(x: &str) -> ServiceResult<u8> {
         Ok(u8::from_str_radix(x, 16)?)
     }
@@ -281,6 +281,7 @@ pub enum NfcResult {
 
 /// Get account by nfc.
 /// Return `ServiceError` if no account is registered for given nfc.
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn get(conn: &DbConnection, card_id: &str) -> ServiceResult<NfcResult> {
     use crate::core::schema::authentication_nfc::dsl;
 
@@ -289,7 +290,10 @@ pub fn get(conn: &DbConnection, card_id: &str) -> ServiceResult<NfcResult> {
         .limit(1)
         .load::<AuthenticationNfc>(conn)?;
 
-    let mut entry = results.pop().ok_or_else(|| ServiceError::NotFound)?;
+    let mut entry = results.pop().ok_or_else(|| {
+        metrics::count_failed_authentication(AuthMethod::Nfc);
+        ServiceError::NotFound
+    })?;
 
     if entry.need_write_key(&conn)? {
         let key = bytes_to_string(&generate_key(16));
@@ -323,6 +327,7 @@ pub fn get(conn: &DbConnection, card_id: &str) -> ServiceResult<NfcResult> {
 
 /// Get account by nfc.
 /// Return `ServiceError` if no account is registered for given nfc.
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn get_challenge_response(
     conn: &DbConnection,
     card_id: &str,
@@ -336,7 +341,10 @@ pub fn get_challenge_response(
         .limit(1)
         .load::<AuthenticationNfc>(conn)?;
 
-    let entry = results.pop().ok_or_else(|| ServiceError::NotFound)?;
+    let entry = results.pop().ok_or_else(|| {
+        metrics::count_failed_authentication(AuthMethod::Nfc);
+        ServiceError::NotFound
+    })?;
 
     if let Some(secret) = entry.secret {
         let secret = str_to_bytes(&secret)?;
@@ -344,6 +352,7 @@ pub fn get_challenge_response(
             let account = Account::get(conn, &entry.account_id)?;
             return Ok(account);
         }
+        metrics::count_failed_authentication(AuthMethod::Nfc);
         Err(ServiceError::Unauthorized)
     } else {
         Err(ServiceError::BadRequest(
@@ -2,11 +2,13 @@ use argonautica::{Hasher, Verifier};
 use chrono::{Duration, Local, NaiveDateTime};
 use diesel::prelude::*;
 use std::fmt;
-use uuid::Uuid;
 
 use crate::core::mail;
+use crate::core::metrics::{self, AuthMethod};
 use crate::core::schema::{authentication_password, authentication_password_invitation};
-use crate::core::{env, generate_uuid_str, Account, DbConnection, ServiceError, ServiceResult};
+use crate::core::{
+    config, generate_uuid_str, Account, DbConnection, ServiceError, ServiceResult, Uuid,
+};
 
 /// Represent a username - password authentication for the given account
 #[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset)]
@@ -31,7 +33,7 @@ impl fmt::Display for InvitationLink {
         write!(
             f,
             "{base}/register/{link_id}",
-            base = env::BASE_URL.as_str(),
+            base = config::get().base_url.as_str(),
             link_id = self.link
         )
     }
@@ -94,10 +96,7 @@ pub fn get_account_by_invitation_link(conn: &DbConnection, link: &str) -> Servic
 
     match invitation_link {
         Some(invitation_link) => Account::get(conn, &invitation_link.account_id),
-        None => Err(ServiceError::InternalServerError(
-            "Invalid link",
-            "".to_owned(),
-        )),
+        None => Err(ServiceError::InvalidInvitation),
     }
 }
 
@@ -142,19 +141,28 @@ pub fn has_password(conn: &DbConnection, account: &Account) -> ServiceResult<boo
 
 /// Get account by username and password.
 /// Return `ServiceError` if no account is registered for given username - passoword pair
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn get(conn: &DbConnection, login: &str, password: &str) -> ServiceResult<Account> {
     use crate::core::schema::authentication_password::dsl;
 
-    let account = Account::find_by_login(&conn, login)?;
+    let failed = || {
+        metrics::count_failed_authentication(AuthMethod::Password);
+        ServiceError::NotFound
+    };
+
+    let account = match Account::find_by_login(&conn, login) {
+        Err(ServiceError::NotFound) => return Err(failed()),
+        result => result?,
+    };
 
     let mut results = dsl::authentication_password
         .filter(dsl::account_id.eq(account.id))
         .load::<AuthenticationPassword>(conn)?;
 
-    let entry = results.pop().ok_or_else(|| ServiceError::NotFound)?;
+    let entry = results.pop().ok_or_else(failed)?;
 
     if !verify(&entry.password, password)? {
-        return Err(ServiceError::NotFound);
+        return Err(failed());
     }
 
     let a = Account::get(conn, &entry.account_id)?;
@@ -162,6 +170,7 @@ pub fn get(conn: &DbConnection, login: &str, password: &str) -> ServiceResult<Ac
     Ok(a)
 }
 
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
 pub fn verify_password(
     conn: &DbConnection,
     account: &Account,
@@ -181,17 +190,17 @@ pub fn verify_password(
 /// Create the hash version of a password
 fn hash_password(password: &str) -> ServiceResult<String> {
     if password.is_empty() {
-        return Err(ServiceError::BadRequest(
-            "Empty password",
+        return Err(ServiceError::invalid_field(
+            "password",
             "Password should not be empty".to_owned(),
         ));
     }
     Hasher::default()
         .with_password(password)
-        .with_secret_key(env::PASSWORD_SALT.as_str())
+        .with_secret_key(config::get().password_salt.as_str())
         .hash()
         .map_err(|err| {
-            dbg!(&err);
+            error!(error = %err, "Could not hash password");
             ServiceError::InternalServerError("Hash password", format!("{}", err))
         })
 }
@@ -204,7 +213,7 @@ fn verify(hash: &str, password: &str) -> ServiceResult<bool> {
     Verifier::default()
         .with_hash(hash)
         .with_password(password)
-        .with_secret_key(env::PASSWORD_SALT.as_str())
+        .with_secret_key(config::get().password_salt.as_str())
         .verify()
         .map_err(|err| ServiceError::InternalServerError("Hash password", format!("{}", err)))
 }
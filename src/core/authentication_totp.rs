@@ -0,0 +1,521 @@
+//! Time based one time passwords (TOTP, RFC 6238) as second factor of the login.
+//!
+//! An account enrolls by adding the secret to an authenticator app and confirming it with a code.
+//! The confirmation creates single use recovery codes for a lost authenticator. Every code of the
+//! app is accepted only once, so an observed code cannot be replayed.
+use chrono::{Local, NaiveDateTime};
+use diesel::prelude::*;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use rand_core::RngCore;
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::core::metrics::{self, AuthMethod};
+use crate::core::schema::{authentication_totp, authentication_totp_recovery};
+use crate::core::{
+    config, Account, DbConnection, Permission, ServiceError, ServiceResult, Session, Uuid,
+};
+
+/// Issuer that authenticator apps show next to the account
+const ISSUER: &str = "ascii pay";
+
+/// Number of random bytes of a secret, the size recommended by RFC 4226
+const SECRET_LENGTH: usize = 20;
+
+/// Number of digits of a code
+const DIGITS: usize = 6;
+
+/// Lifetime of a code in seconds
+const STEP_SECONDS: u64 = 30;
+
+/// Accepted clock drift of the authenticator app in steps
+const SKEW: u64 = 1;
+
+/// Number of recovery codes that are created at once
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Number of random bytes of a recovery code
+const RECOVERY_CODE_LENGTH: usize = 8;
+
+/// Represent the totp secret of an account
+#[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset)]
+#[changeset_options(treat_none_as_null = "true")]
+#[table_name = "authentication_totp"]
+#[primary_key(account_id)]
+struct AuthenticationTotp {
+    account_id: Uuid,
+    /// Base32 encoded secret
+    secret: String,
+    /// Unconfirmed secrets are not asked for at the login
+    confirmed: bool,
+    /// Latest accepted step, older and equal steps are rejected
+    last_step: Option<i64>,
+    created: NaiveDateTime,
+}
+
+impl AuthenticationTotp {
+    fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
+        use crate::core::schema::authentication_totp::dsl;
+
+        diesel::update(dsl::authentication_totp.find(&self.account_id))
+            .set(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+}
+
+/// Sha256 hash of an unused recovery code
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "authentication_totp_recovery"]
+struct RecoveryCode {
+    account_id: Uuid,
+    code_hash: String,
+}
+
+/// Data to add an unconfirmed secret to an authenticator app
+#[derive(Debug, Serialize)]
+pub struct Enrollment {
+    /// Base32 encoded secret for manual input
+    pub secret: String,
+    /// `otpauth://` uri of the secret
+    pub uri: String,
+    /// Svg image of a qr code with the `uri`
+    pub qr_code: String,
+}
+
+fn get(conn: &DbConnection, account: &Account) -> ServiceResult<Option<AuthenticationTotp>> {
+    use crate::core::schema::authentication_totp::dsl;
+
+    let mut results = dsl::authentication_totp
+        .filter(dsl::account_id.eq(&account.id))
+        .load::<AuthenticationTotp>(conn)?;
+
+    Ok(results.pop())
+}
+
+/// Check if the account has a confirmed totp secret
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn is_enabled(conn: &DbConnection, account: &Account) -> ServiceResult<bool> {
+    Ok(get(conn, account)?.map(|t| t.confirmed).unwrap_or(false))
+}
+
+/// Check if the account must use a second factor regardless of its own choice
+pub fn is_mandatory(account: &Account) -> bool {
+    account.permission == Permission::ADMIN && config::get().admin_totp_required
+}
+
+/// Check if a login of the account needs a second factor
+pub fn is_required(conn: &DbConnection, account: &Account) -> ServiceResult<bool> {
+    Ok(is_mandatory(account) || is_enabled(conn, account)?)
+}
+
+/// Get the unconfirmed secret of the account, a new one is created if there is none
+///
+/// Accounts with a confirmed secret have to disable it first.
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn enroll(conn: &DbConnection, account: &Account) -> ServiceResult<Enrollment> {
+    use crate::core::schema::authentication_totp::dsl;
+
+    let totp = match get(conn, account)? {
+        Some(totp) if totp.confirmed => {
+            return Err(ServiceError::BadRequest(
+                "Second factor already enabled",
+                "Disable the authenticator app before adding a new one".to_owned(),
+            ));
+        }
+        Some(totp) => totp,
+        None => {
+            let mut data = [0u8; SECRET_LENGTH];
+            rand::thread_rng().fill_bytes(&mut data);
+
+            let totp = AuthenticationTotp {
+                account_id: account.id,
+                secret: Secret::Raw(data.to_vec()).to_encoded().to_string(),
+                confirmed: false,
+                last_step: None,
+                created: Local::now().naive_local(),
+            };
+            diesel::insert_into(dsl::authentication_totp)
+                .values(&totp)
+                .execute(conn)?;
+            totp
+        }
+    };
+
+    let uri = format!(
+        "otpauth://totp/{issuer}:{label}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = encode_uri_component(ISSUER),
+        label = encode_uri_component(&label(account)),
+        secret = totp.secret,
+        digits = DIGITS,
+        period = STEP_SECONDS,
+    );
+    let qr_code = QrCode::new(uri.as_bytes())
+        .map_err(|e| ServiceError::InternalServerError("QR code error", format!("{}", e)))?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+
+    Ok(Enrollment {
+        secret: totp.secret,
+        uri,
+        qr_code,
+    })
+}
+
+/// Confirm the unconfirmed secret of the account with a code of the authenticator app
+///
+/// All other sessions of the account are deleted, they were created without the second factor.
+/// Return the new recovery codes, they are only shown once. Wrong codes result in `NotFound`.
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn confirm(
+    conn: &DbConnection,
+    account: &Account,
+    session_id: &str,
+    code: &str,
+) -> ServiceResult<Vec<String>> {
+    let mut totp = match get(conn, account)? {
+        Some(totp) if !totp.confirmed => totp,
+        _ => return Err(ServiceError::NotFound),
+    };
+
+    verify_code(conn, &mut totp, code, now())?;
+
+    totp.confirmed = true;
+    totp.update(conn)?;
+
+    Session::delete_by_account(conn, &account.id, Some(session_id))?;
+
+    regenerate_recovery_codes(conn, account)
+}
+
+/// Verify a code of the authenticator app or an unused recovery code of the account
+///
+/// Used recovery codes are deleted. Wrong codes result in `NotFound`.
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn verify(conn: &DbConnection, account: &Account, code: &str) -> ServiceResult<()> {
+    verify_at(conn, account, code, now())
+}
+
+fn verify_at(conn: &DbConnection, account: &Account, code: &str, time: u64) -> ServiceResult<()> {
+    use crate::core::schema::authentication_totp_recovery::dsl;
+
+    let mut totp = match get(conn, account)? {
+        Some(totp) if totp.confirmed => totp,
+        _ => return Err(ServiceError::NotFound),
+    };
+
+    let code = normalize(code);
+    if code.len() == DIGITS && code.chars().all(|c| c.is_ascii_digit()) {
+        return verify_code(conn, &mut totp, &code, time);
+    }
+
+    let deleted = diesel::delete(
+        dsl::authentication_totp_recovery
+            .filter(dsl::account_id.eq(&account.id))
+            .filter(dsl::code_hash.eq(hash_code(&code))),
+    )
+    .execute(conn)?;
+
+    if deleted == 0 {
+        metrics::count_failed_authentication(AuthMethod::Totp);
+        return Err(ServiceError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Check a code of the authenticator app against the steps around `time` and store its step
+fn verify_code(
+    conn: &DbConnection,
+    totp: &mut AuthenticationTotp,
+    code: &str,
+    time: u64,
+) -> ServiceResult<()> {
+    let generator = generator(&totp.secret)?;
+    let current = time / STEP_SECONDS;
+    let code = normalize(code);
+
+    let step = (current.saturating_sub(SKEW)..=current + SKEW)
+        .find(|step| generator.check(&code, step * STEP_SECONDS))
+        .map(|step| step as i64);
+
+    match step {
+        Some(step) if totp.last_step.map(|last| step > last).unwrap_or(true) => {
+            totp.last_step = Some(step);
+            totp.update(conn)?;
+            Ok(())
+        }
+        _ => {
+            metrics::count_failed_authentication(AuthMethod::Totp);
+            Err(ServiceError::NotFound)
+        }
+    }
+}
+
+/// Replace the recovery codes of the account and return the new ones
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn regenerate_recovery_codes(
+    conn: &DbConnection,
+    account: &Account,
+) -> ServiceResult<Vec<String>> {
+    use crate::core::schema::authentication_totp_recovery::dsl;
+
+    diesel::delete(dsl::authentication_totp_recovery.filter(dsl::account_id.eq(&account.id)))
+        .execute(conn)?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let mut data = [0u8; RECOVERY_CODE_LENGTH];
+        rand::thread_rng().fill_bytes(&mut data);
+        let code = hex(&data);
+
+        diesel::insert_into(dsl::authentication_totp_recovery)
+            .values(&RecoveryCode {
+                account_id: account.id,
+                code_hash: hash_code(&code),
+            })
+            .execute(conn)?;
+
+        // Groups of four are easier to copy by hand
+        let groups: Vec<&str> = (0..code.len())
+            .step_by(4)
+            .map(|i| &code[i..i + 4])
+            .collect();
+        codes.push(groups.join("-"));
+    }
+
+    Ok(codes)
+}
+
+/// Number of unused recovery codes of the account
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn count_recovery_codes(conn: &DbConnection, account: &Account) -> ServiceResult<i64> {
+    use crate::core::schema::authentication_totp_recovery::dsl;
+
+    let count = dsl::authentication_totp_recovery
+        .filter(dsl::account_id.eq(&account.id))
+        .count()
+        .get_result(conn)?;
+
+    Ok(count)
+}
+
+/// Remove the secret and the recovery codes of the account
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
+pub fn disable(conn: &DbConnection, account: &Account) -> ServiceResult<()> {
+    use crate::core::schema::authentication_totp::dsl as totp_dsl;
+    use crate::core::schema::authentication_totp_recovery::dsl as recovery_dsl;
+
+    conn.transaction(|| {
+        diesel::delete(totp_dsl::authentication_totp.filter(totp_dsl::account_id.eq(&account.id)))
+            .execute(conn)?;
+        diesel::delete(
+            recovery_dsl::authentication_totp_recovery
+                .filter(recovery_dsl::account_id.eq(&account.id)),
+        )
+        .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// Delete the sessions of all admins without a confirmed secret, returns the number of sessions
+///
+/// If `admin_totp_required` is set, the sessions of admins that were created before have not
+/// verified a second factor. Sessions that wait for the second factor are kept.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn delete_admin_sessions_without_second_factor(conn: &DbConnection) -> ServiceResult<usize> {
+    use crate::core::schema::account::dsl as account_dsl;
+    use crate::core::schema::authentication_totp::dsl as totp_dsl;
+    use crate::core::schema::session::dsl as session_dsl;
+
+    let enrolled = totp_dsl::authentication_totp
+        .select(totp_dsl::account_id)
+        .filter(totp_dsl::confirmed.eq(true));
+    let admins = account_dsl::account
+        .select(account_dsl::id)
+        .filter(account_dsl::permission.eq(Permission::ADMIN))
+        .filter(diesel::dsl::not(account_dsl::id.eq_any(enrolled)));
+
+    let deleted = diesel::delete(
+        session_dsl::session
+            .filter(session_dsl::second_factor_pending.eq(false))
+            .filter(session_dsl::account_id.eq_any(admins)),
+    )
+    .execute(conn)?;
+
+    Ok(deleted)
+}
+
+fn generator(secret: &str) -> ServiceResult<TOTP> {
+    let secret = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|e| ServiceError::InternalServerError("TOTP error", format!("{:?}", e)))?;
+
+    // The skew is checked by `verify_code` to know the matching step
+    TOTP::new(Algorithm::SHA1, DIGITS, 0, STEP_SECONDS, secret)
+        .map_err(|e| ServiceError::InternalServerError("TOTP error", format!("{}", e)))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Name of the account in the authenticator app
+fn label(account: &Account) -> String {
+    account
+        .username
+        .clone()
+        .or_else(|| account.mail.clone())
+        .unwrap_or_else(|| account.name.clone())
+}
+
+/// Remove the separators users may type or copy with a code
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Hash a normalized recovery code for storage
+///
+/// Recovery codes are long random values, so a fast unsalted hash is sufficient.
+fn hash_code(code: &str) -> String {
+    hex(&Sha256::digest(code.as_bytes()))
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent encode everything except the unreserved characters of RFC 3986
+fn encode_uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_connection;
+
+    fn current_code(conn: &DbConnection, account: &Account, time: u64) -> ServiceResult<String> {
+        let totp = get(conn, account)?.expect("enrolled account");
+        Ok(generator(&totp.secret)?.generate(time))
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_enroll_and_verify() -> ServiceResult<()> {
+        let conn = test_connection();
+        let account = Account::create(&conn, "Alice", Permission::DEFAULT)?;
+
+        let enrollment = enroll(&conn, &account)?;
+        assert!(enrollment
+            .uri
+            .starts_with("otpauth://totp/ascii%20pay:Alice?secret="));
+        assert!(enrollment.qr_code.contains("<svg"));
+        // The pending secret is kept until it is confirmed
+        assert_eq!(enroll(&conn, &account)?.secret, enrollment.secret);
+        assert!(!is_required(&conn, &account)?);
+
+        let session = Session::create(&conn, &account.id, false)?;
+        let other = Session::create(&conn, &account.id, false)?;
+
+        let code = current_code(&conn, &account, now())?;
+        let wrong = format!("{:06}", (code.parse::<u32>()? + 1) % 1_000_000);
+        assert!(confirm(&conn, &account, &session.id, &wrong).is_err());
+        assert!(!is_enabled(&conn, &account)?);
+
+        let recovery_codes = confirm(&conn, &account, &session.id, &code)?;
+        assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+        // Other sessions were created without the second factor
+        assert!(Session::get(&conn, &session.id).is_ok());
+        assert!(Session::get(&conn, &other.id).is_err());
+        assert!(is_required(&conn, &account)?);
+        assert!(enroll(&conn, &account).is_err());
+
+        // A code is accepted once within the skew and never again
+        let time = now() + 10 * STEP_SECONDS;
+        let code = current_code(&conn, &account, time)?;
+        assert!(verify_at(&conn, &account, &code, time - STEP_SECONDS).is_ok());
+        assert!(verify_at(&conn, &account, &code, time).is_err());
+        let later = current_code(&conn, &account, time + 3 * STEP_SECONDS)?;
+        assert!(verify_at(&conn, &account, &later, time).is_err());
+        assert!(verify_at(&conn, &account, &later, time + 3 * STEP_SECONDS).is_ok());
+
+        disable(&conn, &account)?;
+        assert!(!is_enabled(&conn, &account)?);
+        assert_eq!(count_recovery_codes(&conn, &account)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_recovery_codes() -> ServiceResult<()> {
+        let conn = test_connection();
+        let account = Account::create(&conn, "Alice", Permission::DEFAULT)?;
+
+        enroll(&conn, &account)?;
+        let code = current_code(&conn, &account, now())?;
+        let codes = confirm(&conn, &account, "session", &code)?;
+
+        assert!(verify(&conn, &account, "0000-0000-0000-0000").is_err());
+        // Codes are accepted without separators and in upper case
+        let entered = codes[0].replace('-', " ").to_uppercase();
+        assert!(verify(&conn, &account, &entered).is_ok());
+        assert!(verify(&conn, &account, &codes[0]).is_err());
+        assert_eq!(
+            count_recovery_codes(&conn, &account)?,
+            RECOVERY_CODE_COUNT as i64 - 1
+        );
+
+        let new_codes = regenerate_recovery_codes(&conn, &account)?;
+        assert!(verify(&conn, &account, &codes[1]).is_err());
+        assert!(verify(&conn, &account, &new_codes[1]).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_delete_admin_sessions() -> ServiceResult<()> {
+        let conn = test_connection();
+        let admin = Account::create(&conn, "Admin", Permission::ADMIN)?;
+        let enrolled = Account::create(&conn, "Enrolled", Permission::ADMIN)?;
+        let member = Account::create(&conn, "Member", Permission::MEMBER)?;
+
+        enroll(&conn, &enrolled)?;
+        let code = current_code(&conn, &enrolled, now())?;
+        confirm(&conn, &enrolled, "session", &code)?;
+
+        let unverified = Session::create(&conn, &admin.id, false)?;
+        let pending = Session::create(&conn, &admin.id, true)?;
+        let verified = Session::create(&conn, &enrolled.id, false)?;
+        let default = Session::create(&conn, &member.id, false)?;
+
+        assert_eq!(delete_admin_sessions_without_second_factor(&conn)?, 1);
+        assert!(Session::get(&conn, &unverified.id).is_err());
+        assert!(Session::get(&conn, &pending.id).is_ok());
+        assert!(Session::get(&conn, &verified.id).is_ok());
+        assert!(Session::get(&conn, &default.id).is_ok());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Weekday};
+
+use crate::core::{ServiceError, ServiceResult, Uuid, DB};
+
+/// Represent an availability window of a product
+///
+/// A product is available if the current date is between `date_start` and `date_end` (both inclusive
+/// and optional) and the current weekday is one of the `weekdays`.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
+pub struct Availability {
+    #[serde(default = "crate::core::generate_uuid")]
+    pub id: Uuid,
+    pub date_start: Option<NaiveDate>,
+    pub date_end: Option<NaiveDate>,
+    #[serde(default = "all_weekdays")]
+    pub weekdays: Vec<Weekday>,
+}
+
+/// List of all weekdays, starting with monday
+pub fn all_weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+}
+
+impl Availability {
+    /// Check if this availability window contains the given datetime
+    pub fn contains(&self, datetime: &NaiveDateTime) -> bool {
+        let date = datetime.date();
+
+        if let Some(date_start) = self.date_start {
+            if date < date_start {
+                return false;
+            }
+        }
+        if let Some(date_end) = self.date_end {
+            if date > date_end {
+                return false;
+            }
+        }
+
+        self.weekdays.contains(&date.weekday())
+    }
+
+    /// Check that this availability window can contain any date
+    pub fn validate(&self) -> ServiceResult<()> {
+        if let (Some(date_start), Some(date_end)) = (self.date_start, self.date_end) {
+            if date_start > date_end {
+                return Err(ServiceError::invalid_field(
+                    "availabilities",
+                    format!(
+                        "The start date {} is after the end date {}",
+                        date_start, date_end
+                    ),
+                ));
+            }
+        }
+        if self.weekdays.is_empty() {
+            return Err(ServiceError::invalid_field(
+                "availabilities",
+                "At least one weekday is required".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Convert the `weekdays` vec to its database representation
+    ///
+    /// Every weekday is represented by one bit, starting with monday as the lowest bit.
+    pub fn weekdays_to_bits(&self) -> i16 {
+        self.weekdays
+            .iter()
+            .fold(0, |bits, day| bits | (1 << day.num_days_from_monday()))
+    }
+
+    /// Convert the database representation of weekdays to a `weekdays` vec
+    pub fn weekdays_from_bits(bits: i16) -> Vec<Weekday> {
+        all_weekdays()
+            .into_iter()
+            .filter(|day| bits & (1 << day.num_days_from_monday()) != 0)
+            .collect()
+    }
+}
+
+/// Custom db loader for `Availability`
+///
+/// Skip product id
+impl
+    diesel::Queryable<
+        (
+            crate::core::sql_types::Uuid,
+            crate::core::sql_types::Uuid,
+            diesel::sql_types::Nullable<diesel::sql_types::Date>,
+            diesel::sql_types::Nullable<diesel::sql_types::Date>,
+            diesel::sql_types::SmallInt,
+        ),
+        DB,
+    > for Availability
+{
+    type Row = (Uuid, Uuid, Option<NaiveDate>, Option<NaiveDate>, i16);
+
+    fn build(row: Self::Row) -> Self {
+        Availability {
+            id: row.0,
+            date_start: row.2,
+            date_end: row.3,
+            weekdays: Availability::weekdays_from_bits(row.4),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn availability(
+        date_start: Option<NaiveDate>,
+        date_end: Option<NaiveDate>,
+        weekdays: Vec<Weekday>,
+    ) -> Availability {
+        Availability {
+            id: crate::core::generate_uuid(),
+            date_start,
+            date_end,
+            weekdays,
+        }
+    }
+
+    #[test]
+    fn test_weekday_bits() {
+        let weekend = availability(None, None, vec![Weekday::Sat, Weekday::Sun]);
+        assert_eq!(weekend.weekdays_to_bits(), 0b110_0000);
+        assert_eq!(
+            Availability::weekdays_from_bits(0b110_0000),
+            vec![Weekday::Sat, Weekday::Sun]
+        );
+
+        let all = availability(None, None, all_weekdays());
+        assert_eq!(all.weekdays_to_bits(), 0b111_1111);
+        assert_eq!(Availability::weekdays_from_bits(0b111_1111), all_weekdays());
+
+        assert_eq!(availability(None, None, vec![]).weekdays_to_bits(), 0);
+        assert!(Availability::weekdays_from_bits(0).is_empty());
+        assert_eq!(Availability::weekdays_from_bits(0b1), vec![Weekday::Mon]);
+    }
+
+    #[test]
+    fn test_contains() {
+        // 2020-04-06 is a monday
+        let at = |day: u32, hour: u32| NaiveDate::from_ymd(2020, 4, day).and_hms(hour, 0, 0);
+        let april = availability(
+            Some(NaiveDate::from_ymd(2020, 4, 6)),
+            Some(NaiveDate::from_ymd(2020, 4, 12)),
+            vec![Weekday::Mon, Weekday::Sun],
+        );
+
+        // Both dates are inclusive for the whole day
+        assert!(april.contains(&at(6, 0)));
+        assert!(april.contains(&at(12, 23)));
+        assert!(!april.contains(&at(7, 12)));
+        assert!(!april.contains(&at(5, 12)));
+        assert!(!april.contains(&at(13, 12)));
+
+        let open_end = availability(Some(NaiveDate::from_ymd(2020, 4, 6)), None, all_weekdays());
+        assert!(!open_end.contains(&at(5, 12)));
+        assert!(open_end.contains(&NaiveDate::from_ymd(2030, 1, 1).and_hms(12, 0, 0)));
+
+        let never = availability(None, None, vec![]);
+        assert!(!never.contains(&at(6, 12)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let day = |day: u32| Some(NaiveDate::from_ymd(2020, 4, day));
+
+        assert!(availability(None, None, all_weekdays()).validate().is_ok());
+        assert!(availability(day(6), day(6), vec![Weekday::Mon])
+            .validate()
+            .is_ok());
+        assert!(availability(day(7), None, vec![Weekday::Mon])
+            .validate()
+            .is_ok());
+
+        for invalid in &[
+            availability(day(7), day(6), all_weekdays()),
+            availability(None, None, vec![]),
+        ] {
+            assert!(matches!(
+                invalid.validate(),
+                Err(ServiceError::InvalidFields(_))
+            ));
+        }
+    }
+}
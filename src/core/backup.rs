@@ -0,0 +1,739 @@
+//! Full export and import of the database and the product images.
+//!
+//! The backup is a single versioned json document, so it can be used to move the data between
+//! servers independent of the database and storage backend. Sessions are not exported.
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::core::schema::{
+    account, authentication_barcode, authentication_nfc, authentication_nfc_write_key,
+    authentication_password, authentication_password_invitation, authentication_totp,
+    authentication_totp_recovery, category, category_price, product, product_availability,
+    product_barcode, product_price, terminal, transaction, transaction_product,
+};
+use crate::core::storage::image_storage;
+use crate::core::{images, snapshot_transaction, DbConnection, ServiceError, ServiceResult, Uuid};
+
+/// Version of the backup format, this has to be increased on every change of the exported tables
+pub const BACKUP_VERSION: u32 = 4;
+
+/// Older backup versions that can still be imported
+///
+/// Version 1 has no terminals, version 2 no terminal certificates and version 3 no totp secrets,
+/// the missing tables and columns are filled with their defaults.
+const COMPATIBLE_VERSIONS: &[u32] = &[1, 2, 3, BACKUP_VERSION];
+
+/// Maximal number of rows per insert statement
+const INSERT_CHUNK_SIZE: usize = 1000;
+
+/// Insert all `rows` into the `table` in chunks of `INSERT_CHUNK_SIZE`
+macro_rules! insert_chunked {
+    ($conn:expr, $table:expr, $rows:expr) => {
+        for chunk in $rows.chunks(INSERT_CHUNK_SIZE) {
+            diesel::insert_into($table).values(chunk).execute($conn)?;
+        }
+    };
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "account"]
+pub struct AccountRow {
+    id: Uuid,
+    credit: i32,
+    minimum_credit: i32,
+    name: String,
+    mail: Option<String>,
+    username: Option<String>,
+    account_number: Option<String>,
+    permission: i16,
+    receives_monthly_report: bool,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_barcode"]
+pub struct AuthenticationBarcodeRow {
+    account_id: Uuid,
+    code: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_nfc"]
+pub struct AuthenticationNfcRow {
+    account_id: Uuid,
+    card_id: String,
+    key: Option<String>,
+    secret: Option<String>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_nfc_write_key"]
+pub struct AuthenticationNfcWriteKeyRow {
+    account_id: Uuid,
+    card_id: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_password"]
+pub struct AuthenticationPasswordRow {
+    account_id: Uuid,
+    password: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_password_invitation"]
+pub struct AuthenticationPasswordInvitationRow {
+    account_id: Uuid,
+    link: String,
+    valid_until: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_totp"]
+pub struct AuthenticationTotpRow {
+    account_id: Uuid,
+    secret: String,
+    confirmed: bool,
+    last_step: Option<i64>,
+    created: NaiveDateTime,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "authentication_totp_recovery"]
+pub struct AuthenticationTotpRecoveryRow {
+    account_id: Uuid,
+    code_hash: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "category"]
+pub struct CategoryRow {
+    id: Uuid,
+    name: String,
+    parent: Option<Uuid>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "category_price"]
+pub struct CategoryPriceRow {
+    category_id: Uuid,
+    validity_start: NaiveDateTime,
+    value: i32,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "product"]
+pub struct ProductRow {
+    id: Uuid,
+    name: String,
+    category: Option<Uuid>,
+    image: Option<String>,
+    parent: Option<Uuid>,
+    active: bool,
+    sort_order: i32,
+    quick_key: bool,
+    color: Option<String>,
+    icon: Option<String>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "product_availability"]
+pub struct ProductAvailabilityRow {
+    id: Uuid,
+    product_id: Uuid,
+    date_start: Option<NaiveDate>,
+    date_end: Option<NaiveDate>,
+    weekdays: i16,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "product_barcode"]
+pub struct ProductBarcodeRow {
+    product_id: Uuid,
+    code: String,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "product_price"]
+pub struct ProductPriceRow {
+    product_id: Uuid,
+    validity_start: NaiveDateTime,
+    value: i32,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "terminal"]
+pub struct TerminalRow {
+    id: Uuid,
+    name: String,
+    location: Option<String>,
+    key_hash: String,
+    created: NaiveDateTime,
+    last_seen: Option<NaiveDateTime>,
+    revoked: Option<NaiveDateTime>,
+    #[serde(default)]
+    certificate_subject: Option<String>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "transaction"]
+pub struct TransactionRow {
+    id: Uuid,
+    account_id: Uuid,
+    cashier_id: Option<Uuid>,
+    total: i32,
+    before_credit: i32,
+    after_credit: i32,
+    date: NaiveDateTime,
+    #[serde(default)]
+    terminal_id: Option<Uuid>,
+}
+
+#[derive(Debug, Queryable, Insertable, Serialize, Deserialize)]
+#[table_name = "transaction_product"]
+pub struct TransactionProductRow {
+    transaction: Uuid,
+    product_id: Uuid,
+    amount: i32,
+}
+
+/// Represent the content of all exported tables
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupTables {
+    account: Vec<AccountRow>,
+    authentication_barcode: Vec<AuthenticationBarcodeRow>,
+    authentication_nfc: Vec<AuthenticationNfcRow>,
+    authentication_nfc_write_key: Vec<AuthenticationNfcWriteKeyRow>,
+    authentication_password: Vec<AuthenticationPasswordRow>,
+    authentication_password_invitation: Vec<AuthenticationPasswordInvitationRow>,
+    #[serde(default)]
+    authentication_totp: Vec<AuthenticationTotpRow>,
+    #[serde(default)]
+    authentication_totp_recovery: Vec<AuthenticationTotpRecoveryRow>,
+    category: Vec<CategoryRow>,
+    category_price: Vec<CategoryPriceRow>,
+    product: Vec<ProductRow>,
+    product_availability: Vec<ProductAvailabilityRow>,
+    product_barcode: Vec<ProductBarcodeRow>,
+    product_price: Vec<ProductPriceRow>,
+    #[serde(default)]
+    terminal: Vec<TerminalRow>,
+    transaction: Vec<TransactionRow>,
+    transaction_product: Vec<TransactionProductRow>,
+}
+
+/// Represent a full backup of the database and the product images
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub created: NaiveDateTime,
+    pub tables: BackupTables,
+    /// Base64 encoded content of all product images and thumbnails by file name
+    pub images: BTreeMap<String, String>,
+}
+
+/// Summary of a backup, eg. the result of an import
+#[derive(Debug, Serialize)]
+pub struct BackupSummary {
+    /// Number of rows by table name
+    pub tables: BTreeMap<&'static str, usize>,
+    pub images: usize,
+}
+
+impl Backup {
+    pub fn summary(&self) -> BackupSummary {
+        let t = &self.tables;
+        let mut tables = BTreeMap::new();
+        tables.insert("account", t.account.len());
+        tables.insert("authentication_barcode", t.authentication_barcode.len());
+        tables.insert("authentication_nfc", t.authentication_nfc.len());
+        tables.insert(
+            "authentication_nfc_write_key",
+            t.authentication_nfc_write_key.len(),
+        );
+        tables.insert("authentication_password", t.authentication_password.len());
+        tables.insert(
+            "authentication_password_invitation",
+            t.authentication_password_invitation.len(),
+        );
+        tables.insert("authentication_totp", t.authentication_totp.len());
+        tables.insert(
+            "authentication_totp_recovery",
+            t.authentication_totp_recovery.len(),
+        );
+        tables.insert("category", t.category.len());
+        tables.insert("category_price", t.category_price.len());
+        tables.insert("product", t.product.len());
+        tables.insert("product_availability", t.product_availability.len());
+        tables.insert("product_barcode", t.product_barcode.len());
+        tables.insert("product_price", t.product_price.len());
+        tables.insert("terminal", t.terminal.len());
+        tables.insert("transaction", t.transaction.len());
+        tables.insert("transaction_product", t.transaction_product.len());
+
+        BackupSummary {
+            tables,
+            images: self.images.len(),
+        }
+    }
+}
+
+/// Export all tables and product images
+///
+/// All tables are read from the same snapshot of the database, so concurrent changes cannot break
+/// the references between them.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn export(conn: &DbConnection) -> ServiceResult<Backup> {
+    let tables = snapshot_transaction(conn, || {
+        Ok(BackupTables {
+            account: account::table.load(conn)?,
+            authentication_barcode: authentication_barcode::table.load(conn)?,
+            authentication_nfc: authentication_nfc::table.load(conn)?,
+            authentication_nfc_write_key: authentication_nfc_write_key::table.load(conn)?,
+            authentication_password: authentication_password::table.load(conn)?,
+            authentication_password_invitation: authentication_password_invitation::table
+                .load(conn)?,
+            authentication_totp: authentication_totp::table.load(conn)?,
+            authentication_totp_recovery: authentication_totp_recovery::table.load(conn)?,
+            category: category::table.load(conn)?,
+            category_price: category_price::table.load(conn)?,
+            product: product::table.load(conn)?,
+            product_availability: product_availability::table.load(conn)?,
+            product_barcode: product_barcode::table.load(conn)?,
+            product_price: product_price::table.load(conn)?,
+            terminal: terminal::table.load(conn)?,
+            transaction: transaction::table.load(conn)?,
+            transaction_product: transaction_product::table.load(conn)?,
+        })
+    })?;
+
+    let mut images = BTreeMap::new();
+    for name in tables.product.iter().filter_map(|p| p.image.as_ref()) {
+        for file in images::image_files(name) {
+            match image_storage().get(&file) {
+                Ok(data) => {
+                    images.insert(file, base64::encode(&data));
+                }
+                // Images uploaded before thumbnails were introduced only have the original file
+                Err(ServiceError::NotFound) if file != *name => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created: Local::now().naive_local(),
+        tables,
+        images,
+    })
+}
+
+/// Import a backup into an empty database
+///
+/// All references between the tables are checked before anything is written. With `dry_run`
+/// only the checks are performed. The tables are imported in a single database transaction.
+#[tracing::instrument(level = "debug", skip_all, fields(dry_run))]
+pub fn import(conn: &DbConnection, backup: &Backup, dry_run: bool) -> ServiceResult<BackupSummary> {
+    check_version(backup)?;
+    check_empty(conn)?;
+    check_references(backup)?;
+
+    let mut decoded_images = Vec::with_capacity(backup.images.len());
+    for (name, data) in &backup.images {
+        let data = base64::decode(data).map_err(|e| {
+            ServiceError::BadRequest("Invalid image data", format!("{}: {}", name, e))
+        })?;
+        decoded_images.push((name, data));
+    }
+
+    if dry_run {
+        return Ok(backup.summary());
+    }
+
+    conn.transaction::<_, ServiceError, _>(|| {
+        let t = &backup.tables;
+        insert_chunked!(conn, account::table, &t.account);
+        insert_chunked!(
+            conn,
+            authentication_barcode::table,
+            &t.authentication_barcode
+        );
+        insert_chunked!(conn, authentication_nfc::table, &t.authentication_nfc);
+        insert_chunked!(
+            conn,
+            authentication_nfc_write_key::table,
+            &t.authentication_nfc_write_key
+        );
+        insert_chunked!(
+            conn,
+            authentication_password::table,
+            &t.authentication_password
+        );
+        insert_chunked!(
+            conn,
+            authentication_password_invitation::table,
+            &t.authentication_password_invitation
+        );
+        insert_chunked!(conn, authentication_totp::table, &t.authentication_totp);
+        insert_chunked!(
+            conn,
+            authentication_totp_recovery::table,
+            &t.authentication_totp_recovery
+        );
+        insert_chunked!(conn, category::table, &t.category);
+        insert_chunked!(conn, category_price::table, &t.category_price);
+        insert_chunked!(conn, product::table, &t.product);
+        insert_chunked!(conn, product_availability::table, &t.product_availability);
+        insert_chunked!(conn, product_barcode::table, &t.product_barcode);
+        insert_chunked!(conn, product_price::table, &t.product_price);
+        insert_chunked!(conn, terminal::table, &t.terminal);
+        insert_chunked!(conn, transaction::table, &t.transaction);
+        insert_chunked!(conn, transaction_product::table, &t.transaction_product);
+        Ok(())
+    })?;
+
+    for (name, data) in decoded_images {
+        image_storage().put(name, &data)?;
+    }
+
+    Ok(backup.summary())
+}
+
+/// Check that the backup version can be imported
+fn check_version(backup: &Backup) -> ServiceResult<()> {
+    if !COMPATIBLE_VERSIONS.contains(&backup.version) {
+        return Err(ServiceError::BadRequest(
+            "Unsupported backup version",
+            format!(
+                "The backup has version {}, but only the versions {:?} are supported",
+                backup.version, COMPATIBLE_VERSIONS
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that the database does not contain any accounts, categories, products or transactions
+fn check_empty(conn: &DbConnection) -> ServiceResult<()> {
+    let counts: Vec<(&str, i64)> = vec![
+        ("account", account::table.count().get_result(conn)?),
+        ("category", category::table.count().get_result(conn)?),
+        ("product", product::table.count().get_result(conn)?),
+        ("transaction", transaction::table.count().get_result(conn)?),
+    ];
+
+    let non_empty: Vec<String> = counts
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(table, count)| format!("{} ({} rows)", table, count))
+        .collect();
+
+    if !non_empty.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Database not empty",
+            format!(
+                "A backup can only be imported into an empty database, found: {}",
+                non_empty.join(", ")
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Check that all references between the tables of the backup are valid
+fn check_references(backup: &Backup) -> ServiceResult<()> {
+    let t = &backup.tables;
+
+    let accounts: HashSet<Uuid> = t.account.iter().map(|r| r.id).collect();
+    let categories: HashSet<Uuid> = t.category.iter().map(|r| r.id).collect();
+    let products: HashSet<Uuid> = t.product.iter().map(|r| r.id).collect();
+    let terminals: HashSet<Uuid> = t.terminal.iter().map(|r| r.id).collect();
+    let transactions: HashSet<Uuid> = t.transaction.iter().map(|r| r.id).collect();
+
+    let mut errors: Vec<String> = Vec::new();
+    let mut check = |set: &HashSet<Uuid>, id: &Uuid, from: &str, to: &str| {
+        if !set.contains(id) {
+            errors.push(format!("{} references unknown {} '{}'", from, to, id));
+        }
+    };
+
+    for r in &t.authentication_barcode {
+        check(
+            &accounts,
+            &r.account_id,
+            "authentication_barcode",
+            "account",
+        );
+    }
+    for r in &t.authentication_nfc {
+        check(&accounts, &r.account_id, "authentication_nfc", "account");
+    }
+    for r in &t.authentication_nfc_write_key {
+        check(
+            &accounts,
+            &r.account_id,
+            "authentication_nfc_write_key",
+            "account",
+        );
+    }
+    for r in &t.authentication_password {
+        check(
+            &accounts,
+            &r.account_id,
+            "authentication_password",
+            "account",
+        );
+    }
+    for r in &t.authentication_password_invitation {
+        check(
+            &accounts,
+            &r.account_id,
+            "authentication_password_invitation",
+            "account",
+        );
+    }
+    for r in &t.authentication_totp {
+        check(&accounts, &r.account_id, "authentication_totp", "account");
+    }
+    for r in &t.authentication_totp_recovery {
+        check(
+            &accounts,
+            &r.account_id,
+            "authentication_totp_recovery",
+            "account",
+        );
+    }
+    for r in &t.category {
+        if let Some(parent) = &r.parent {
+            check(&categories, parent, "category", "category");
+        }
+    }
+    for r in &t.category_price {
+        check(&categories, &r.category_id, "category_price", "category");
+    }
+    for r in &t.product {
+        if let Some(category) = &r.category {
+            check(&categories, category, "product", "category");
+        }
+        if let Some(parent) = &r.parent {
+            check(&products, parent, "product", "product");
+        }
+    }
+    for r in &t.product_availability {
+        check(&products, &r.product_id, "product_availability", "product");
+    }
+    for r in &t.product_barcode {
+        check(&products, &r.product_id, "product_barcode", "product");
+    }
+    for r in &t.product_price {
+        check(&products, &r.product_id, "product_price", "product");
+    }
+    for r in &t.transaction {
+        check(&accounts, &r.account_id, "transaction", "account");
+        if let Some(cashier) = &r.cashier_id {
+            check(&accounts, cashier, "transaction", "account");
+        }
+        if let Some(terminal) = &r.terminal_id {
+            check(&terminals, terminal, "transaction", "terminal");
+        }
+    }
+    for r in &t.transaction_product {
+        check(
+            &transactions,
+            &r.transaction,
+            "transaction_product",
+            "transaction",
+        );
+        check(&products, &r.product_id, "transaction_product", "product");
+    }
+
+    // Cyclic hierarchies cannot be listed as a tree
+    let parents: HashMap<Uuid, Option<Uuid>> =
+        t.category.iter().map(|r| (r.id, r.parent)).collect();
+    for r in &t.category {
+        let mut seen = HashSet::new();
+        let mut next = r.parent;
+        while let Some(parent) = next {
+            if parent == r.id {
+                errors.push(format!("category '{}' is its own ancestor", r.id));
+                break;
+            }
+            if !seen.insert(parent) {
+                break;
+            }
+            next = parents.get(&parent).copied().flatten();
+        }
+    }
+
+    for r in &t.product {
+        if let Some(image) = &r.image {
+            if !backup.images.contains_key(image) {
+                errors.push(format!("product references unknown image '{}'", image));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Invalid backup references",
+            errors.join("\n"),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backup of version 1, without terminals and totp secrets
+    fn backup() -> serde_json::Value {
+        json!({
+            "version": 1,
+            "created": "2020-01-01T12:00:00",
+            "tables": {
+                "account": [{
+                    "id": "8c4a6f6e-3c3a-4c1b-9b8a-2f6a4c1b9b8a",
+                    "credit": 0,
+                    "minimum_credit": 0,
+                    "name": "Alice",
+                    "mail": null,
+                    "username": null,
+                    "account_number": null,
+                    "permission": 0,
+                    "receives_monthly_report": false
+                }],
+                "authentication_barcode": [],
+                "authentication_nfc": [],
+                "authentication_nfc_write_key": [],
+                "authentication_password": [],
+                "authentication_password_invitation": [],
+                "category": [{
+                    "id": "1d9e3f6a-5b2c-4e8d-a7f1-3c6b9e2d5a8f",
+                    "name": "Drinks",
+                    "parent": null
+                }],
+                "category_price": [],
+                "product": [{
+                    "id": "6f2a8c4e-9d1b-4a3c-b5e7-8d2f6a4c1e9b",
+                    "name": "Club Mate",
+                    "category": "1d9e3f6a-5b2c-4e8d-a7f1-3c6b9e2d5a8f",
+                    "image": "mate.png",
+                    "parent": null,
+                    "active": true,
+                    "sort_order": 0,
+                    "quick_key": false,
+                    "color": null,
+                    "icon": null
+                }],
+                "product_availability": [],
+                "product_barcode": [],
+                "product_price": [],
+                "transaction": [{
+                    "id": "3b7e1a9c-4d6f-4c2a-8e5b-9a1c7e3b5d2f",
+                    "account_id": "8c4a6f6e-3c3a-4c1b-9b8a-2f6a4c1b9b8a",
+                    "cashier_id": null,
+                    "total": -150,
+                    "before_credit": 0,
+                    "after_credit": -150,
+                    "date": "2020-01-01T12:00:00"
+                }],
+                "transaction_product": [{
+                    "transaction": "3b7e1a9c-4d6f-4c2a-8e5b-9a1c7e3b5d2f",
+                    "product_id": "6f2a8c4e-9d1b-4a3c-b5e7-8d2f6a4c1e9b",
+                    "amount": 1
+                }]
+            },
+            "images": {
+                "mate.png": "aW1hZ2U="
+            }
+        })
+    }
+
+    fn parse(value: serde_json::Value) -> Backup {
+        serde_json::from_value(value).expect("valid backup json")
+    }
+
+    #[test]
+    fn test_check_version() {
+        let mut value = backup();
+        for version in COMPATIBLE_VERSIONS {
+            value["version"] = json!(version);
+            assert!(check_version(&parse(value.clone())).is_ok());
+        }
+
+        for version in &[0, BACKUP_VERSION + 1] {
+            value["version"] = json!(version);
+            match check_version(&parse(value.clone())) {
+                Err(ServiceError::BadRequest(title, _)) => {
+                    assert_eq!(title, "Unsupported backup version")
+                }
+                result => panic!("unexpected result {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_references() {
+        let backup = parse(backup());
+        assert!(backup.tables.terminal.is_empty());
+        assert_eq!(backup.tables.transaction[0].terminal_id, None);
+        assert!(check_references(&backup).is_ok());
+
+        let mut value = self::backup();
+        let unknown = "0e5c3a1f-7b9d-4f2e-a6c8-1b3d5f7a9c2e";
+        value["tables"]["product"][0]["category"] = json!(unknown);
+        value["tables"]["product"][0]["parent"] = json!(unknown);
+        value["tables"]["transaction"][0]["cashier_id"] = json!(unknown);
+        value["tables"]["transaction"][0]["terminal_id"] = json!(unknown);
+        value["tables"]["transaction_product"][0]["transaction"] = json!(unknown);
+        value["images"] = json!({});
+
+        match check_references(&parse(value)) {
+            Err(ServiceError::BadRequest(title, errors)) => {
+                assert_eq!(title, "Invalid backup references");
+                assert_eq!(
+                    errors.lines().collect::<Vec<_>>(),
+                    vec![
+                        format!("product references unknown category '{}'", unknown),
+                        format!("product references unknown product '{}'", unknown),
+                        format!("transaction references unknown account '{}'", unknown),
+                        format!("transaction references unknown terminal '{}'", unknown),
+                        format!(
+                            "transaction_product references unknown transaction '{}'",
+                            unknown
+                        ),
+                        "product references unknown image 'mate.png'".to_owned(),
+                    ]
+                );
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        // A self parented category and a cycle of two categories
+        let drinks = "1d9e3f6a-5b2c-4e8d-a7f1-3c6b9e2d5a8f";
+        let sweets = "5a8f1d9e-3f6a-4b2c-8e7d-a1f3c6b9e2d5";
+        let chocolate = "9e2d5a8f-1d9e-4f6a-b5b2-c4e8da7f13c6";
+        let mut value = self::backup();
+        value["tables"]["category"][0]["parent"] = json!(drinks);
+        let categories = value["tables"]["category"].as_array_mut().unwrap();
+        categories.push(json!({"id": sweets, "name": "Sweets", "parent": chocolate}));
+        categories.push(json!({"id": chocolate, "name": "Chocolate", "parent": sweets}));
+
+        match check_references(&parse(value)) {
+            Err(ServiceError::BadRequest(_, errors)) => assert_eq!(
+                errors.lines().collect::<Vec<_>>(),
+                vec![
+                    format!("category '{}' is its own ancestor", drinks),
+                    format!("category '{}' is its own ancestor", sweets),
+                    format!("category '{}' is its own ancestor", chocolate),
+                ]
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,524 @@
+//! Export and import of the product catalogue as CSV or JSON.
+//!
+//! An import is always previewed as a diff against the current products before it is applied.
+use chrono::NaiveDate;
+use diesel::prelude::*;
+use std::collections::HashMap;
+
+use crate::core::{
+    Category, DbConnection, Money, Price, Product, ServiceError, ServiceResult, Uuid,
+};
+
+/// Represent a product in the exported catalogue
+///
+/// Variants are listed as separate entries with the `parent` set to the id of their product.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct CatalogueEntry {
+    /// Id of an existing product, new products are created for entries without an id
+    pub id: Option<Uuid>,
+    pub parent: Option<Uuid>,
+    pub name: String,
+    /// Name of the category
+    pub category: Option<String>,
+    pub barcode: Option<String>,
+    /// Full price history of the product
+    #[serde(default = "std::vec::Vec::new")]
+    pub prices: Vec<Price>,
+    /// File name of the product image, this is ignored on import
+    pub image: Option<String>,
+}
+
+impl CatalogueEntry {
+    fn from_product(product: &Product) -> CatalogueEntry {
+        let mut prices = product.prices.clone();
+        prices.sort_by(|p1, p2| p1.validity_start.cmp(&p2.validity_start));
+
+        CatalogueEntry {
+            id: Some(product.id),
+            parent: product.parent,
+            name: product.name.clone(),
+            category: product.category.as_ref().map(|c| c.name.clone()),
+            barcode: product.barcode.clone(),
+            prices,
+            image: product.image.clone(),
+        }
+    }
+
+    /// List the names of all fields that differ from the given product
+    fn changed_fields(&self, product: &Product) -> Vec<&'static str> {
+        let current = CatalogueEntry::from_product(product);
+        let mut prices = self.prices.clone();
+        prices.sort_by(|p1, p2| p1.validity_start.cmp(&p2.validity_start));
+
+        let mut fields = Vec::new();
+        if self.name != current.name {
+            fields.push("name");
+        }
+        if self.parent != current.parent {
+            fields.push("parent");
+        }
+        if self.category != current.category {
+            fields.push("category");
+        }
+        if self.barcode != current.barcode {
+            fields.push("barcode");
+        }
+        if prices != current.prices {
+            fields.push("prices");
+        }
+        fields
+    }
+}
+
+/// Represent a csv row of the catalogue
+///
+/// The price history is encoded as `date=value` pairs separated by `;`, eg. `2020-01-01=1.50;2020-06-01=1.80`.
+#[derive(Debug, Serialize, Deserialize)]
+struct CatalogueRow {
+    id: String,
+    parent: String,
+    name: String,
+    category: String,
+    barcode: String,
+    prices: String,
+    image: String,
+}
+
+/// Export all products including their variants
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn export(conn: &DbConnection) -> ServiceResult<Vec<CatalogueEntry>> {
+    let mut entries = Vec::new();
+
+    for product in Product::all(conn)? {
+        entries.push(CatalogueEntry::from_product(&product));
+        for variant in &product.variants {
+            entries.push(CatalogueEntry::from_product(variant));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Serialize the catalogue as csv with a header line
+pub fn to_csv(entries: &[CatalogueEntry]) -> ServiceResult<String> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+
+    for entry in entries {
+        let prices = entry
+            .prices
+            .iter()
+            .map(|p| {
+                format!(
+                    "{}={:.2}",
+                    p.validity_start.format("%Y-%m-%d"),
+                    p.value as f64 / 100.0
+                )
+            })
+            .collect::<Vec<String>>()
+            .join(";");
+
+        writer.serialize(CatalogueRow {
+            id: entry.id.map(|id| id.to_string()).unwrap_or_default(),
+            parent: entry.parent.map(|id| id.to_string()).unwrap_or_default(),
+            name: entry.name.clone(),
+            category: entry.category.clone().unwrap_or_default(),
+            barcode: entry.barcode.clone().unwrap_or_default(),
+            prices,
+            image: entry.image.clone().unwrap_or_default(),
+        })?;
+    }
+
+    let data = writer
+        .into_inner()
+        .map_err(|e| ServiceError::InternalServerError("CSV error", format!("{}", e)))?;
+
+    String::from_utf8(data)
+        .map_err(|e| ServiceError::InternalServerError("CSV error", format!("{}", e)))
+}
+
+/// Parse a catalogue in csv format, the first line has to be the header line
+pub fn from_csv(data: &str) -> ServiceResult<Vec<CatalogueEntry>> {
+    let non_empty = |s: String| {
+        let s = s.trim().to_owned();
+        if s.is_empty() {
+            None
+        } else {
+            Some(s)
+        }
+    };
+    let parse_uuid = |s: String| -> ServiceResult<Option<Uuid>> {
+        match non_empty(s) {
+            Some(s) => Ok(Some(Uuid::parse_str(&s)?)),
+            None => Ok(None),
+        }
+    };
+
+    let mut reader = csv::Reader::from_reader(data.as_bytes());
+    let mut entries = Vec::new();
+
+    for row in reader.deserialize() {
+        let row: CatalogueRow = row?;
+
+        entries.push(CatalogueEntry {
+            id: parse_uuid(row.id)?,
+            parent: parse_uuid(row.parent)?,
+            name: row.name.trim().to_owned(),
+            category: non_empty(row.category),
+            barcode: non_empty(row.barcode),
+            prices: parse_prices(&row.prices)?,
+            image: non_empty(row.image),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn parse_prices(prices: &str) -> ServiceResult<Vec<Price>> {
+    let invalid = || {
+        ServiceError::BadRequest(
+            "Invalid price list",
+            format!(
+                "'{}' does not match the format '2020-01-01=1.50;2020-06-01=1.80'",
+                prices
+            ),
+        )
+    };
+
+    prices
+        .split(';')
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            let mut parts = p.splitn(2, '=');
+            let date = parts.next().ok_or_else(invalid)?.trim();
+            let value = parts.next().ok_or_else(invalid)?.trim();
+
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid())?;
+            let value = value.parse::<f64>().map_err(|_| invalid())?;
+
+            Ok(Price {
+                validity_start: date.and_hms(0, 0, 0),
+                value: (value * 100.0).round() as Money,
+            })
+        })
+        .collect()
+}
+
+/// Parse a catalogue in json or csv format, json is detected by a leading `[`
+pub fn parse(data: &str) -> ServiceResult<Vec<CatalogueEntry>> {
+    if data.trim_start().starts_with('[') {
+        serde_json::from_str(data)
+            .map_err(|e| ServiceError::BadRequest("JSON error", format!("{}", e)))
+    } else {
+        from_csv(data)
+    }
+}
+
+/// Represent a changed product of an import
+#[derive(Debug, Serialize, Clone)]
+pub struct ChangedEntry {
+    pub product: Product,
+    pub entry: CatalogueEntry,
+    pub fields: Vec<&'static str>,
+}
+
+/// Preview of a catalogue import
+#[derive(Debug, Serialize, Clone)]
+pub struct CatalogueDiff {
+    pub new: Vec<CatalogueEntry>,
+    pub changed: Vec<ChangedEntry>,
+    pub unchanged: Vec<CatalogueEntry>,
+}
+
+/// Compare the imported entries with the current products
+///
+/// Entries are matched by their id, entries without id are matched by the parent and the product
+/// name. Names that match multiple products are rejected. All categories have to exist already.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn diff(conn: &DbConnection, entries: Vec<CatalogueEntry>) -> ServiceResult<CatalogueDiff> {
+    let categories: HashMap<String, Category> = Category::all(conn)?
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+
+    let mut products: HashMap<Uuid, Product> = HashMap::new();
+    for product in Product::all(conn)? {
+        for variant in &product.variants {
+            products.insert(variant.id, variant.clone());
+        }
+        products.insert(product.id, product);
+    }
+
+    let mut result = CatalogueDiff {
+        new: vec![],
+        changed: vec![],
+        unchanged: vec![],
+    };
+
+    for mut entry in entries {
+        if entry.name.is_empty() {
+            return Err(ServiceError::BadRequest(
+                "Invalid catalogue entry",
+                "Every product needs a name".to_owned(),
+            ));
+        }
+        if let Some(category) = &entry.category {
+            if !categories.contains_key(category) {
+                return Err(ServiceError::BadRequest(
+                    "Unknown category",
+                    format!("The category '{}' does not exist", category),
+                ));
+            }
+        }
+
+        let product = match entry.id {
+            Some(id) => Some(products.get(&id).ok_or_else(|| {
+                ServiceError::BadRequest(
+                    "Unknown product",
+                    format!("The product with the id '{}' does not exist", id),
+                )
+            })?),
+            None => {
+                // Variant names like `small` repeat across products, so the parent has to match
+                let mut matches = products
+                    .values()
+                    .filter(|p| p.parent == entry.parent && p.name == entry.name);
+                let product = matches.next();
+                if matches.next().is_some() {
+                    return Err(ServiceError::BadRequest(
+                        "Ambiguous product name",
+                        format!(
+                            "Multiple products are named '{}', add the id to the entry",
+                            entry.name
+                        ),
+                    ));
+                }
+                product
+            }
+        };
+
+        match product {
+            Some(product) => {
+                entry.id = Some(product.id);
+                let fields = entry.changed_fields(product);
+
+                if fields.is_empty() {
+                    result.unchanged.push(entry);
+                } else {
+                    result.changed.push(ChangedEntry {
+                        product: product.clone(),
+                        entry,
+                        fields,
+                    });
+                }
+            }
+            None => result.new.push(entry),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Apply a previewed import, new products are created and changed products are updated
+///
+/// The import is applied in a single database transaction, so a failing entry leaves all
+/// products unchanged.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn apply(conn: &DbConnection, diff: &CatalogueDiff) -> ServiceResult<()> {
+    let categories: HashMap<String, Category> = Category::all(conn)?
+        .into_iter()
+        .map(|c| (c.name.clone(), c))
+        .collect();
+    let get_category = |entry: &CatalogueEntry| {
+        entry
+            .category
+            .as_ref()
+            .and_then(|name| categories.get(name))
+            .cloned()
+    };
+
+    conn.transaction::<_, ServiceError, _>(|| {
+        for entry in &diff.new {
+            let mut product = Product::create(conn, &entry.name, get_category(entry))?;
+
+            product.parent = entry.parent;
+            product.barcode = entry.barcode.clone();
+            product.update(conn)?;
+
+            product.update_prices(conn, &entry.prices)?;
+        }
+
+        for changed in &diff.changed {
+            let entry = &changed.entry;
+            let mut product = Product::get(conn, &changed.product.id)?;
+
+            product.name = entry.name.clone();
+            product.parent = entry.parent;
+            product.category = get_category(entry);
+            product.barcode = entry.barcode.clone();
+            product.update(conn)?;
+
+            if changed.fields.contains(&"prices") {
+                product.update_prices(conn, &entry.prices)?;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_connection;
+    use chrono::NaiveDate;
+
+    fn entry(name: &str) -> CatalogueEntry {
+        CatalogueEntry {
+            id: None,
+            parent: None,
+            name: name.to_owned(),
+            category: None,
+            barcode: None,
+            prices: Vec::new(),
+            image: None,
+        }
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_apply_rolls_back() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut invalid = entry("Mate Cola");
+        invalid.parent = Some(Uuid::new_v4());
+        let diff = diff(&conn, vec![entry("Club Mate"), invalid])?;
+        assert_eq!(diff.new.len(), 2);
+
+        assert!(apply(&conn, &diff).is_err());
+        // The valid entry before the failing one is not imported either
+        assert!(Product::all(&conn)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_diff() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let drinks = Category::create(&conn, "Drinks")?;
+        let mut mate = Product::create(&conn, "Club Mate", Some(drinks))?;
+        mate.barcode = Some("4029764001807".to_owned());
+        mate.update(&conn)?;
+        let cola = Product::create(&conn, "Cola", None)?;
+        let water = Product::create(&conn, "Water", None)?;
+
+        let mut entries = export(&conn)?;
+        assert_eq!(entries.len(), 3);
+
+        // Changed by id, the export itself is unchanged
+        let mut renamed = entries.remove(0);
+        assert_eq!(renamed.id, Some(mate.id));
+        renamed.name = "Mate".to_owned();
+        renamed.barcode = None;
+
+        // Matched by name without an id
+        let mut priced = entry("Cola");
+        priced.prices = vec![Price {
+            validity_start: NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0),
+            value: 150,
+        }];
+
+        let diff = diff(
+            &conn,
+            vec![renamed, priced, entries.remove(1), entry("Juice")],
+        )?;
+
+        assert_eq!(diff.changed.len(), 2);
+        assert_eq!(diff.changed[0].product.id, mate.id);
+        assert_eq!(diff.changed[0].fields, vec!["name", "barcode"]);
+        assert_eq!(diff.changed[1].product.id, cola.id);
+        assert_eq!(diff.changed[1].entry.id, Some(cola.id));
+        assert_eq!(diff.changed[1].fields, vec!["prices"]);
+        assert_eq!(diff.unchanged.len(), 1);
+        assert_eq!(diff.unchanged[0].id, Some(water.id));
+        assert_eq!(diff.new.len(), 1);
+        assert_eq!(diff.new[0].name, "Juice");
+
+        let mut unknown_category = entry("Juice");
+        unknown_category.category = Some("Food".to_owned());
+        assert!(super::diff(&conn, vec![unknown_category]).is_err());
+
+        let mut unknown_id = entry("Juice");
+        unknown_id.id = Some(Uuid::new_v4());
+        assert!(super::diff(&conn, vec![unknown_id]).is_err());
+
+        assert!(super::diff(&conn, vec![entry("")]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_diff_by_parent() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut variants = Vec::new();
+        for name in &["Coffee", "Tea"] {
+            let product = Product::create(&conn, name, None)?;
+            let mut small = Product::create(&conn, "small", None)?;
+            small.parent = Some(product.id);
+            small.update(&conn)?;
+            variants.push(small);
+        }
+
+        // Entries without an id are matched by their parent and name
+        let mut small_tea = entry("small");
+        small_tea.parent = variants[1].parent;
+        small_tea.barcode = Some("4000000000001".to_owned());
+        let diff = diff(&conn, vec![small_tea, entry("small")])?;
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].product.id, variants[1].id);
+        assert_eq!(diff.changed[0].fields, vec!["barcode"]);
+        assert_eq!(diff.new.len(), 1);
+        assert!(diff.unchanged.is_empty());
+
+        Product::create(&conn, "Coffee", None)?;
+        match super::diff(&conn, vec![entry("Coffee")]) {
+            Err(ServiceError::BadRequest(title, _)) => assert_eq!(title, "Ambiguous product name"),
+            result => panic!("unexpected result {:?}", result),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv() -> ServiceResult<()> {
+        let mut mate = entry("Club Mate, 0.5l");
+        mate.id = Some(Uuid::new_v4());
+        mate.category = Some("Drinks".to_owned());
+        mate.prices = vec![
+            Price {
+                validity_start: NaiveDate::from_ymd(2020, 1, 1).and_hms(0, 0, 0),
+                value: 150,
+            },
+            Price {
+                validity_start: NaiveDate::from_ymd(2020, 6, 1).and_hms(0, 0, 0),
+                value: 180,
+            },
+        ];
+        let mut variant = entry("Club Mate, 0.33l");
+        variant.parent = mate.id;
+        let entries = vec![mate, variant];
+
+        let csv = to_csv(&entries)?;
+        assert!(csv.contains("2020-01-01=1.50;2020-06-01=1.80"));
+        assert_eq!(parse(&csv)?, entries);
+        assert_eq!(parse(&serde_json::to_string(&entries)?)?, entries);
+
+        assert!(parse("id,parent,name,category,barcode,prices,image\n,,Mate,,,1.50,\n").is_err());
+
+        Ok(())
+    }
+}
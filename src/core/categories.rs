@@ -1,8 +1,10 @@
 use chrono::{Local, NaiveDateTime};
 use diesel::prelude::*;
-use uuid::Uuid;
+use std::collections::{HashMap, HashSet};
 
-use crate::core::{generate_uuid, DbConnection, Money, Price, ServiceError, ServiceResult, DB};
+use crate::core::{
+    generate_uuid, DbConnection, Money, Price, ServiceError, ServiceResult, Uuid, DB,
+};
 
 /// Represent a category
 #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Clone)]
@@ -13,13 +15,37 @@ pub struct Category {
     #[serde(default = "std::vec::Vec::new")]
     pub prices: Vec<Price>,
     pub current_price: Option<Money>,
+    /// The parent category in the category hierarchy
+    pub parent: Option<Uuid>,
+    /// All ancestors of this category, starting with the direct parent
+    ///
+    /// The prices of the ancestors are used as fallback for the `current_price`
+    #[serde(skip)]
+    pub ancestors: Vec<Category>,
+}
+
+/// Represent a category with all its subcategories
+#[derive(Debug, Serialize, Clone)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    pub children: Vec<CategoryNode>,
 }
 
 /// Custom db loader for `Category`
 ///
 /// Ignore price vec
-impl diesel::Queryable<(diesel::sql_types::Uuid, diesel::sql_types::Text), DB> for Category {
-    type Row = (Uuid, String);
+impl
+    diesel::Queryable<
+        (
+            crate::core::sql_types::Uuid,
+            diesel::sql_types::Text,
+            diesel::sql_types::Nullable<crate::core::sql_types::Uuid>,
+        ),
+        DB,
+    > for Category
+{
+    type Row = (Uuid, String, Option<Uuid>);
 
     fn build(row: Self::Row) -> Self {
         Category {
@@ -27,12 +53,15 @@ impl diesel::Queryable<(diesel::sql_types::Uuid, diesel::sql_types::Text), DB> f
             name: row.1,
             prices: vec![],
             current_price: None,
+            parent: row.2,
+            ancestors: vec![],
         }
     }
 }
 
 impl Category {
     /// Create a new category with the given name and category
+    #[tracing::instrument(level = "debug", skip_all, fields(name))]
     pub fn create(conn: &DbConnection, name: &str) -> ServiceResult<Category> {
         use crate::core::schema::category::dsl;
 
@@ -41,6 +70,8 @@ impl Category {
             name: name.to_owned(),
             prices: vec![],
             current_price: None,
+            parent: None,
+            ancestors: vec![],
         };
 
         diesel::insert_into(dsl::category)
@@ -53,11 +84,23 @@ impl Category {
     /// Save the current category data to the database
     ///
     /// This ignores all changes to the `prices` vec
+    #[tracing::instrument(level = "debug", skip_all, fields(category_id = %self.id))]
     pub fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::category::dsl;
 
+        if let Some(parent_id) = &self.parent {
+            let parent = Category::get(conn, parent_id)?;
+
+            if parent.id == self.id || parent.ancestors.iter().any(|a| a.id == self.id) {
+                return Err(ServiceError::invalid_field(
+                    "parent",
+                    "A category cannot be a subcategory of itself".to_owned(),
+                ));
+            }
+        }
+
         diesel::update(dsl::category.find(&self.id))
-            .set(dsl::name.eq(&self.name))
+            .set((dsl::name.eq(&self.name), dsl::parent.eq(&self.parent)))
             .execute(conn)?;
 
         Ok(())
@@ -128,6 +171,7 @@ impl Category {
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(category_id = %self.id))]
     pub fn update_prices(
         &mut self,
         conn: &DbConnection,
@@ -171,12 +215,58 @@ impl Category {
         Ok(())
     }
 
+    /// Load the ancestors for this category
+    ///
+    /// This updates the `ancestors` vec and the `current_price`
+    fn load_ancestors(&mut self, conn: &DbConnection) -> ServiceResult<()> {
+        use crate::core::schema::category::dsl;
+
+        self.ancestors.clear();
+
+        let mut next = self.parent;
+        while let Some(parent_id) = next {
+            // Stop on broken hierarchies instead of looping forever
+            if parent_id == self.id || self.ancestors.iter().any(|a| a.id == parent_id) {
+                break;
+            }
+
+            let mut results = dsl::category
+                .filter(dsl::id.eq(&parent_id))
+                .load::<Category>(conn)?;
+
+            let mut parent = match results.pop() {
+                Some(parent) => parent,
+                None => break,
+            };
+            parent.load_prices(conn)?;
+
+            next = parent.parent;
+            self.ancestors.push(parent);
+        }
+
+        self.calc_current_price();
+
+        Ok(())
+    }
+
     /// Calculate the `current_price` based on the `prices` vec
     fn calc_current_price(&mut self) {
         self.current_price = self.get_price_at(&Local::now().naive_local());
     }
 
+    /// Get the price of this category at the given datetime
+    ///
+    /// If this category has no valid price, the price of the nearest ancestor is used.
     pub fn get_price_at(&self, datetime: &NaiveDateTime) -> Option<Money> {
+        self.get_own_price_at(datetime).or_else(|| {
+            self.ancestors
+                .iter()
+                .filter_map(|a| a.get_own_price_at(datetime))
+                .next()
+        })
+    }
+
+    fn get_own_price_at(&self, datetime: &NaiveDateTime) -> Option<Money> {
         let current = self
             .prices
             .iter()
@@ -190,6 +280,7 @@ impl Category {
     }
 
     /// List all categorys
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn all(conn: &DbConnection) -> ServiceResult<Vec<Category>> {
         use crate::core::schema::category::dsl;
 
@@ -199,12 +290,74 @@ impl Category {
 
         for p in &mut results {
             p.load_prices(conn)?;
+            p.load_ancestors(conn)?;
         }
 
         Ok(results)
     }
 
+    /// List all categories as a tree
+    ///
+    /// Categories with a missing parent are listed as top level categories. Broken hierarchies of
+    /// older data are listed from the first category of each cycle, every category is listed once.
+    pub fn tree(conn: &DbConnection) -> ServiceResult<Vec<CategoryNode>> {
+        let categories = Category::all(conn)?;
+        let parents: HashMap<Uuid, Option<Uuid>> =
+            categories.iter().map(|c| (c.id, c.parent)).collect();
+
+        // Categories below a cycle are listed as children of the cycle
+        let is_top_level = |category: &Category| match category.parent {
+            None => true,
+            Some(parent_id) if !parents.contains_key(&parent_id) => true,
+            Some(_) => {
+                let mut seen = HashSet::new();
+                let mut next = category.parent;
+                while let Some(parent_id) = next {
+                    if parent_id == category.id {
+                        return true;
+                    }
+                    if !seen.insert(parent_id) {
+                        return false;
+                    }
+                    next = parents.get(&parent_id).copied().flatten();
+                }
+                false
+            }
+        };
+
+        fn children_of(
+            categories: &[Category],
+            parent_id: Uuid,
+            visited: &mut HashSet<Uuid>,
+        ) -> Vec<CategoryNode> {
+            let mut children = Vec::new();
+            for c in categories {
+                if c.parent == Some(parent_id) && c.id != parent_id && visited.insert(c.id) {
+                    children.push(CategoryNode {
+                        category: c.clone(),
+                        children: children_of(categories, c.id, visited),
+                    });
+                }
+            }
+            children
+        }
+
+        let mut visited = HashSet::new();
+        let mut tree = Vec::new();
+        for c in categories.iter().filter(|c| is_top_level(c)) {
+            if visited.insert(c.id) {
+                tree.push(CategoryNode {
+                    category: c.clone(),
+                    children: children_of(&categories, c.id, &mut visited),
+                });
+            }
+        }
+
+        Ok(tree)
+    }
+
     /// Get a category by the `id`
+    #[tracing::instrument(level = "debug", skip_all, fields(category_id = %id))]
     pub fn get(conn: &DbConnection, id: &Uuid) -> ServiceResult<Category> {
         use crate::core::schema::category::dsl;
 
@@ -215,7 +368,146 @@ impl Category {
         let mut category = results.pop().ok_or_else(|| ServiceError::NotFound)?;
 
         category.load_prices(conn)?;
+        category.load_ancestors(conn)?;
 
         Ok(category)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{test_connection, Product};
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(year, month, day).and_hms(0, 0, 0)
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_price_fallback() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut drinks = Category::create(&conn, "Drinks")?;
+        drinks.add_price(&conn, date(2020, 1, 1), 100)?;
+
+        let mut soft_drinks = Category::create(&conn, "Soft drinks")?;
+        soft_drinks.parent = Some(drinks.id);
+        soft_drinks.update(&conn)?;
+
+        let mut mate = Category::create(&conn, "Mate")?;
+        mate.parent = Some(soft_drinks.id);
+        mate.update(&conn)?;
+        mate.add_price(&conn, date(2021, 1, 1), 150)?;
+
+        let mate = Category::get(&conn, &mate.id)?;
+        assert_eq!(
+            mate.ancestors.iter().map(|a| a.id).collect::<Vec<_>>(),
+            vec![soft_drinks.id, drinks.id]
+        );
+        // Before its own price the nearest ancestor with a price is used
+        assert_eq!(mate.get_price_at(&date(2019, 6, 1)), None);
+        assert_eq!(mate.get_price_at(&date(2020, 6, 1)), Some(100));
+        assert_eq!(mate.get_price_at(&date(2021, 6, 1)), Some(150));
+
+        let soft_drinks = Category::get(&conn, &soft_drinks.id)?;
+        assert_eq!(soft_drinks.get_price_at(&date(2021, 6, 1)), Some(100));
+
+        // Products without own prices use the price of their category
+        let product = Product::create(&conn, "Club Mate", Some(mate.clone()))?;
+        assert_eq!(product.get_price_at(&date(2020, 6, 1)), Some(100));
+        assert_eq!(product.get_price_at(&date(2021, 6, 1)), Some(150));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_cycle() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let drinks = Category::create(&conn, "Drinks")?;
+        let mut soft_drinks = Category::create(&conn, "Soft drinks")?;
+        soft_drinks.parent = Some(drinks.id);
+        soft_drinks.update(&conn)?;
+        let mut mate = Category::create(&conn, "Mate")?;
+        mate.parent = Some(soft_drinks.id);
+        mate.update(&conn)?;
+
+        let mut drinks = Category::get(&conn, &drinks.id)?;
+        drinks.parent = Some(drinks.id);
+        assert!(matches!(
+            drinks.update(&conn),
+            Err(ServiceError::InvalidFields(_))
+        ));
+        drinks.parent = Some(mate.id);
+        assert!(matches!(
+            drinks.update(&conn),
+            Err(ServiceError::InvalidFields(_))
+        ));
+        assert_eq!(Category::get(&conn, &drinks.id)?.parent, None);
+
+        // Broken hierarchies of older data do not loop forever
+        {
+            use crate::core::schema::category::dsl;
+            diesel::update(dsl::category.find(&drinks.id))
+                .set(dsl::parent.eq(&mate.id))
+                .execute(&conn)?;
+        }
+        let mate = Category::get(&conn, &mate.id)?;
+        assert_eq!(mate.ancestors.len(), 2);
+
+        Ok(())
+    }
+
+    fn names(nodes: &[CategoryNode]) -> Vec<String> {
+        nodes
+            .iter()
+            .map(|n| format!("{}{:?}", n.category.name, names(&n.children)))
+            .collect()
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_tree() -> ServiceResult<()> {
+        let conn = test_connection();
+        use crate::core::schema::category::dsl;
+
+        let drinks = Category::create(&conn, "Drinks")?;
+        let mut mate = Category::create(&conn, "Mate")?;
+        mate.parent = Some(drinks.id);
+        mate.update(&conn)?;
+        let food = Category::create(&conn, "Food")?;
+        let sweets = Category::create(&conn, "Sweets")?;
+        let mut chocolate = Category::create(&conn, "Chocolate")?;
+        chocolate.parent = Some(sweets.id);
+        chocolate.update(&conn)?;
+        let mut bars = Category::create(&conn, "Bars")?;
+        bars.parent = Some(chocolate.id);
+        bars.update(&conn)?;
+
+        // Broken hierarchies of older data: a self parented category, a cycle with a child and a
+        // missing parent
+        diesel::update(dsl::category.find(&food.id))
+            .set(dsl::parent.eq(&food.id))
+            .execute(&conn)?;
+        diesel::update(dsl::category.find(&sweets.id))
+            .set(dsl::parent.eq(&chocolate.id))
+            .execute(&conn)?;
+        diesel::update(dsl::category.find(&drinks.id))
+            .set(dsl::parent.eq(&generate_uuid()))
+            .execute(&conn)?;
+
+        assert_eq!(
+            names(&Category::tree(&conn)?),
+            vec![
+                r#"Chocolate["Bars[]", "Sweets[]"]"#,
+                r#"Drinks["Mate[]"]"#,
+                "Food[]",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,453 @@
+//! Typed configuration of the server.
+//!
+//! The configuration is loaded once at startup from a toml file. Every setting can be overridden
+//! by an environment variable with the upper case name of the setting, eg. `PORT` for `port`.
+//! All settings are validated before the server starts.
+use lettre::EmailAddress;
+use once_cell::sync::OnceCell;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+use url::Url;
+
+use crate::core::{ServiceError, ServiceResult};
+
+/// Default config file, it is optional
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Settings that are never printed
+const SECRET_SETTINGS: &[&str] = &[
+    "database_url",
+    "password_salt",
+    "cookie_encryption_key",
+    "cron_secret",
+    "metrics_token",
+    "s3_secret_key",
+    "mail_password",
+];
+
+const INSECURE_SECRET: &str = "01230123012301230123012301230123";
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// Parse the value of an environment variable
+fn parse_env<T: FromStr>(name: &str, value: &str) -> ServiceResult<T> {
+    value.parse::<T>().map_err(|_| {
+        ServiceError::InternalServerError(
+            "Invalid configuration",
+            format!("'{}' is not a valid value for {}", value, name),
+        )
+    })
+}
+
+macro_rules! config {
+    ($($(#[$doc:meta])* $field:ident: $type:ty = $default:expr, $env:literal;)*) => {
+        /// Configuration of the server
+        #[derive(Debug, Clone, Deserialize)]
+        #[serde(default, deny_unknown_fields)]
+        pub struct Config {
+            $($(#[$doc])* pub $field: $type,)*
+        }
+
+        impl Default for Config {
+            fn default() -> Self {
+                Config {
+                    $($field: $default,)*
+                }
+            }
+        }
+
+        impl Config {
+            /// Override all settings with their environment variables
+            fn apply_env(&mut self) -> ServiceResult<()> {
+                $(
+                    if let Ok(value) = std::env::var($env) {
+                        self.$field = parse_env($env, &value)?;
+                    }
+                )*
+                Ok(())
+            }
+
+            /// List all settings with their printable values
+            fn entries(&self) -> Vec<(&'static str, String)> {
+                vec![$((stringify!($field), self.$field.to_string()),)*]
+            }
+        }
+    };
+}
+
+config! {
+    /// Allow insecure settings for local development.
+    dev_mode: bool = false, "DEV_MODE";
+
+    /// Host name of the application. The web server only listens to request with a matching host name.
+    host: String = "localhost".to_owned(), "HOST";
+    /// The application port.
+    port: u16 = 8080, "PORT";
+    /// Domain string for cookies. Cookies will be valid for this domain name.
+    domain: String = "localhost".to_owned(), "DOMAIN";
+    /// Base url for server generated urls. This is the base reference for eg. password invitation links.
+    base_url: String = "http://localhost:8080".to_owned(), "BASE_URL";
+
+    /// PEM file with the certificate chain of the server. If set, the server only accepts https.
+    tls_cert_file: String = String::new(), "TLS_CERT_FILE";
+    /// PEM file with the private key of the server certificate.
+    tls_key_file: String = String::new(), "TLS_KEY_FILE";
+    /// Port of a plain http listener that redirects all requests to `base_url`, requires `tls_cert_file`. Disabled if 0.
+    http_redirect_port: u16 = 0, "HTTP_REDIRECT_PORT";
+    /// Client certificates for the terminal routes: `none`, `tls` to verify them in the tls handshake
+    /// of this server or `header` to trust the subject forwarded by a reverse proxy.
+    client_cert_mode: String = "none".to_owned(), "CLIENT_CERT_MODE";
+    /// PEM file with the CA certificates that issue the client certificates, required for `tls`.
+    tls_client_ca_file: String = String::new(), "TLS_CLIENT_CA_FILE";
+    /// Header with the verified client certificate subject, set by the reverse proxy in `header` mode.
+    client_cert_header: String = "X-Client-Cert-Subject".to_owned(), "CLIENT_CERT_HEADER";
+    /// Comma separated ip addresses of the reverse proxies that may set the `client_cert_header` and `X-Forwarded-For`.
+    trusted_proxies: String = String::new(), "TRUSTED_PROXIES";
+
+    /// Database connection string, or the path of the database file for the `sqlite` feature.
+    database_url: String = String::new(), "DATABASE_URL";
+    /// Apply pending database migrations when the server starts.
+    auto_migrate: bool = true, "AUTO_MIGRATE";
+
+    /// Salt for password hashing.
+    password_salt: String = INSECURE_SECRET.to_owned(), "PASSWORD_SALT";
+    /// Encryption key for authentication cookies, at least 32 bytes.
+    cookie_encryption_key: String = INSECURE_SECRET.to_owned(), "COOKIE_ENCRYPTION_KEY";
+    /// Header secret to access cron urls.
+    cron_secret: String = String::new(), "CRON_SECRET";
+    /// Bearer token to access the prometheus metrics at `/metrics`. The endpoint is disabled if this is empty.
+    metrics_token: String = String::new(), "METRICS_TOKEN";
+
+    /// Failed password logins of a client ip within `lockout_minutes` until the ip is locked out. 0 disables the limit.
+    max_failures_per_ip: u32 = 20, "MAX_FAILURES_PER_IP";
+    /// Failed password logins of an account within `lockout_minutes` until the account is locked out. 0 disables the limit.
+    max_failures_per_account: u32 = 5, "MAX_FAILURES_PER_ACCOUNT";
+    /// Unknown barcodes and nfc cards of a client ip within `lockout_minutes` until the ip is locked out of `/api/v1/identify`. 0 disables the limit.
+    max_identify_failures_per_ip: u32 = 50, "MAX_IDENTIFY_FAILURES_PER_IP";
+    /// Time window in which failures are counted, a lockout ends this long after the last failure.
+    lockout_minutes: u32 = 15, "LOCKOUT_MINUTES";
+    /// Failed authentications are deleted after this many days, they are kept for the audit until then.
+    auth_failure_retention_days: u32 = 90, "AUTH_FAILURE_RETENTION_DAYS";
+    /// Require a second factor (TOTP) for admin accounts, admins without one enroll at their next login.
+    admin_totp_required: bool = false, "ADMIN_TOTP_REQUIRED";
+
+    /// Log filter in the `tracing_subscriber::EnvFilter` syntax, eg. `info` or `info,ascii_pay_server=debug`.
+    log_filter: String = "info".to_owned(), "RUST_LOG";
+    /// Log format, either `json` for structured logs or `text` for human readable logs.
+    log_format: String = "json".to_owned(), "LOG_FORMAT";
+
+    /// Server path to store uploaded images.
+    image_path: String = "img/".to_owned(), "IMAGE_PATH";
+    /// Maximal size of uploaded images in bytes.
+    image_max_size: usize = 5 * 1024 * 1024, "IMAGE_MAX_SIZE";
+    /// Storage backend for uploaded images, either `local` (stored in `image_path`) or `s3`.
+    image_storage: String = "local".to_owned(), "IMAGE_STORAGE";
+    /// Endpoint of the S3-compatible image storage without a path, eg. `http://localhost:9000` for a
+    /// local MinIO.
+    s3_endpoint: String = String::new(), "S3_ENDPOINT";
+    /// Bucket of the S3-compatible image storage.
+    s3_bucket: String = String::new(), "S3_BUCKET";
+    /// Region of the S3-compatible image storage.
+    s3_region: String = "us-east-1".to_owned(), "S3_REGION";
+    /// Access key of the S3-compatible image storage.
+    s3_access_key: String = String::new(), "S3_ACCESS_KEY";
+    /// Secret key of the S3-compatible image storage.
+    s3_secret_key: String = String::new(), "S3_SECRET_KEY";
+
+    /// Source address for mails, eg. `payments@ascii.coffee`.
+    mail_sender: String = String::new(), "MAIL_SENDER";
+    /// Sender name for mails, eg. `Ascii Pay Service`.
+    mail_sender_name: String = String::new(), "MAIL_SENDER_NAME";
+    /// Mail server url. Server can be set to test mode if url ends with `.local` eg: `mail.example.local`.
+    /// Otherwise the url is used to verify tls certificates.
+    mail_url: String = String::new(), "MAIL_URL";
+    /// Login username for mail server.
+    mail_user: String = String::new(), "MAIL_USER";
+    /// Login password for mail server.
+    mail_password: String = String::new(), "MAIL_PASSWORD";
+}
+
+impl Config {
+    /// Load the configuration from the given toml file and the environment
+    ///
+    /// Without a file, `config.toml` is used if it exists.
+    pub fn load(file: Option<&str>) -> ServiceResult<Config> {
+        let file = file
+            .map(|f| f.to_owned())
+            .or_else(|| std::env::var("CONFIG_FILE").ok())
+            .or_else(|| {
+                if Path::new(DEFAULT_CONFIG_FILE).exists() {
+                    Some(DEFAULT_CONFIG_FILE.to_owned())
+                } else {
+                    None
+                }
+            });
+
+        let mut config = match file {
+            Some(file) => {
+                let data = std::fs::read_to_string(&file).map_err(|e| {
+                    ServiceError::InternalServerError(
+                        "Invalid configuration",
+                        format!("Cannot read '{}': {}", file, e),
+                    )
+                })?;
+                toml::from_str(&data).map_err(|e| {
+                    ServiceError::InternalServerError(
+                        "Invalid configuration",
+                        format!("Cannot parse '{}': {}", file, e),
+                    )
+                })?
+            }
+            None => Config::default(),
+        };
+
+        config.apply_env()?;
+
+        Ok(config)
+    }
+
+    /// Check all settings, returns the warnings of a valid configuration
+    ///
+    /// Insecure settings are errors unless `dev_mode` is enabled.
+    pub fn validate(&self) -> ServiceResult<Vec<String>> {
+        let mut errors = Vec::new();
+        let mut insecure = Vec::new();
+        let mut warnings = Vec::new();
+
+        let mut required = |name: &str, value: &str| {
+            if value.trim().is_empty() {
+                errors.push(format!("{} must be set", name));
+            }
+        };
+        required("database_url", &self.database_url);
+        required("host", &self.host);
+        required("domain", &self.domain);
+        required("cron_secret", &self.cron_secret);
+        required("mail_sender_name", &self.mail_sender_name);
+        required("mail_url", &self.mail_url);
+        if !self.mail_url.ends_with(".local") {
+            required("mail_user", &self.mail_user);
+            required("mail_password", &self.mail_password);
+        }
+        if self.image_storage == "s3" {
+            required("s3_endpoint", &self.s3_endpoint);
+            required("s3_bucket", &self.s3_bucket);
+            required("s3_region", &self.s3_region);
+            required("s3_access_key", &self.s3_access_key);
+            required("s3_secret_key", &self.s3_secret_key);
+        }
+        if self.client_cert_mode == "tls" {
+            required("tls_cert_file", &self.tls_cert_file);
+            required("tls_key_file", &self.tls_key_file);
+            required("tls_client_ca_file", &self.tls_client_ca_file);
+        }
+        if self.client_cert_mode == "header" {
+            required("client_cert_header", &self.client_cert_header);
+            required("trusted_proxies", &self.trusted_proxies);
+        }
+
+        if self.port == 0 {
+            errors.push("port must not be 0".to_owned());
+        }
+        if !self.base_url.starts_with("http://") && !self.base_url.starts_with("https://") {
+            errors.push(format!(
+                "base_url '{}' must start with http:// or https://",
+                self.base_url
+            ));
+        }
+        if EmailAddress::new(self.mail_sender.clone()).is_err() {
+            errors.push(format!(
+                "mail_sender '{}' is not a valid mail address",
+                self.mail_sender
+            ));
+        }
+        if self.lockout_minutes == 0 {
+            errors.push("lockout_minutes must not be 0".to_owned());
+        }
+        if u64::from(self.auth_failure_retention_days) * 24 * 60 < u64::from(self.lockout_minutes) {
+            errors.push("auth_failure_retention_days must cover lockout_minutes".to_owned());
+        }
+        if self.image_max_size == 0 {
+            errors.push("image_max_size must not be 0".to_owned());
+        }
+        if self.image_storage == "s3" && !self.s3_endpoint.is_empty() {
+            match Url::parse(&self.s3_endpoint) {
+                Ok(url) if url.host_str().is_none() => {
+                    errors.push(format!("s3_endpoint '{}' has no host", self.s3_endpoint))
+                }
+                Ok(url) if url.path() != "/" => errors.push(format!(
+                    "s3_endpoint '{}' must not have a path",
+                    self.s3_endpoint
+                )),
+                Ok(_) => {}
+                Err(e) => errors.push(format!(
+                    "s3_endpoint '{}' is not a valid url: {}",
+                    self.s3_endpoint, e
+                )),
+            }
+        }
+        if self.image_storage != "local" && self.image_storage != "s3" {
+            errors.push(format!(
+                "image_storage '{}' must be either 'local' or 's3'",
+                self.image_storage
+            ));
+        }
+        if !["none", "tls", "header"].contains(&self.client_cert_mode.as_str()) {
+            errors.push(format!(
+                "client_cert_mode '{}' must be either 'none', 'tls' or 'header'",
+                self.client_cert_mode
+            ));
+        }
+        if self.tls_cert_file.is_empty() != self.tls_key_file.is_empty() {
+            errors.push("tls_cert_file and tls_key_file must be set together".to_owned());
+        }
+        if self.http_redirect_port != 0 {
+            if !self.tls_enabled() {
+                errors.push("http_redirect_port requires tls_cert_file".to_owned());
+            }
+            if !self.base_url.starts_with("https://") {
+                errors.push("http_redirect_port requires a https:// base_url".to_owned());
+            }
+            if self.http_redirect_port == self.port {
+                errors.push("http_redirect_port must differ from port".to_owned());
+            }
+        }
+        for file in &[
+            &self.tls_cert_file,
+            &self.tls_key_file,
+            &self.tls_client_ca_file,
+        ] {
+            if !file.is_empty() && !Path::new(file).is_file() {
+                errors.push(format!("tls file '{}' does not exist", file));
+            }
+        }
+        for proxy in self.trusted_proxies.split(',').map(str::trim) {
+            if !proxy.is_empty() && proxy.parse::<IpAddr>().is_err() {
+                errors.push(format!("trusted_proxies '{}' is not an ip address", proxy));
+            }
+        }
+        if self.log_format != "json" && self.log_format != "text" {
+            errors.push(format!(
+                "log_format '{}' must be either 'json' or 'text'",
+                self.log_format
+            ));
+        }
+        if self.cookie_encryption_key.len() < 32 {
+            errors.push("cookie_encryption_key must have at least 32 bytes".to_owned());
+        }
+
+        if self.password_salt == INSECURE_SECRET || self.password_salt.len() < 16 {
+            insecure.push("password_salt uses the default value or is shorter than 16 bytes");
+        }
+        if self.cookie_encryption_key == INSECURE_SECRET {
+            insecure.push("cookie_encryption_key uses the default value");
+        }
+
+        if self.dev_mode {
+            warnings.extend(insecure.into_iter().map(|i| format!("{} (dev mode)", i)));
+        } else {
+            errors.extend(
+                insecure
+                    .into_iter()
+                    .map(|i| format!("{}, this is only allowed in dev_mode", i)),
+            );
+        }
+
+        if self.mail_url.ends_with(".local") {
+            warnings.push("mail_url ends with '.local', mails are only logged".to_owned());
+        }
+        if !self.secure_cookies() && !self.dev_mode {
+            warnings.push("base_url does not use https, cookies are sent unencrypted".to_owned());
+        }
+        if self.tls_enabled() && self.base_url.starts_with("http://") {
+            warnings.push("tls is enabled but base_url does not use https".to_owned());
+        }
+
+        if errors.is_empty() {
+            Ok(warnings)
+        } else {
+            Err(ServiceError::InternalServerError(
+                "Invalid configuration",
+                errors.join("\n"),
+            ))
+        }
+    }
+
+    /// Whether the server terminates tls itself
+    pub fn tls_enabled(&self) -> bool {
+        !self.tls_cert_file.is_empty()
+    }
+
+    /// Whether cookies are restricted to https, either served by this server or by a proxy
+    pub fn secure_cookies(&self) -> bool {
+        self.tls_enabled() || self.base_url.starts_with("https://")
+    }
+
+    /// Whether the terminal routes require a client certificate
+    pub fn client_cert_required(&self) -> bool {
+        self.client_cert_mode != "none"
+    }
+
+    /// Ip addresses of the `trusted_proxies`, invalid entries are skipped
+    pub fn trusted_proxies(&self) -> Vec<IpAddr> {
+        self.trusted_proxies
+            .split(',')
+            .filter_map(|proxy| proxy.trim().parse().ok())
+            .collect()
+    }
+
+    /// Printable summary of all settings, secrets are hidden
+    pub fn summary(&self) -> String {
+        let entries = self.entries();
+        let width = entries
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        entries
+            .into_iter()
+            .map(|(name, value)| {
+                let value = if !SECRET_SETTINGS.contains(&name) {
+                    value
+                } else if value.is_empty() {
+                    "(not set)".to_owned()
+                } else {
+                    "(set)".to_owned()
+                };
+                format!("{:<width$} = {}", name, value, width = width)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+/// Store the validated configuration, this can only be done once
+pub fn init(config: Config) -> ServiceResult<()> {
+    CONFIG.set(config).map_err(|_| {
+        ServiceError::InternalServerError(
+            "Invalid configuration",
+            "The configuration was already loaded".to_owned(),
+        )
+    })
+}
+
+/// Load the default development configuration for tests, can be called multiple times
+#[cfg(test)]
+pub fn init_test() {
+    CONFIG.get_or_init(|| Config {
+        dev_mode: true,
+        ..Config::default()
+    });
+}
+
+/// Get the configuration
+///
+/// # Panics
+/// If the configuration was not loaded with `init` at startup.
+pub fn get() -> &'static Config {
+    CONFIG
+        .get()
+        .expect("The configuration has to be loaded at startup")
+}
@@ -1,8 +1,12 @@
-use actix_web::http::header::ToStrError;
-use actix_web::{error::ResponseError, Error as ActixError, HttpResponse};
+use actix_web::error::{BlockingError, ResponseError};
+use actix_web::http::header::{self, ToStrError};
+use actix_web::http::StatusCode;
+use actix_web::{Error as ActixError, HttpResponse};
 use derive_more::Display;
 use lettre::smtp::error::Error as LettreError;
 
+use crate::core::config;
+
 pub const AUTH_COOKIE_NAME: &str = "auth";
 
 /// Represent errors in the application
@@ -22,17 +26,190 @@ pub enum ServiceError {
     #[display(fmt = "Unauthorized")]
     Unauthorized,
 
+    #[display(fmt = "A code of the authenticator app or a recovery code is required")]
+    SecondFactorRequired,
+
     #[display(fmt = "You have insufficient privileges to view this site")]
     InsufficientPrivileges,
 
     #[display(fmt = "Error sending mail: {}", _0)]
     MailError(LettreError),
+
+    #[display(fmt = "The database is not available: {}", _0)]
+    DatabaseUnavailable(String),
+
+    #[display(fmt = "The credit of the account is not sufficient for this transaction")]
+    InsufficientCredit,
+
+    #[display(fmt = "The payment token is invalid or was already used")]
+    InvalidToken,
+
+    #[display(fmt = "The invitation link is invalid or expired")]
+    InvalidInvitation,
+
+    #[display(fmt = "Unsupported Media Type: '{}'\n{}", _0, _1)]
+    UnsupportedMediaType(&'static str, String),
+
+    #[display(fmt = "Invalid fields: {:?}", _0)]
+    InvalidFields(Vec<FieldError>),
+
+    #[display(fmt = "A registered terminal is required")]
+    TerminalRequired,
+
+    #[display(fmt = "A client certificate of a registered terminal is required")]
+    ClientCertRequired,
+
+    #[display(fmt = "This operation is not supported")]
+    NotSupported,
+
+    #[display(fmt = "Too many failed attempts, try again in {} seconds", _0)]
+    TooManyRequests(i64),
+}
+
+/// Stable, machine readable code of an error response
+///
+/// Clients should only depend on the code, the message may change at any time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request is malformed, eg. invalid json or an invalid id
+    BadRequest,
+    /// Some fields of the request have invalid values, see `fields`
+    InvalidFields,
+    NotFound,
+    /// Not logged in or the authentication failed
+    Unauthorized,
+    /// The password is correct but the account needs a second factor, send it as `code`
+    SecondFactorRequired,
+    /// The logged in account is not allowed to do this
+    InsufficientPrivileges,
+    TerminalRequired,
+    /// The request needs a client certificate, see `client_cert_mode`
+    ClientCertRequired,
+    InsufficientCredit,
+    InvalidToken,
+    InvalidInvitation,
+    UnsupportedMediaType,
+    NotSupported,
+    /// Too many failed attempts of the client ip or account, retry after the `Retry-After` header
+    TooManyRequests,
+    /// The database is not reachable, the request can be retried later
+    DatabaseUnavailable,
+    MailError,
+    InternalError,
+}
+
+impl ErrorCode {
+    /// All error codes, eg. for the api documentation
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::BadRequest,
+        ErrorCode::InvalidFields,
+        ErrorCode::NotFound,
+        ErrorCode::Unauthorized,
+        ErrorCode::SecondFactorRequired,
+        ErrorCode::InsufficientPrivileges,
+        ErrorCode::TerminalRequired,
+        ErrorCode::ClientCertRequired,
+        ErrorCode::InsufficientCredit,
+        ErrorCode::InvalidToken,
+        ErrorCode::InvalidInvitation,
+        ErrorCode::UnsupportedMediaType,
+        ErrorCode::NotSupported,
+        ErrorCode::TooManyRequests,
+        ErrorCode::DatabaseUnavailable,
+        ErrorCode::MailError,
+        ErrorCode::InternalError,
+    ];
+}
+
+/// Invalid value of a single request field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Json body of all error responses
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Additional information, omitted for internal errors outside of dev mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
 }
 
 impl ServiceError {
     pub fn actix(self) -> ActixError {
         self.into()
     }
+
+    /// Create an error for a single invalid field
+    pub fn invalid_field(field: &str, message: String) -> ServiceError {
+        ServiceError::InvalidFields(vec![FieldError {
+            field: field.to_owned(),
+            message,
+        }])
+    }
+
+    /// Get the stable error code
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ServiceError::BadRequest(_, _) => ErrorCode::BadRequest,
+            ServiceError::InternalServerError(_, _) => ErrorCode::InternalError,
+            ServiceError::NotFound => ErrorCode::NotFound,
+            ServiceError::Unauthorized => ErrorCode::Unauthorized,
+            ServiceError::SecondFactorRequired => ErrorCode::SecondFactorRequired,
+            ServiceError::InsufficientPrivileges => ErrorCode::InsufficientPrivileges,
+            ServiceError::MailError(_) => ErrorCode::MailError,
+            ServiceError::DatabaseUnavailable(_) => ErrorCode::DatabaseUnavailable,
+            ServiceError::InsufficientCredit => ErrorCode::InsufficientCredit,
+            ServiceError::InvalidToken => ErrorCode::InvalidToken,
+            ServiceError::InvalidInvitation => ErrorCode::InvalidInvitation,
+            ServiceError::UnsupportedMediaType(_, _) => ErrorCode::UnsupportedMediaType,
+            ServiceError::InvalidFields(_) => ErrorCode::InvalidFields,
+            ServiceError::TerminalRequired => ErrorCode::TerminalRequired,
+            ServiceError::ClientCertRequired => ErrorCode::ClientCertRequired,
+            ServiceError::NotSupported => ErrorCode::NotSupported,
+            ServiceError::TooManyRequests(_) => ErrorCode::TooManyRequests,
+        }
+    }
+
+    /// Create the json body of the error response
+    pub fn to_response(&self) -> ErrorResponse {
+        let (message, detail) = match self {
+            ServiceError::BadRequest(title, detail)
+            | ServiceError::InternalServerError(title, detail)
+            | ServiceError::UnsupportedMediaType(title, detail) => {
+                ((*title).to_owned(), Some(detail.clone()))
+            }
+            ServiceError::MailError(e) => (
+                "An error occured when trying to send an email".to_owned(),
+                Some(format!("{}", e)),
+            ),
+            ServiceError::DatabaseUnavailable(detail) => (
+                "The database is not available, please try again later".to_owned(),
+                Some(detail.clone()),
+            ),
+            ServiceError::InvalidFields(_) => ("Some fields have invalid values".to_owned(), None),
+            e => (format!("{}", e), None),
+        };
+
+        // Internal details may contain sensitive data like sql queries
+        let show_detail = self.status_code().is_client_error() || config::get().dev_mode;
+
+        ErrorResponse {
+            code: self.code(),
+            message,
+            detail: detail.filter(|d| show_detail && !d.is_empty()),
+            fields: match self {
+                ServiceError::InvalidFields(fields) => fields.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
 }
 
 /// Helper for `ServiceError` result
@@ -40,7 +217,10 @@ pub type ServiceResult<T> = Result<T, ServiceError>;
 
 impl From<diesel::result::Error> for ServiceError {
     fn from(error: diesel::result::Error) -> Self {
-        ServiceError::InternalServerError("Database error", format!("{}", error))
+        match error {
+            diesel::result::Error::NotFound => ServiceError::NotFound,
+            error => ServiceError::InternalServerError("Database error", format!("{}", error)),
+        }
     }
 }
 
@@ -58,7 +238,19 @@ impl From<handlebars::RenderError> for ServiceError {
 
 impl From<r2d2::Error> for ServiceError {
     fn from(error: r2d2::Error) -> Self {
-        ServiceError::InternalServerError("r2d2 error", format!("{}", error))
+        ServiceError::DatabaseUnavailable(format!("{}", error))
+    }
+}
+
+impl From<BlockingError<ServiceError>> for ServiceError {
+    fn from(error: BlockingError<ServiceError>) -> Self {
+        match error {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => ServiceError::InternalServerError(
+                "Blocking task canceled",
+                "The thread pool of blocking tasks is gone".to_owned(),
+            ),
+        }
     }
 }
 
@@ -68,21 +260,35 @@ impl From<uuid::parser::ParseError> for ServiceError {
     }
 }
 
+/// Json errors of parsed input are caused by the client
+///
+/// Serialization errors are classified like syntax or data errors, but have no position in the
+/// input. They are internal errors like failed reads and writes.
 impl From<serde_json::Error> for ServiceError {
     fn from(error: serde_json::Error) -> Self {
-        ServiceError::InternalServerError("Serialization error", format!("{}", error))
+        if error.is_io() || error.line() == 0 {
+            ServiceError::InternalServerError("Serialization error", format!("{}", error))
+        } else {
+            ServiceError::BadRequest("Invalid json", format!("{}", error))
+        }
+    }
+}
+
+impl From<csv::Error> for ServiceError {
+    fn from(error: csv::Error) -> Self {
+        ServiceError::BadRequest("CSV error", format!("{}", error))
     }
 }
 
 impl From<actix_multipart::MultipartError> for ServiceError {
     fn from(error: actix_multipart::MultipartError) -> Self {
-        ServiceError::InternalServerError("Error in Multipart stream", format!("{}", error))
+        ServiceError::BadRequest("Invalid multipart body", format!("{}", error))
     }
 }
 
 impl From<base64::DecodeError> for ServiceError {
     fn from(error: base64::DecodeError) -> Self {
-        ServiceError::InternalServerError("Base64 error", format!("{}", error))
+        ServiceError::BadRequest("Invalid base64", format!("{}", error))
     }
 }
 
@@ -112,10 +318,7 @@ impl From<LettreError> for ServiceError {
 
 impl From<ToStrError> for ServiceError {
     fn from(error: ToStrError) -> Self {
-        ServiceError::BadRequest(
-            "Request contained invalid CRON_SECRET header value",
-            format!("{}", error),
-        )
+        ServiceError::BadRequest("Invalid header value", format!("{}", error))
     }
 }
 
@@ -142,33 +345,92 @@ impl From<std::option::NoneError> for ServiceError {
 
 /// Transform `ServiceError` to `HttpResponse`
 impl ResponseError for ServiceError {
-    fn error_response(&self) -> HttpResponse {
+    fn status_code(&self) -> StatusCode {
         match self {
-            ServiceError::InternalServerError(ref source, ref cause) => {
-                HttpResponse::InternalServerError().json(json!({
-                    "message": "Internal Server Error, Please try again later",
-                    "source": source,
-                    "cause": cause
-                }))
-            }
-            ServiceError::BadRequest(ref source, ref cause) => {
-                HttpResponse::BadRequest().json(json!({
-                    "message": "Internal Server Error, Please try again later",
-                    "source": source,
-                    "cause": cause
-                }))
-            }
-            ServiceError::NotFound => HttpResponse::NotFound().json("NotFound"),
-            ServiceError::Unauthorized => HttpResponse::Unauthorized().json("Unauthorized"),
-            ServiceError::InsufficientPrivileges => {
-                HttpResponse::Unauthorized().json("Insufficient Privileges")
-            }
-            ServiceError::MailError(ref mail_err) => {
-                HttpResponse::InternalServerError().json(json!({
-                    "message": "An error occured when trying to send an email.",
-                    "cause": format!("{}", mail_err)
-                }))
+            ServiceError::BadRequest(_, _) => StatusCode::BAD_REQUEST,
+            ServiceError::InvalidFields(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ServiceError::NotFound | ServiceError::InvalidInvitation => StatusCode::NOT_FOUND,
+            ServiceError::Unauthorized
+            | ServiceError::SecondFactorRequired
+            | ServiceError::InvalidToken => StatusCode::UNAUTHORIZED,
+            ServiceError::InsufficientPrivileges
+            | ServiceError::TerminalRequired
+            | ServiceError::ClientCertRequired => StatusCode::FORBIDDEN,
+            ServiceError::InsufficientCredit => StatusCode::PAYMENT_REQUIRED,
+            ServiceError::UnsupportedMediaType(_, _) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ServiceError::NotSupported => StatusCode::METHOD_NOT_ALLOWED,
+            ServiceError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ServiceError::DatabaseUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::InternalServerError(_, _) | ServiceError::MailError(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
             }
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        if self.status_code().is_server_error() {
+            error!("{}", self);
+        }
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let ServiceError::TooManyRequests(seconds) = self {
+            response.header(header::RETRY_AFTER, seconds.to_string());
+        }
+        response.json(self.to_response())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_input_is_bad_request() {
+        for json in &["[1, 2", "[1, 2]]", "[1, \"2\"]"] {
+            let error = serde_json::from_str::<Vec<u32>>(json).unwrap_err();
+            assert_eq!(
+                ServiceError::from(error).status_code(),
+                StatusCode::BAD_REQUEST,
+                "{}",
+                json
+            );
+        }
+
+        let base64 = base64::decode("not base64!").unwrap_err();
+        assert_eq!(
+            ServiceError::from(base64).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let multipart = actix_multipart::MultipartError::Boundary;
+        assert_eq!(
+            ServiceError::from(multipart).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    struct Unserializable;
+
+    impl serde::Serialize for Unserializable {
+        fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(serde::ser::Error::custom("not serializable"))
+        }
+    }
+
+    #[test]
+    fn test_serialization_error_is_internal() {
+        // Json object keys have to be strings
+        let mut map = std::collections::HashMap::new();
+        map.insert((1, 2), 3);
+
+        for error in vec![
+            serde_json::to_string(&map).unwrap_err(),
+            serde_json::to_string(&Unserializable).unwrap_err(),
+        ] {
+            assert_eq!(
+                ServiceError::from(error).status_code(),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+        }
+    }
 }
@@ -0,0 +1,105 @@
+//! Readiness checks of the server and its dependencies.
+use diesel::prelude::*;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::core::{migrations, storage, DbConnection, Pool, ServiceError, ServiceResult};
+
+/// Result of a single readiness check
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub ok: bool,
+    /// Reason why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl CheckResult {
+    fn from_result(result: ServiceResult<()>) -> CheckResult {
+        match result {
+            Ok(()) => CheckResult {
+                ok: true,
+                message: None,
+            },
+            Err(ServiceError::BadRequest(title, detail))
+            | Err(ServiceError::InternalServerError(title, detail)) => {
+                if detail.is_empty() {
+                    CheckResult::failed(title.to_owned())
+                } else {
+                    CheckResult::failed(format!("{}: {}", title, detail))
+                }
+            }
+            Err(e) => CheckResult::failed(format!("{}", e)),
+        }
+    }
+
+    fn failed(message: String) -> CheckResult {
+        CheckResult {
+            ok: false,
+            message: Some(message),
+        }
+    }
+}
+
+/// Result of all readiness checks
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: BTreeMap<&'static str, CheckResult>,
+}
+
+impl Readiness {
+    /// Remove the failure reasons, they may contain internal details like hosts or paths
+    pub fn hide_messages(&mut self) {
+        for check in self.checks.values_mut() {
+            check.message = None;
+        }
+    }
+}
+
+/// Check if all migrations of this binary are applied and the database knows no newer migrations
+fn check_migrations(conn: &DbConnection) -> ServiceResult<()> {
+    migrations::status(conn)?.check_up_to_date()
+}
+
+/// Check if the image storage is available, probes must not write to it
+fn check_image_storage() -> ServiceResult<()> {
+    storage::image_storage().check()
+}
+
+/// Run all readiness checks
+///
+/// Checks that need the database fail if no connection is available within two seconds.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn check_readiness(pool: &Pool) -> Readiness {
+    let mut checks = BTreeMap::new();
+
+    match pool.get_timeout(Duration::from_secs(2)) {
+        Ok(conn) => {
+            let database = diesel::sql_query("SELECT 1")
+                .execute(&conn)
+                .map(|_| ())
+                .map_err(ServiceError::from);
+            checks.insert("database", CheckResult::from_result(database));
+            checks.insert(
+                "migrations",
+                CheckResult::from_result(check_migrations(&conn)),
+            );
+        }
+        Err(e) => {
+            let message = format!("No database connection available: {}", e);
+            checks.insert("database", CheckResult::failed(message.clone()));
+            checks.insert("migrations", CheckResult::failed(message));
+        }
+    }
+
+    checks.insert(
+        "image_storage",
+        CheckResult::from_result(check_image_storage()),
+    );
+
+    Readiness {
+        ready: checks.values().all(|c| c.ok),
+        checks,
+    }
+}
@@ -0,0 +1,241 @@
+use image::{DynamicImage, ImageFormat};
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use crate::core::storage::image_storage;
+use crate::core::{config, ServiceError, ServiceResult};
+
+/// Url prefix under which the product images are served
+pub const IMAGE_URL_PREFIX: &str = "/product/image";
+
+/// Thumbnail sizes as name and maximal edge length in pixels
+pub const THUMBNAIL_SIZES: [(&str, u32); 2] = [("small", 128), ("medium", 512)];
+
+/// Supported image types, detected by the magic bytes of the file
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ImageType {
+    Png,
+    Jpeg,
+    Gif,
+    WebP,
+}
+
+impl ImageType {
+    /// Detect the image type by the magic bytes of the file content
+    pub fn detect(data: &[u8]) -> Option<ImageType> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageType::Png)
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageType::Jpeg)
+        } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(ImageType::Gif)
+        } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(ImageType::WebP)
+        } else {
+            None
+        }
+    }
+
+    /// Get the image type by a file extension
+    pub fn from_extension(extension: &str) -> Option<ImageType> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(ImageType::Png),
+            "jpg" | "jpeg" => Some(ImageType::Jpeg),
+            "gif" => Some(ImageType::Gif),
+            "webp" => Some(ImageType::WebP),
+            _ => None,
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::WebP => "image/webp",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageType::Png => "png",
+            ImageType::Jpeg => "jpg",
+            ImageType::Gif => "gif",
+            ImageType::WebP => "webp",
+        }
+    }
+
+    fn format(self) -> ImageFormat {
+        match self {
+            ImageType::Png => ImageFormat::Png,
+            ImageType::Jpeg => ImageFormat::Jpeg,
+            ImageType::Gif => ImageFormat::Gif,
+            ImageType::WebP => ImageFormat::WebP,
+        }
+    }
+}
+
+/// Validate, re-encode and save an uploaded image with all of its thumbnails
+///
+/// Re-encoding the decoded image strips all metadata (eg. exif location data) of the upload.
+/// The thumbnails are saved in the original image type and additionally as WebP.
+/// Returns the file name of the original image.
+pub fn save_image(id: &str, data: &[u8]) -> ServiceResult<String> {
+    if data.len() > config::get().image_max_size {
+        return Err(ServiceError::BadRequest(
+            "Image too large",
+            format!(
+                "The image has {} bytes, but at most {} bytes are allowed",
+                data.len(),
+                config::get().image_max_size
+            ),
+        ));
+    }
+
+    let image_type = ImageType::detect(data).ok_or_else(|| {
+        ServiceError::UnsupportedMediaType(
+            "Unsupported image type",
+            "Only png, jpeg, gif and webp images are supported".to_owned(),
+        )
+    })?;
+
+    let image = image::load_from_memory_with_format(data, image_type.format())
+        .map_err(|e| ServiceError::BadRequest("Invalid image", format!("{}", e)))?;
+
+    let name = format!("{}.{}", id, image_type.extension());
+    write_image(&image, image_type, &name)?;
+
+    for (size_name, size) in THUMBNAIL_SIZES.iter() {
+        let thumbnail = image.thumbnail(*size, *size);
+
+        write_image(
+            &thumbnail,
+            image_type,
+            &format!("{}-{}.{}", id, size_name, image_type.extension()),
+        )?;
+        if image_type != ImageType::WebP {
+            write_image(
+                &thumbnail,
+                ImageType::WebP,
+                &format!("{}-{}.webp", id, size_name),
+            )?;
+        }
+    }
+
+    Ok(name)
+}
+
+/// Remove an image and all of its thumbnails
+pub fn delete_image(name: &str) -> ServiceResult<()> {
+    for file in image_files(name) {
+        image_storage().delete(&file)?;
+    }
+
+    Ok(())
+}
+
+/// Get the urls of an image and all of its thumbnails
+///
+/// The original image is listed as `original`, the thumbnails by their size name,
+/// eg. `small` and `small_webp`.
+pub fn image_urls(name: &str) -> BTreeMap<String, String> {
+    let (id, extension) = split_name(name);
+
+    let mut urls = BTreeMap::new();
+    urls.insert(
+        "original".to_owned(),
+        format!("{}/{}", IMAGE_URL_PREFIX, name),
+    );
+
+    for (size_name, _) in THUMBNAIL_SIZES.iter() {
+        urls.insert(
+            (*size_name).to_owned(),
+            format!("{}/{}-{}.{}", IMAGE_URL_PREFIX, id, size_name, extension),
+        );
+        urls.insert(
+            format!("{}_webp", size_name),
+            format!("{}/{}-{}.webp", IMAGE_URL_PREFIX, id, size_name),
+        );
+    }
+
+    urls
+}
+
+/// List the file names of an image and all of its thumbnails
+pub fn image_files(name: &str) -> Vec<String> {
+    let (id, extension) = split_name(name);
+
+    let mut files = vec![name.to_owned()];
+    for (size_name, _) in THUMBNAIL_SIZES.iter() {
+        files.push(format!("{}-{}.{}", id, size_name, extension));
+        files.push(format!("{}-{}.webp", id, size_name));
+    }
+    files.dedup();
+
+    files
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(index) => (&name[..index], &name[index + 1..]),
+        None => (name, ""),
+    }
+}
+
+fn write_image(image: &DynamicImage, image_type: ImageType, name: &str) -> ServiceResult<()> {
+    // Jpeg does not support an alpha channel and the WebP encoder only supports rgb(a) images
+    let image = match image_type {
+        ImageType::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ImageType::WebP => DynamicImage::ImageRgba8(image.to_rgba8()),
+        _ => image.clone(),
+    };
+
+    let mut buffer = Cursor::new(Vec::new());
+    image
+        .write_to(&mut buffer, image_type.format())
+        .map_err(|e| ServiceError::InternalServerError("Image error", format!("{}", e)))?;
+
+    image_storage().put(name, &buffer.into_inner())
+}
+
+/// Load an image or thumbnail by its file name
+///
+/// Images uploaded before thumbnails were introduced only have the original file,
+/// in this case the original is returned for all thumbnail names.
+/// Returns the file content and its mime type.
+pub fn load_image(name: &str) -> ServiceResult<(Vec<u8>, &'static str)> {
+    let (id, extension) = split_name(name);
+
+    match image_storage().get(name) {
+        Ok(data) => return Ok((data, mime_type(extension))),
+        Err(ServiceError::NotFound) => {}
+        Err(e) => return Err(e),
+    }
+
+    let original_id = THUMBNAIL_SIZES
+        .iter()
+        .filter_map(|(size_name, _)| id.strip_suffix(&format!("-{}", size_name)))
+        .next()
+        .ok_or(ServiceError::NotFound)?;
+
+    for extension in LEGACY_EXTENSIONS.iter() {
+        match image_storage().get(&format!("{}.{}", original_id, extension)) {
+            Ok(data) => return Ok((data, mime_type(extension))),
+            Err(ServiceError::NotFound) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(ServiceError::NotFound)
+}
+
+/// File extensions of images uploaded before the upload validation was introduced
+const LEGACY_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "svg"];
+
+fn mime_type(extension: &str) -> &'static str {
+    match ImageType::from_extension(extension) {
+        Some(image_type) => image_type.mime_type(),
+        None if extension == "svg" => "image/svg+xml",
+        None => "application/octet-stream",
+    }
+}
@@ -0,0 +1,161 @@
+use chrono::NaiveDateTime;
+use std::collections::HashMap;
+
+use crate::core::{Category, CategoryNode, DbConnection, Product, ServiceResult, Uuid};
+
+/// Represent the products of a category as they are shown on a terminal
+#[derive(Debug, Serialize, Clone)]
+pub struct CategoryLayout {
+    /// The category or `None` for all products without a category
+    pub category: Option<Category>,
+    /// Depth of the category in the category tree
+    pub depth: usize,
+    /// Products that should be shown on the first screen of the category
+    pub quick_keys: Vec<Product>,
+    /// All products of the category ordered by their `sort_order` and name
+    pub products: Vec<Product>,
+}
+
+/// Get the terminal layout of all products that are available at the given datetime
+///
+/// The categories are listed in depth-first order of the category tree, categories without
+/// available products are skipped. Products without a category are listed last.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn get_layout_at(
+    conn: &DbConnection,
+    datetime: &NaiveDateTime,
+) -> ServiceResult<Vec<CategoryLayout>> {
+    let mut products: HashMap<Option<Uuid>, Vec<Product>> = HashMap::new();
+    for product in Product::all_available_at(conn, datetime)? {
+        products
+            .entry(product.category.as_ref().map(|c| c.id))
+            .or_insert_with(Vec::new)
+            .push(product);
+    }
+
+    let mut layout = Vec::new();
+    for node in Category::tree(conn)? {
+        add_category_layout(&mut layout, &mut products, node, 0);
+    }
+
+    if let Some(list) = products.remove(&None) {
+        layout.push(create_category_layout(None, 0, list));
+    }
+
+    Ok(layout)
+}
+
+fn add_category_layout(
+    layout: &mut Vec<CategoryLayout>,
+    products: &mut HashMap<Option<Uuid>, Vec<Product>>,
+    node: CategoryNode,
+    depth: usize,
+) {
+    if let Some(list) = products.remove(&Some(node.category.id)) {
+        layout.push(create_category_layout(Some(node.category), depth, list));
+    }
+
+    for child in node.children {
+        add_category_layout(layout, products, child, depth + 1);
+    }
+}
+
+fn create_category_layout(
+    category: Option<Category>,
+    depth: usize,
+    products: Vec<Product>,
+) -> CategoryLayout {
+    let quick_keys = products.iter().filter(|p| p.quick_key).cloned().collect();
+
+    CategoryLayout {
+        category,
+        depth,
+        quick_keys,
+        products,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{test_connection, Availability};
+    use chrono::{NaiveDate, Weekday};
+
+    fn create_product(
+        conn: &DbConnection,
+        name: &str,
+        category: Option<&Category>,
+        sort_order: i32,
+        quick_key: bool,
+    ) -> ServiceResult<Product> {
+        let mut product = Product::create(conn, name, category.cloned())?;
+        product.sort_order = sort_order;
+        product.quick_key = quick_key;
+        product.update(conn)?;
+        Ok(product)
+    }
+
+    fn names(products: &[Product]) -> Vec<&str> {
+        products.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_layout() -> ServiceResult<()> {
+        let conn = test_connection();
+        // 2020-04-06 is a monday
+        let monday = NaiveDate::from_ymd(2020, 4, 6).and_hms(12, 0, 0);
+
+        let drinks = Category::create(&conn, "Drinks")?;
+        let mut mate = Category::create(&conn, "Mate")?;
+        mate.parent = Some(drinks.id);
+        mate.update(&conn)?;
+        let empty = Category::create(&conn, "Empty")?;
+
+        create_product(&conn, "Water", Some(&drinks), 1, false)?;
+        create_product(&conn, "Coffee", Some(&drinks), 0, true)?;
+        create_product(&conn, "Apple juice", Some(&drinks), 1, true)?;
+        create_product(&conn, "Club Mate", Some(&mate), 0, false)?;
+        create_product(&conn, "Voucher", None, 0, false)?;
+
+        let mut weekend = create_product(&conn, "Brunch", Some(&empty), 0, false)?;
+        weekend.add_availability(
+            &conn,
+            Availability {
+                id: crate::core::generate_uuid(),
+                date_start: None,
+                date_end: None,
+                weekdays: vec![Weekday::Sat, Weekday::Sun],
+            },
+        )?;
+
+        let layout = get_layout_at(&conn, &monday)?;
+
+        // Depth-first categories, empty categories skipped, uncategorized products last
+        let categories: Vec<_> = layout
+            .iter()
+            .map(|l| (l.category.as_ref().map(|c| c.name.as_str()), l.depth))
+            .collect();
+        assert_eq!(
+            categories,
+            vec![(Some("Drinks"), 0), (Some("Mate"), 1), (None, 0)]
+        );
+
+        assert_eq!(
+            names(&layout[0].products),
+            vec!["Coffee", "Apple juice", "Water"]
+        );
+        assert_eq!(names(&layout[0].quick_keys), vec!["Coffee", "Apple juice"]);
+        assert_eq!(names(&layout[1].products), vec!["Club Mate"]);
+        assert!(layout[1].quick_keys.is_empty());
+        assert_eq!(names(&layout[2].products), vec!["Voucher"]);
+
+        // The weekend product is listed on saturdays
+        let saturday = NaiveDate::from_ymd(2020, 4, 11).and_hms(12, 0, 0);
+        let layout = get_layout_at(&conn, &saturday)?;
+        assert_eq!(layout.len(), 4);
+        assert_eq!(names(&layout[2].products), vec!["Brunch"]);
+
+        Ok(())
+    }
+}
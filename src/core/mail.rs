@@ -1,5 +1,5 @@
 use crate::core::authentication_password::InvitationLink;
-use crate::core::{env, Account, ServiceError, ServiceResult};
+use crate::core::{config, Account, ServiceError, ServiceResult};
 use lettre::smtp::authentication::Credentials;
 use lettre::{SendableEmail, SmtpClient, Transport};
 use lettre_email::EmailBuilder;
@@ -14,28 +14,31 @@ fn send_standard_mail(account: &Account, subj: &str, message: String) -> Service
         ));
     };
 
+    let config = config::get();
     let email = EmailBuilder::new()
         // Addresses can be specified by the tuple (email, alias)
         .to((mail_address, &account.name))
-        .from((env::MAIL_SENDER.as_str(), env::MAIL_SENDER_NAME.as_str()))
+        .from((
+            config.mail_sender.as_str(),
+            config.mail_sender_name.as_str(),
+        ))
         .subject(subj)
         .text(message)
         .build()?;
 
-    if env::MAIL_SERVER.as_str().ends_with(".local") {
+    if config.mail_url.ends_with(".local") {
         // dump the mail to the log
         let m: SendableEmail = email.into();
-        println!(
-            "{}",
-            m.message_to_string()
-                .expect("This was unrealistic to happen")
+        info!(
+            mail = %m.message_to_string().expect("This was unrealistic to happen"),
+            "Mail server is in test mode, mail was not sent"
         );
     } else {
         // Open a smtp connection
-        let mut mailer = SmtpClient::new_simple(&env::MAIL_SERVER)?
+        let mut mailer = SmtpClient::new_simple(&config.mail_url)?
             .credentials(Credentials::new(
-                env::MAIL_USER.clone(),
-                env::MAIL_PASS.clone(),
+                config.mail_user.clone(),
+                config.mail_password.clone(),
             ))
             .transport();
 
@@ -77,17 +80,18 @@ pub fn send_report_mail(account: &Account, subject: String, report: String) -> S
 // TODO: Needs a route!
 /// Sends a test mail to the given receiver.
 pub fn send_test_mail(receiver: String) -> ServiceResult<()> {
+    let config = config::get();
     let mail = EmailBuilder::new()
         .to(receiver)
-        .from(env::MAIL_SENDER.as_str())
+        .from(config.mail_sender.as_str())
         .subject("[ascii pay] Test Mail")
         .text("This is a test mail to verify that the mailing system works.")
         .build()?;
 
-    let mut mailer = SmtpClient::new_simple(&env::MAIL_SERVER)?
+    let mut mailer = SmtpClient::new_simple(&config.mail_url)?
         .credentials(Credentials::new(
-            env::MAIL_USER.clone(),
-            env::MAIL_PASS.clone(),
+            config.mail_user.clone(),
+            config.mail_password.clone(),
         ))
         .transport();
 
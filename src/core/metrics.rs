@@ -0,0 +1,154 @@
+//! Prometheus metrics of the server.
+//!
+//! Counters and histograms are updated while handling requests, gauges are collected on each scrape.
+use chrono::Local;
+use diesel::prelude::*;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::core::{stats, Pool, ServiceError, ServiceResult};
+
+lazy_static::lazy_static! {
+    static ref REGISTRY: Registry = Registry::new_custom(Some("ascii_pay".to_owned()), None)
+        .expect("metrics registry");
+
+    static ref HTTP_REQUESTS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("http_requests_total", "Number of handled http requests"),
+        &["method", "route", "status"],
+    ));
+
+    static ref HTTP_REQUEST_DURATION: HistogramVec = register(HistogramVec::new(
+        HistogramOpts::new("http_request_duration_seconds", "Latency of http requests"),
+        &["method", "route"],
+    ));
+
+    static ref PAYMENTS: IntCounter = register(IntCounter::new(
+        "payments_total",
+        "Number of executed payments",
+    ));
+
+    static ref FAILED_AUTHENTICATIONS: IntCounterVec = register(IntCounterVec::new(
+        Opts::new("failed_authentications_total", "Number of failed authentications"),
+        &["method"],
+    ));
+
+    static ref DB_CONNECTIONS: IntGauge = register(IntGauge::new(
+        "db_connections",
+        "Number of open database connections",
+    ));
+
+    static ref DB_CONNECTIONS_IDLE: IntGauge = register(IntGauge::new(
+        "db_connections_idle",
+        "Number of idle database connections",
+    ));
+
+    static ref DB_CONNECTIONS_MAX: IntGauge = register(IntGauge::new(
+        "db_connections_max",
+        "Maximal number of database connections",
+    ));
+
+    static ref TOTAL_BALANCE: IntGauge = register(IntGauge::new(
+        "total_balance_cents",
+        "Sum of the credit of all accounts",
+    ));
+
+    static ref ACTIVE_SESSIONS: IntGauge = register(IntGauge::new(
+        "active_sessions",
+        "Number of sessions that are still valid",
+    ));
+}
+
+/// Register a new metric in the registry of this server
+fn register<T>(metric: prometheus::Result<T>) -> T
+where
+    T: prometheus::core::Collector + Clone + 'static,
+{
+    let metric = metric.expect("valid metric");
+    REGISTRY
+        .register(Box::new(metric.clone()))
+        .expect("unique metric");
+    metric
+}
+
+/// Authentication method for failed authentication counts
+pub enum AuthMethod {
+    Barcode,
+    Nfc,
+    Password,
+    Totp,
+}
+
+impl AuthMethod {
+    fn label(&self) -> &'static str {
+        match self {
+            AuthMethod::Barcode => "barcode",
+            AuthMethod::Nfc => "nfc",
+            AuthMethod::Password => "password",
+            AuthMethod::Totp => "totp",
+        }
+    }
+}
+
+/// Count a handled http request
+///
+/// `route` should be the matched route pattern and not the concrete path to keep the number of labels small.
+pub fn observe_request(method: &str, route: &str, status: u16, seconds: f64) {
+    HTTP_REQUESTS
+        .with_label_values(&[method, route, &status.to_string()])
+        .inc();
+    HTTP_REQUEST_DURATION
+        .with_label_values(&[method, route])
+        .observe(seconds);
+}
+
+/// Count an executed payment
+pub fn count_payment() {
+    PAYMENTS.inc();
+}
+
+/// Count a failed authentication with the given method
+pub fn count_failed_authentication(method: AuthMethod) {
+    FAILED_AUTHENTICATIONS
+        .with_label_values(&[method.label()])
+        .inc();
+}
+
+/// Update all gauges and render the metrics in the prometheus text format
+pub fn render(pool: &Pool) -> ServiceResult<String> {
+    use crate::core::schema::session::dsl;
+
+    // Counters are registered on first use, initialize them to export them from the start
+    lazy_static::initialize(&HTTP_REQUESTS);
+    lazy_static::initialize(&HTTP_REQUEST_DURATION);
+    lazy_static::initialize(&PAYMENTS);
+    lazy_static::initialize(&FAILED_AUTHENTICATIONS);
+
+    let state = pool.state();
+    DB_CONNECTIONS.set(i64::from(state.connections));
+    DB_CONNECTIONS_IDLE.set(i64::from(state.idle_connections));
+    DB_CONNECTIONS_MAX.set(i64::from(pool.max_size()));
+
+    let conn = &pool.get()?;
+
+    TOTAL_BALANCE.set(match stats::get_total_balance(conn) {
+        Ok(balance) => i64::from(balance),
+        Err(ServiceError::NotFound) => 0,
+        Err(e) => return Err(e),
+    });
+
+    let active_sessions: i64 = dsl::session
+        .filter(dsl::valid_until.gt(Local::now().naive_local()))
+        .count()
+        .get_result(conn)?;
+    ACTIVE_SESSIONS.set(active_sessions);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .map_err(|e| ServiceError::InternalServerError("Metrics error", format!("{}", e)))?;
+
+    String::from_utf8(buffer)
+        .map_err(|e| ServiceError::InternalServerError("Metrics error", format!("{}", e)))
+}
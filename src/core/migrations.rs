@@ -0,0 +1,113 @@
+//! Database migrations that are embedded into the binary.
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+
+use crate::core::{DbConnection, ServiceError, ServiceResult};
+
+#[cfg(not(feature = "sqlite"))]
+embed_migrations!("migrations");
+
+#[cfg(feature = "sqlite")]
+embed_migrations!("migrations_sqlite");
+
+include!(concat!(env!("OUT_DIR"), "/migrations.rs"));
+
+#[derive(QueryableByName)]
+struct MigrationVersion {
+    #[sql_type = "Text"]
+    version: String,
+}
+
+/// Difference between the migrations of this binary and the applied migrations of the database
+#[derive(Debug)]
+pub struct MigrationStatus {
+    /// Migrations of this binary that are not applied yet
+    pub pending: Vec<&'static str>,
+    /// Applied migrations that are unknown to this binary
+    pub unknown: Vec<String>,
+}
+
+impl MigrationStatus {
+    /// Returns an error if the database schema is newer than this binary
+    pub fn check_not_newer(&self) -> ServiceResult<()> {
+        if self.unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::InternalServerError(
+                "Database schema is newer than this server",
+                self.unknown.join(", "),
+            ))
+        }
+    }
+
+    /// Returns an error if the database schema does not match this binary
+    pub fn check_up_to_date(&self) -> ServiceResult<()> {
+        if !self.pending.is_empty() {
+            return Err(ServiceError::InternalServerError(
+                "Pending migrations",
+                self.pending.join(", "),
+            ));
+        }
+        self.check_not_newer()
+    }
+}
+
+/// Check if the table of applied migrations exists
+fn has_migration_table(conn: &DbConnection) -> ServiceResult<bool> {
+    #[derive(QueryableByName)]
+    struct Exists {
+        #[sql_type = "diesel::sql_types::Bool"]
+        exists: bool,
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    let query = "SELECT EXISTS (SELECT 1 FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = '__diesel_schema_migrations') AS exists";
+    #[cfg(feature = "sqlite")]
+    let query = "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '__diesel_schema_migrations') AS \"exists\"";
+
+    let result = diesel::sql_query(query).get_result::<Exists>(conn)?;
+
+    Ok(result.exists)
+}
+
+/// Compare the migrations of this binary with the applied migrations of the database
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn status(conn: &DbConnection) -> ServiceResult<MigrationStatus> {
+    let applied: Vec<String> = if has_migration_table(conn)? {
+        diesel::sql_query("SELECT version FROM __diesel_schema_migrations ORDER BY version")
+            .load::<MigrationVersion>(conn)?
+            .into_iter()
+            .map(|m| m.version)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(MigrationStatus {
+        pending: MIGRATIONS
+            .iter()
+            .filter(|v| !applied.iter().any(|a| a == *v))
+            .cloned()
+            .collect(),
+        unknown: applied
+            .into_iter()
+            .filter(|a| !MIGRATIONS.contains(&a.as_str()))
+            .collect(),
+    })
+}
+
+/// Apply all pending migrations, returns the applied versions
+///
+/// Refuses to change anything if the database schema is newer than this binary.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn run(conn: &DbConnection) -> ServiceResult<Vec<&'static str>> {
+    let status = status(conn)?;
+    status.check_not_newer()?;
+
+    if !status.pending.is_empty() {
+        embedded_migrations::run(conn)
+            .map_err(|e| ServiceError::InternalServerError("Migration failed", format!("{}", e)))?;
+    }
+
+    Ok(status.pending)
+}
@@ -1,26 +1,42 @@
 #![allow(dead_code)]
 
 mod accounts;
+pub mod auth_failures;
 pub mod authentication_barcode;
 pub mod authentication_nfc;
 pub mod authentication_password;
+pub mod authentication_totp;
+mod availabilities;
+pub mod backup;
+pub mod catalogue;
 mod categories;
-pub mod env;
+pub mod config;
 mod errors;
+pub mod health;
+pub mod images;
+pub mod layouts;
 pub mod mail;
+pub mod metrics;
+pub mod migrations;
 mod prices;
 mod products;
+pub mod reports;
 mod schema;
 mod sessions;
+pub mod sql_types;
 pub mod stats;
+pub mod storage;
+mod terminals;
 pub mod transactions;
 mod utils;
 
 pub use self::accounts::{Account, Permission};
+pub use self::availabilities::*;
 pub use self::categories::*;
 pub use self::errors::*;
 pub use self::prices::*;
 pub use self::products::*;
 pub use self::sessions::Session;
+pub use self::terminals::Terminal;
 pub use self::transactions::Transaction;
 pub use self::utils::*;
@@ -1,7 +1,6 @@
 use chrono::NaiveDateTime;
-use uuid::Uuid;
 
-use crate::core::{Money, DB};
+use crate::core::{Money, Uuid, DB};
 
 /// Represent a price of a product or category with a validity
 ///
@@ -58,7 +57,7 @@ pub mod naive_date_time_serializer {
 impl
     diesel::Queryable<
         (
-            diesel::sql_types::Uuid,
+            crate::core::sql_types::Uuid,
             diesel::sql_types::Timestamp,
             diesel::sql_types::Integer,
         ),
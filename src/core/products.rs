@@ -1,12 +1,11 @@
 use chrono::{Local, NaiveDateTime};
 use diesel::prelude::*;
-use std::fs::{self, File};
-use std::path::Path;
-use uuid::Uuid;
+use std::collections::BTreeMap;
 
 use crate::core::schema::product_barcode;
 use crate::core::{
-    env, generate_uuid, Category, DbConnection, Money, Price, ServiceError, ServiceResult, DB,
+    generate_uuid, generate_uuid_str, images, Availability, Category, DbConnection, Money, Price,
+    ServiceError, ServiceResult, Uuid, DB,
 };
 
 /// Represent a product
@@ -16,10 +15,46 @@ pub struct Product {
     pub name: String,
     pub category: Option<Category>,
     pub image: Option<String>,
+    /// Urls of the image and its thumbnails by size, eg. `original`, `small` or `small_webp`
+    #[serde(skip_deserializing)]
+    pub image_urls: BTreeMap<String, String>,
     #[serde(default = "std::vec::Vec::new")]
     pub prices: Vec<Price>,
     pub current_price: Option<Money>,
     pub barcode: Option<String>,
+    /// The parent product if this product is a variant (eg. size or flavour) of another product
+    pub parent: Option<Uuid>,
+    #[serde(default = "std::vec::Vec::new")]
+    pub variants: Vec<Product>,
+    /// Inactive products are never available, eg. if they are sold out
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// Optional sales schedule. Without any availability windows the product is always available
+    #[serde(default = "std::vec::Vec::new")]
+    pub availabilities: Vec<Availability>,
+    #[serde(skip_deserializing)]
+    pub available: bool,
+    /// Position of the product within its category, lower values are shown first
+    #[serde(default)]
+    pub sort_order: i32,
+    /// Quick key products are shown on the first screen of their category on the terminal
+    #[serde(default)]
+    pub quick_key: bool,
+    /// Optional display color as hex value, eg. `#ff8800`
+    pub color: Option<String>,
+    /// Optional name of the icon that is shown on the terminal
+    pub icon: Option<String>,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+/// Check if the given string is a hex color like `#ff8800`
+fn is_hex_color(color: &str) -> bool {
+    color.len() == 7
+        && color.starts_with('#')
+        && color.chars().skip(1).all(|c| c.is_ascii_hexdigit())
 }
 
 #[derive(Debug, Queryable, Insertable, Identifiable, AsChangeset, Clone)]
@@ -36,15 +71,32 @@ struct ProductBarcode {
 impl
     diesel::Queryable<
         (
-            diesel::sql_types::Uuid,
+            crate::core::sql_types::Uuid,
             diesel::sql_types::Text,
-            diesel::sql_types::Nullable<diesel::sql_types::Uuid>,
+            diesel::sql_types::Nullable<crate::core::sql_types::Uuid>,
+            diesel::sql_types::Nullable<diesel::sql_types::Text>,
+            diesel::sql_types::Nullable<crate::core::sql_types::Uuid>,
+            diesel::sql_types::Bool,
+            diesel::sql_types::Integer,
+            diesel::sql_types::Bool,
+            diesel::sql_types::Nullable<diesel::sql_types::Text>,
             diesel::sql_types::Nullable<diesel::sql_types::Text>,
         ),
         DB,
     > for Product
 {
-    type Row = (Uuid, String, Option<Uuid>, Option<String>);
+    type Row = (
+        Uuid,
+        String,
+        Option<Uuid>,
+        Option<String>,
+        Option<Uuid>,
+        bool,
+        i32,
+        bool,
+        Option<String>,
+        Option<String>,
+    );
 
     fn build(row: Self::Row) -> Self {
         let category = match row.2 {
@@ -53,6 +105,8 @@ impl
                 name: String::new(),
                 prices: vec![],
                 current_price: None,
+                parent: None,
+                ancestors: vec![],
             }),
             None => None,
         };
@@ -61,16 +115,31 @@ impl
             id: row.0,
             name: row.1,
             category,
+            image_urls: row
+                .3
+                .as_ref()
+                .map(|name| images::image_urls(name))
+                .unwrap_or_default(),
             image: row.3,
             prices: vec![],
             current_price: None,
             barcode: None,
+            parent: row.4,
+            variants: vec![],
+            active: row.5,
+            availabilities: vec![],
+            available: false,
+            sort_order: row.6,
+            quick_key: row.7,
+            color: row.8,
+            icon: row.9,
         }
     }
 }
 
 impl Product {
     /// Create a new product with the given name and category
+    #[tracing::instrument(level = "debug", skip_all, fields(name))]
     pub fn create(
         conn: &DbConnection,
         name: &str,
@@ -88,9 +157,19 @@ impl Product {
             name: name.to_owned(),
             category,
             image: None,
+            image_urls: BTreeMap::new(),
             prices: vec![],
             current_price: None,
             barcode: None,
+            parent: None,
+            variants: vec![],
+            active: true,
+            availabilities: vec![],
+            available: true,
+            sort_order: 0,
+            quick_key: false,
+            color: None,
+            icon: None,
         };
 
         diesel::insert_into(dsl::product)
@@ -106,23 +185,55 @@ impl Product {
 
     /// Save the current product data to the database
     ///
-    /// This ignores all changes to the `prices` vec
+    /// This ignores all changes to the `prices` and `variants` vec.
+    /// A variant always shares the category of its parent product.
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %self.id))]
     pub fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::product::dsl;
 
-        let category = match &self.category {
-            Some(category) => Some(category.id.to_owned()),
-            None => None,
+        if let Some(color) = &self.color {
+            if !is_hex_color(color) {
+                return Err(ServiceError::invalid_field(
+                    "color",
+                    format!("'{}' is not a hex color like '#ff8800'", color),
+                ));
+            }
+        }
+
+        let category = match &self.parent {
+            Some(parent_id) => {
+                let parent = self.check_parent(conn, parent_id)?;
+                parent.category.map(|c| c.id)
+            }
+            None => self.category.as_ref().map(|c| c.id),
         };
 
         diesel::update(dsl::product.find(&self.id))
-            .set((dsl::name.eq(&self.name), dsl::category.eq(&category)))
+            .set((
+                dsl::name.eq(&self.name),
+                dsl::category.eq(&category),
+                dsl::parent.eq(&self.parent),
+                dsl::active.eq(&self.active),
+                dsl::sort_order.eq(&self.sort_order),
+                dsl::quick_key.eq(&self.quick_key),
+                dsl::color.eq(&self.color),
+                dsl::icon.eq(&self.icon),
+            ))
             .execute(conn)?;
 
-        match &self.barcode {
-            Some(barcode) => {
-                use crate::core::schema::product_barcode::dsl;
+        // Keep the category of all variants in sync with the parent product
+        diesel::update(dsl::product.filter(dsl::parent.eq(&self.id)))
+            .set(dsl::category.eq(&category))
+            .execute(conn)?;
+
+        // Replace the barcode, upserts are not supported by all database backends
+        conn.transaction::<_, ServiceError, _>(|| {
+            use crate::core::schema::product_barcode::dsl;
+
+            diesel::delete(dsl::product_barcode.filter(dsl::product_id.eq(&self.id)))
+                .execute(conn)?;
 
+            if let Some(barcode) = &self.barcode {
                 let help = ProductBarcode {
                     product_id: self.id,
                     code: barcode.clone(),
@@ -130,17 +241,11 @@ impl Product {
 
                 diesel::insert_into(dsl::product_barcode)
                     .values(&help)
-                    .on_conflict(dsl::product_id)
-                    .do_update()
-                    .set(&help)
                     .execute(conn)?;
             }
-            None => {
-                use crate::core::schema::product_barcode::dsl;
-                diesel::delete(dsl::product_barcode.filter(dsl::product_id.eq(&self.id)))
-                    .execute(conn)?;
-            }
-        }
+
+            Ok(())
+        })?;
 
         Ok(())
     }
@@ -210,6 +315,7 @@ impl Product {
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %self.id))]
     pub fn update_prices(
         &mut self,
         conn: &DbConnection,
@@ -236,6 +342,114 @@ impl Product {
         Ok(())
     }
 
+    /// Add and save a new availability window to the product
+    ///
+    /// This updates the `availabilities` vec and the `available` flag. Windows that cannot contain
+    /// any date are rejected, see `Availability::validate`.
+    pub fn add_availability(
+        &mut self,
+        conn: &DbConnection,
+        availability: Availability,
+    ) -> ServiceResult<()> {
+        use crate::core::schema::product_availability::dsl;
+
+        availability.validate()?;
+
+        diesel::insert_into(dsl::product_availability)
+            .values((
+                dsl::id.eq(&availability.id),
+                dsl::product_id.eq(&self.id),
+                dsl::date_start.eq(&availability.date_start),
+                dsl::date_end.eq(&availability.date_end),
+                dsl::weekdays.eq(availability.weekdays_to_bits()),
+            ))
+            .execute(conn)?;
+
+        self.availabilities.push(availability);
+
+        self.calc_available();
+
+        Ok(())
+    }
+
+    /// Remove and save an availability window from the product by its `id`
+    ///
+    /// This updates the `availabilities` vec and the `available` flag
+    pub fn remove_availability(&mut self, conn: &DbConnection, id: &Uuid) -> ServiceResult<()> {
+        use crate::core::schema::product_availability::dsl;
+
+        diesel::delete(
+            dsl::product_availability.filter(dsl::product_id.eq(&self.id).and(dsl::id.eq(id))),
+        )
+        .execute(conn)?;
+
+        self.availabilities.retain(|a| a.id != *id);
+
+        self.calc_available();
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %self.id))]
+    pub fn update_availabilities(
+        &mut self,
+        conn: &DbConnection,
+        new_availabilities: &[Availability],
+    ) -> ServiceResult<()> {
+        use crate::core::schema::product_availability::dsl;
+
+        for a in new_availabilities {
+            a.validate()?;
+        }
+
+        diesel::delete(dsl::product_availability.filter(dsl::product_id.eq(&self.id)))
+            .execute(conn)?;
+        self.availabilities.clear();
+
+        for a in new_availabilities {
+            self.add_availability(conn, a.clone())?;
+        }
+
+        self.calc_available();
+        Ok(())
+    }
+
+    /// Check if the product with the given `parent_id` is a valid parent for this product
+    ///
+    /// Only one level of variants is supported, so the parent must not be a variant itself
+    /// and this product must not have variants on its own.
+    fn check_parent(&self, conn: &DbConnection, parent_id: &Uuid) -> ServiceResult<Product> {
+        use crate::core::schema::product::dsl;
+
+        if *parent_id == self.id {
+            return Err(ServiceError::invalid_field(
+                "parent",
+                "A product cannot be a variant of itself".to_owned(),
+            ));
+        }
+
+        let parent = Product::get(conn, parent_id)?;
+        if parent.parent.is_some() {
+            return Err(ServiceError::invalid_field(
+                "parent",
+                "A variant cannot have variants on its own".to_owned(),
+            ));
+        }
+
+        let variant_count: i64 = dsl::product
+            .filter(dsl::parent.eq(&self.id))
+            .count()
+            .get_result(conn)?;
+        if variant_count > 0 {
+            return Err(ServiceError::invalid_field(
+                "parent",
+                "A product with variants cannot be a variant on its own".to_owned(),
+            ));
+        }
+
+        Ok(parent)
+    }
+
     fn load_category(&mut self, conn: &DbConnection) -> ServiceResult<()> {
         self.category = match &self.category {
             Some(category) => Some(Category::get(&conn, &category.id)?),
@@ -256,6 +470,58 @@ impl Product {
         Ok(())
     }
 
+    /// Load the variants of this product
+    ///
+    /// This updates the `variants` vec
+    fn load_variants(&mut self, conn: &DbConnection) -> ServiceResult<()> {
+        use crate::core::schema::product::dsl;
+
+        let mut results = dsl::product
+            .filter(dsl::parent.eq(&self.id))
+            .order((dsl::sort_order.asc(), dsl::name.asc()))
+            .load::<Product>(conn)?;
+
+        for p in &mut results {
+            p.load_category(conn)?;
+            p.load_prices(conn)?;
+            p.load_barcode(conn)?;
+            p.load_availabilities(conn)?;
+        }
+
+        self.variants = results;
+
+        Ok(())
+    }
+
+    /// Load the availability windows for this product
+    ///
+    /// This updates the `availabilities` vec and the `available` flag
+    fn load_availabilities(&mut self, conn: &DbConnection) -> ServiceResult<()> {
+        use crate::core::schema::product_availability::dsl;
+
+        let results = dsl::product_availability
+            .filter(dsl::product_id.eq(&self.id))
+            .load::<Availability>(conn)?;
+
+        self.availabilities = results;
+
+        self.calc_available();
+
+        Ok(())
+    }
+
+    /// Calculate the `available` flag based on the `availabilities` vec
+    fn calc_available(&mut self) {
+        self.available = self.is_available_at(&Local::now().naive_local());
+    }
+
+    /// Check if the product can be sold at the given datetime
+    pub fn is_available_at(&self, datetime: &NaiveDateTime) -> bool {
+        self.active
+            && (self.availabilities.is_empty()
+                || self.availabilities.iter().any(|a| a.contains(datetime)))
+    }
+
     /// Load the prices for this product
     ///
     /// This updates the `prices` vec and the `current_price`
@@ -294,35 +560,36 @@ impl Product {
         }
     }
 
-    pub fn set_image(&mut self, conn: &DbConnection, file_extension: &str) -> ServiceResult<File> {
+    /// Validate and save a new product image with its thumbnails
+    ///
+    /// The previous image is only removed if the new image is valid.
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %self.id))]
+    pub fn set_image(&mut self, conn: &DbConnection, data: &[u8]) -> ServiceResult<()> {
         use crate::core::schema::product::dsl;
 
-        self.remove_image(&conn)?;
+        let name = images::save_image(&generate_uuid_str(), data)?;
 
-        let name = format!("{}.{}", generate_uuid(), file_extension);
-        self.image = Some(name.clone());
+        self.remove_image(&conn)?;
 
-        fs::create_dir_all(env::IMAGE_PATH.as_str())?;
-        let file = File::create(format!("{}/{}", env::IMAGE_PATH.as_str(), name))?;
+        self.image = Some(name);
+        self.image_urls = images::image_urls(self.image.as_ref().expect(""));
 
         diesel::update(dsl::product.find(&self.id))
             .set(dsl::image.eq(&self.image))
             .execute(conn)?;
 
-        Ok(file)
+        Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %self.id))]
     pub fn remove_image(&mut self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::product::dsl;
 
         if let Some(name) = self.image.clone() {
-            let p = format!("{}/{}", env::IMAGE_PATH.as_str(), name);
-
-            if Path::new(&p).exists() {
-                fs::remove_file(p)?;
-            }
+            images::delete_image(&name)?;
 
             self.image = None;
+            self.image_urls.clear();
             diesel::update(dsl::product.find(&self.id))
                 .set(dsl::image.eq(&self.image))
                 .execute(conn)?;
@@ -331,22 +598,49 @@ impl Product {
         Ok(())
     }
 
-    /// List all products
+    /// List all products ordered by their `sort_order` and name
+    ///
+    /// Variants are not listed on their own but as part of their parent product
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn all(conn: &DbConnection) -> ServiceResult<Vec<Product>> {
         use crate::core::schema::product::dsl;
 
-        let mut results = dsl::product.order(dsl::name.asc()).load::<Product>(conn)?;
+        let mut results = dsl::product
+            .filter(dsl::parent.is_null())
+            .order((dsl::sort_order.asc(), dsl::name.asc()))
+            .load::<Product>(conn)?;
 
         for p in &mut results {
             p.load_category(conn)?;
             p.load_prices(conn)?;
             p.load_barcode(conn)?;
+            p.load_availabilities(conn)?;
+            p.load_variants(conn)?;
+        }
+
+        Ok(results)
+    }
+
+    /// List all products that are available at the given datetime
+    ///
+    /// Unavailable variants are removed from their parent product
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn all_available_at(
+        conn: &DbConnection,
+        datetime: &NaiveDateTime,
+    ) -> ServiceResult<Vec<Product>> {
+        let mut results = Product::all(conn)?;
+
+        results.retain(|p| p.is_available_at(datetime));
+        for p in &mut results {
+            p.variants.retain(|v| v.is_available_at(datetime));
         }
 
         Ok(results)
     }
 
     /// Get a product by the `id`
+    #[tracing::instrument(level = "debug", skip_all, fields(product_id = %id))]
     pub fn get(conn: &DbConnection, id: &Uuid) -> ServiceResult<Product> {
         use crate::core::schema::product::dsl;
 
@@ -357,10 +651,13 @@ impl Product {
         p.load_category(conn)?;
         p.load_prices(conn)?;
         p.load_barcode(conn)?;
+        p.load_availabilities(conn)?;
+        p.load_variants(conn)?;
 
         Ok(p)
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn get_by_barcode(conn: &DbConnection, code: &str) -> ServiceResult<Product> {
         use crate::core::schema::product_barcode::dsl;
 
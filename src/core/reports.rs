@@ -0,0 +1,257 @@
+//! Monthly transaction reports that are sent via mail.
+use crate::core::mail::send_report_mail;
+use crate::core::{transactions, Account, DbConnection, ServiceResult};
+use chrono::{Datelike, Local};
+use std::cmp::max;
+
+fn pad_left(s: &str, width: usize) -> String {
+    let mut result = String::with_capacity(width);
+    result.push_str(&" ".repeat(width - s.len()));
+    result.push_str(s);
+    result
+}
+fn pad_right(s: &str, width: usize) -> String {
+    let mut result = String::with_capacity(width);
+    result.push_str(s);
+    result.push_str(&" ".repeat(width - s.len()));
+    result
+}
+
+/// Prepares a transaction report for a given user. If the user did not do any transactions in a month, `None` is returned.
+fn generate_report(
+    conn: &DbConnection,
+    account: &Account,
+) -> ServiceResult<Option<(String, String)>> {
+    // get the duration for the report
+    let now = Local::today().naive_local().and_hms(0, 0, 0);
+    let start = if now.month() == 1 {
+        // special-case the jump from jan -> dec
+        now.with_year(now.year() - 1)
+            .expect("Math rules changed overnight, send help.")
+            .with_month(12)
+            .expect("lol")
+            .with_day(1)
+            .expect("lol")
+    } else {
+        now.with_month(now.month() - 1)
+            .expect("Math rules changed overnight, send help.")
+            .with_day(1)
+            .expect("lol")
+    };
+    let end = now.with_day(1).expect("lol");
+
+    let list = transactions::get_by_account(&conn, &account, &start, &end)?;
+
+    let total_down = list
+        .iter()
+        .filter(|ta| ta.total < 0)
+        .fold(0, |acc, ta| acc - ta.total) as f32
+        / 100.0;
+    let total_up = list
+        .iter()
+        .filter(|ta| ta.total > 0)
+        .fold(0, |acc, ta| acc + ta.total) as f32
+        / 100.0;
+    if total_down == 0.0 && total_up == 0.0 {
+        return Ok(None);
+    }
+
+    let start_balance = list[list.len() - 1].before_credit as f32 / 100.0;
+    let end_balance = list[0].after_credit as f32 / 100.0;
+
+    let trans: Vec<(String, String, String)> = list
+        .into_iter()
+        .map(|ta| {
+            let c1 = ta.date.format("%d.%m.%Y - %H:%M").to_string();
+            let c2 = if let Ok(prods) = ta.get_products(&conn) {
+                let mut prods_str = prods
+                    .iter()
+                    .map(|p| format!("{} x {}", p.1, p.0.name))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                if prods_str.chars().count() > 30 {
+                    // Cut at a char boundary, product names may contain umlauts
+                    let mut help: String = prods_str.chars().take(27).collect();
+                    help.push_str("...");
+                    prods_str = help;
+                }
+                prods_str
+            } else {
+                "".to_owned()
+            };
+            let c3 = format!("{:.2}€", ta.total as f32 / 100.0);
+            (c1, c2, c3)
+        })
+        .collect();
+
+    let table_head = ("Date".to_owned(), "Products".to_owned(), "Total".to_owned());
+
+    let (w1, w2, w3) = trans.iter().fold(
+        (table_head.0.len(), table_head.1.len(), table_head.2.len()),
+        |(w1, w2, w3), (c1, c2, c3)| (max(c1.len(), w1), max(c2.len(), w2), max(c3.len(), w3)),
+    );
+
+    let mut table: Vec<String> = trans
+        .into_iter()
+        .map(|(c1, c2, c3)| {
+            format!(
+                " {} | {} | {}",
+                pad_right(&c1, w1),
+                pad_right(&c2, w2),
+                pad_left(&c3, w3)
+            )
+        })
+        .collect();
+
+    table.insert(
+        0,
+        format!(
+            " {} | {} | {}",
+            pad_right(&table_head.0, w1),
+            pad_right(&table_head.1, w2),
+            pad_right(&table_head.2, w3)
+        ),
+    );
+
+    table.insert(
+        1,
+        format!(
+            "-{}-|-{}-|{}",
+            "-".repeat(w1),
+            "-".repeat(w2),
+            "-".repeat(w3)
+        ),
+    );
+
+    let table = table.join("\n");
+
+    let subject_line = format!("[ascii pay] Your report for {}", start.format("%m/%Y"));
+    let message = format!("Hey {user},
+
+this is your monthly transaction report for {month} from the ascii pay system.
+ 
+Total spent:           {total_down:5.2}€
+Total charged to card: {total_up:5.2}€
+
+Start balance: {start_balance:5.2}€
+End balance:   {end_balance:5.2}€
+
+{table}
+
+The Ascii Pay System
+
+----
+This mail has been automatically generated. Please do not reply.
+You are receiving this email because you opted in to receive monthly reports about your account activity.
+If you don't want to receive these mails anymore, you can change your settings in the ascii pay system.",
+        user = account.name,
+        month = start.format("%B %Y"),
+        total_down = total_down,
+        total_up = total_up,
+        start_balance = start_balance,
+        end_balance = end_balance,
+        table = table,
+    );
+
+    Ok(Some((subject_line, message)))
+}
+
+/// Send the report of the last month to all accounts that opted in
+///
+/// Accounts without transactions in the last month get no mail. Returns the number of sent reports.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn send_reports(conn: &DbConnection) -> ServiceResult<usize> {
+    let accounts = Account::all(conn)?;
+    let mut count = 0;
+
+    // assemble reports per user and send them via mail to them.
+    for acc in accounts {
+        if acc.receives_monthly_report {
+            // only send mails when a report has been generated
+            if let Some((subject, report)) = generate_report(&conn, &acc)? {
+                send_report_mail(&acc, subject, report)?;
+                count += 1;
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{test_connection, Permission, Product};
+    use chrono::Duration;
+
+    #[test]
+    fn test_pad() {
+        assert_eq!(pad_left("1.50€", 9), "  1.50€");
+        assert_eq!(pad_right("Date", 6), "Date  ");
+        assert_eq!(pad_left("Total", 5), "Total");
+        assert_eq!(pad_right("", 2), "  ");
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_generate_report() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut account = Account::create(&conn, "Alice", Permission::DEFAULT)?;
+        assert_eq!(generate_report(&conn, &account)?, None);
+
+        // Transactions of this month are part of the next report
+        let mut bob = Account::create(&conn, "Bob", Permission::DEFAULT)?;
+        transactions::execute(&conn, &mut bob, None, None, 500)?;
+        assert_eq!(generate_report(&conn, &bob)?, None);
+
+        let last_month = Local::today().naive_local().with_day(1).unwrap() - Duration::days(1);
+        transactions::execute_at(
+            &conn,
+            &mut account,
+            None,
+            None,
+            1000,
+            last_month.and_hms(10, 0, 0),
+        )?;
+        let payment = transactions::execute_at(
+            &conn,
+            &mut account,
+            None,
+            None,
+            -250,
+            last_month.and_hms(12, 30, 0),
+        )?;
+        let mate = Product::create(&conn, "Club-Mate 0,5 Liter Größe", None)?;
+        let coffee = Product::create(&conn, "Coffee", None)?;
+        payment.add_products(&conn, vec![(mate, 1), (coffee, 2)])?;
+
+        let (subject, message) = generate_report(&conn, &account)?.expect("report");
+        assert_eq!(
+            subject,
+            format!("[ascii pay] Your report for {}", last_month.format("%m/%Y"))
+        );
+        assert!(message.starts_with("Hey Alice,"));
+        assert!(message.contains("Total spent:            2.50€"));
+        assert!(message.contains("Total charged to card: 10.00€"));
+        assert!(message.contains("Start balance:  0.00€"));
+        assert!(message.contains("End balance:    7.50€"));
+
+        let table: Vec<&str> = message
+            .lines()
+            .skip_while(|line| !line.starts_with(" Date"))
+            .take(4)
+            .collect();
+        assert_eq!(table.len(), 4);
+        assert!(table[1].starts_with("-------------------"));
+        assert!(table[2].starts_with(&format!(" {} | ", last_month.format("%d.%m.%Y - 12:30"))));
+        assert!(table[2].ends_with(" | -2.50€"));
+        assert!(table[3].ends_with(" | 10.00€"));
+
+        let products = table[2].split(" | ").nth(1).unwrap().trim_end();
+        assert_eq!(products.chars().count(), 30);
+        assert!(products.ends_with("..."));
+
+        Ok(())
+    }
+}
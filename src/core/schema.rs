@@ -1,4 +1,7 @@
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     account (id) {
         id -> Uuid,
         credit -> Int4,
@@ -13,6 +16,25 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
+    auth_failure (id) {
+        id -> Uuid,
+        created -> Timestamp,
+        kind -> Varchar,
+        ip -> Varchar,
+        login -> Nullable<Varchar>,
+        account_id -> Nullable<Uuid>,
+        terminal_id -> Nullable<Uuid>,
+        unlocked -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     authentication_barcode (account_id) {
         account_id -> Uuid,
         code -> Varchar,
@@ -20,6 +42,9 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     authentication_nfc (account_id) {
         account_id -> Uuid,
         card_id -> Varchar,
@@ -29,6 +54,9 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     authentication_nfc_write_key (account_id, card_id) {
         account_id -> Uuid,
         card_id -> Varchar,
@@ -36,6 +64,9 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     authentication_password (account_id) {
         account_id -> Uuid,
         password -> Varchar,
@@ -43,6 +74,9 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     authentication_password_invitation (account_id) {
         account_id -> Uuid,
         link -> Varchar,
@@ -51,13 +85,43 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
+    authentication_totp (account_id) {
+        account_id -> Uuid,
+        secret -> Varchar,
+        confirmed -> Bool,
+        last_step -> Nullable<Int8>,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
+    authentication_totp_recovery (account_id, code_hash) {
+        account_id -> Uuid,
+        code_hash -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     category (id) {
         id -> Uuid,
         name -> Varchar,
+        parent -> Nullable<Uuid>,
     }
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     category_price (category_id, validity_start) {
         category_id -> Uuid,
         validity_start -> Timestamp,
@@ -66,15 +130,40 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     product (id) {
         id -> Uuid,
         name -> Varchar,
         category -> Nullable<Uuid>,
         image -> Nullable<Varchar>,
+        parent -> Nullable<Uuid>,
+        active -> Bool,
+        sort_order -> Int4,
+        quick_key -> Bool,
+        color -> Nullable<Varchar>,
+        icon -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
+    product_availability (id) {
+        id -> Uuid,
+        product_id -> Uuid,
+        date_start -> Nullable<Date>,
+        date_end -> Nullable<Date>,
+        weekdays -> Int2,
     }
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     product_barcode (product_id) {
         product_id -> Uuid,
         code -> Varchar,
@@ -82,6 +171,9 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     product_price (product_id, validity_start) {
         product_id -> Uuid,
         validity_start -> Timestamp,
@@ -90,14 +182,37 @@ table! {
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     session (id) {
         id -> Varchar,
         account_id -> Uuid,
         valid_until -> Timestamp,
+        second_factor_pending -> Bool,
     }
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
+    terminal (id) {
+        id -> Uuid,
+        name -> Varchar,
+        location -> Nullable<Varchar>,
+        key_hash -> Varchar,
+        created -> Timestamp,
+        last_seen -> Nullable<Timestamp>,
+        revoked -> Nullable<Timestamp>,
+        certificate_subject -> Nullable<Varchar>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     transaction (id) {
         id -> Uuid,
         account_id -> Uuid,
@@ -106,10 +221,14 @@ table! {
         before_credit -> Int4,
         after_credit -> Int4,
         date -> Timestamp,
+        terminal_id -> Nullable<Uuid>,
     }
 }
 
 table! {
+    use diesel::sql_types::*;
+    use crate::core::sql_types::Uuid;
+
     transaction_product (transaction, product_id) {
         transaction -> Uuid,
         product_id -> Uuid,
@@ -119,17 +238,22 @@ table! {
 
 allow_tables_to_appear_in_same_query!(
     account,
+    auth_failure,
     authentication_barcode,
     authentication_nfc,
     authentication_nfc_write_key,
     authentication_password,
     authentication_password_invitation,
+    authentication_totp,
+    authentication_totp_recovery,
     category,
     category_price,
     product,
+    product_availability,
     product_barcode,
     product_price,
     session,
+    terminal,
     transaction,
     transaction_product,
 );
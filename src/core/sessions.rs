@@ -1,9 +1,8 @@
 use chrono::{Duration, Local, NaiveDateTime};
 use diesel::prelude::*;
-use uuid::Uuid;
 
 use crate::core::schema::session;
-use crate::core::{generate_uuid_str, DbConnection, ServiceError, ServiceResult};
+use crate::core::{generate_uuid_str, DbConnection, ServiceError, ServiceResult, Uuid};
 
 /// Auto logout after `self` minutes of inactivity
 const VALIDITY_MINUTES: i64 = 10;
@@ -27,11 +26,18 @@ pub struct Session {
     pub id: String,
     pub account_id: Uuid,
     pub valid_until: NaiveDateTime,
+    /// Set until the second factor of the account is verified, the session grants no access before
+    pub second_factor_pending: bool,
 }
 
 impl Session {
     /// Create a new session
-    pub fn create(conn: &DbConnection, account_id: &Uuid) -> ServiceResult<Session> {
+    #[tracing::instrument(level = "debug", skip_all, fields(account_id = %account_id))]
+    pub fn create(
+        conn: &DbConnection,
+        account_id: &Uuid,
+        second_factor_pending: bool,
+    ) -> ServiceResult<Session> {
         use crate::core::schema::session::dsl;
 
         Session::cleanup(&conn)?;
@@ -40,6 +46,7 @@ impl Session {
             id: generate_uuid_str(),
             account_id: *account_id,
             valid_until: Local::now().naive_local() + Duration::minutes(VALIDITY_MINUTES),
+            second_factor_pending,
         };
 
         diesel::delete(dsl::session.filter(dsl::id.eq(&a.id))).execute(conn)?;
@@ -48,6 +55,7 @@ impl Session {
         Ok(a)
     }
 
+    #[tracing::instrument(level = "debug", skip_all, fields(account_id = %account_id))]
     pub fn register(conn: &DbConnection, account_id: &Uuid, token: &str) -> ServiceResult<Session> {
         use crate::core::schema::session::dsl;
 
@@ -57,6 +65,7 @@ impl Session {
             id: token.to_owned(),
             account_id: *account_id,
             valid_until: Local::now().naive_local() + Duration::minutes(VALIDITY_MINUTES),
+            second_factor_pending: false,
         };
 
         diesel::delete(dsl::session.filter(dsl::id.eq(&a.id))).execute(conn)?;
@@ -66,6 +75,7 @@ impl Session {
     }
 
     /// Save the current session data to the database
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::session::dsl;
 
@@ -82,6 +92,7 @@ impl Session {
     }
 
     /// Get an session by the `id`
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn get(conn: &DbConnection, id: &str) -> ServiceResult<Session> {
         use crate::core::schema::session::dsl;
 
@@ -98,6 +109,7 @@ impl Session {
     }
 
     /// Delete and invalidate this session
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn delete(&self, conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::session::dsl;
 
@@ -106,7 +118,28 @@ impl Session {
         Ok(())
     }
 
+    /// Delete all sessions of the account except the session `keep`
+    ///
+    /// Used if the sessions were created with less privileges or credentials than now required.
+    #[tracing::instrument(level = "debug", skip_all, fields(account_id = %account_id))]
+    pub fn delete_by_account(
+        conn: &DbConnection,
+        account_id: &Uuid,
+        keep: Option<&str>,
+    ) -> ServiceResult<()> {
+        use crate::core::schema::session::dsl;
+
+        let sessions = dsl::session.filter(dsl::account_id.eq(account_id));
+        match keep {
+            Some(id) => diesel::delete(sessions.filter(dsl::id.ne(id))).execute(conn)?,
+            None => diesel::delete(sessions).execute(conn)?,
+        };
+
+        Ok(())
+    }
+
     /// Delete all expired sessions
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn cleanup(conn: &DbConnection) -> ServiceResult<()> {
         use crate::core::schema::session::dsl;
 
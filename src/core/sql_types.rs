@@ -0,0 +1,38 @@
+//! Sql types that differ between the database backends.
+//!
+//! Postgres has a native `uuid` type, sqlite stores uuids as hyphenated text.
+
+#[cfg(not(feature = "sqlite"))]
+pub use diesel::sql_types::Uuid;
+
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::Uuid;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use diesel::backend::Backend;
+    use diesel::deserialize::{self, FromSql};
+    use diesel::serialize::{self, Output, ToSql};
+    use diesel::sql_types::Text;
+    use diesel::sqlite::Sqlite;
+    use std::io::Write;
+
+    /// Uuid that is stored as hyphenated text
+    #[derive(Debug, Clone, Copy, Default, QueryId, SqlType)]
+    #[sqlite_type = "Text"]
+    pub struct Uuid;
+
+    impl FromSql<Uuid, Sqlite> for crate::core::Uuid {
+        fn from_sql(bytes: Option<&<Sqlite as Backend>::RawValue>) -> deserialize::Result<Self> {
+            let text = <String as FromSql<Text, Sqlite>>::from_sql(bytes)?;
+            crate::core::Uuid::parse_str(&text).map_err(Into::into)
+        }
+    }
+
+    impl ToSql<Uuid, Sqlite> for crate::core::Uuid {
+        fn to_sql<W: Write>(&self, out: &mut Output<W, Sqlite>) -> serialize::Result {
+            let text = self.to_hyphenated().to_string();
+            <String as ToSql<Text, Sqlite>>::to_sql(&text, out)
+        }
+    }
+}
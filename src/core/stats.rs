@@ -1,8 +1,14 @@
+use chrono::{Datelike, Local, NaiveDateTime, Timelike};
 use diesel::prelude::*;
+use std::collections::HashMap;
 
-use crate::core::{DbConnection, Money, ServiceError, ServiceResult};
+use crate::core::transactions::{self, ValidationResult};
+use crate::core::{
+    Account, Category, DbConnection, Money, Product, ServiceError, ServiceResult, Transaction, Uuid,
+};
 
 /// Get an account by the `id`
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn get_total_balance(conn: &DbConnection) -> ServiceResult<Money> {
     use crate::core::schema::account::dsl;
 
@@ -12,3 +18,432 @@ pub fn get_total_balance(conn: &DbConnection) -> ServiceResult<Money> {
 
     result.map(|v| v as Money).ok_or(ServiceError::NotFound)
 }
+
+/// List all sold products with their amounts between the given datetimes
+///
+/// If `group_variants` is set, the sales of all variants are added to their parent product.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn get_sold_products(
+    conn: &DbConnection,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+    group_variants: bool,
+) -> ServiceResult<Vec<(Product, i32)>> {
+    use crate::core::schema::{transaction, transaction_product};
+
+    let results = transaction_product::table
+        .inner_join(transaction::table.on(transaction::id.eq(transaction_product::transaction)))
+        .filter(transaction::date.between(from, to))
+        .select((transaction_product::product_id, transaction_product::amount))
+        .load::<(Uuid, i32)>(conn)?;
+
+    let mut amounts: HashMap<Uuid, i32> = HashMap::new();
+    for (product_id, amount) in results {
+        *amounts.entry(product_id).or_insert(0) += amount;
+    }
+
+    let mut products: HashMap<Uuid, (Product, i32)> = HashMap::new();
+    for (product_id, amount) in amounts {
+        let mut product = match Product::get(conn, &product_id) {
+            Ok(product) => product,
+            Err(ServiceError::NotFound) => continue,
+            Err(e) => return Err(e),
+        };
+
+        if group_variants {
+            if let Some(parent_id) = product.parent {
+                product = Product::get(conn, &parent_id)?;
+            }
+        }
+
+        products.entry(product.id).or_insert_with(|| (product, 0)).1 += amount;
+    }
+
+    let mut list: Vec<(Product, i32)> = products.into_iter().map(|(_, v)| v).collect();
+    list.sort_by(|(p1, a1), (p2, a2)| a2.cmp(a1).then_with(|| p1.name.cmp(&p2.name)));
+
+    Ok(list)
+}
+
+/// List all categories with the amount of sold products between the given datetimes
+///
+/// The sales of a category are rolled up along the hierarchy, so each category
+/// also contains the sales of all its subcategories.
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn get_sold_categories(
+    conn: &DbConnection,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+) -> ServiceResult<Vec<(Category, i32)>> {
+    let mut categories: HashMap<Uuid, (Category, i32)> = HashMap::new();
+
+    for (product, amount) in get_sold_products(conn, from, to, true)? {
+        let category = match product.category {
+            Some(category) => category,
+            None => continue,
+        };
+
+        let mut path = category.ancestors.clone();
+        path.insert(0, category);
+
+        for mut c in path {
+            c.ancestors.clear();
+
+            categories.entry(c.id).or_insert_with(|| (c, 0)).1 += amount;
+        }
+    }
+
+    let mut list: Vec<(Category, i32)> = categories.into_iter().map(|(_, v)| v).collect();
+    list.sort_by(|(c1, a1), (c2, a2)| a2.cmp(a1).then_with(|| c1.name.cmp(&c2.name)));
+
+    Ok(list)
+}
+
+/// Grouping of a sales statistic
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsGrouping {
+    Product,
+    Category,
+    Day,
+    Weekday,
+    Hour,
+}
+
+/// Represent a single group of a sales statistic
+#[derive(Debug, Serialize, Clone)]
+pub struct StatsEntry {
+    /// Unique key of the group, eg. the product id, the date or the hour
+    pub key: String,
+    pub label: String,
+    /// Sum of all payments
+    ///
+    /// For product and category groups this is estimated with the product price at the transaction date.
+    pub revenue: Money,
+    /// Sum of all top-ups, this is always zero for product and category groups
+    pub top_ups: Money,
+    /// Number of sold items
+    pub items: i32,
+}
+
+impl StatsEntry {
+    fn new(key: String, label: String) -> StatsEntry {
+        StatsEntry {
+            key,
+            label,
+            revenue: 0,
+            top_ups: 0,
+            items: 0,
+        }
+    }
+}
+
+const WEEKDAY_NAMES: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// Calculate revenue, top-ups and item counts between the given datetimes
+///
+/// Time based groupings (`Day`, `Weekday` and `Hour`) contain an entry for every group,
+/// even if there are no transactions. Product and category groups are sorted by revenue.
+#[tracing::instrument(level = "debug", skip_all, fields(grouping = ?grouping))]
+pub fn get_stats(
+    conn: &DbConnection,
+    from: &NaiveDateTime,
+    to: &NaiveDateTime,
+    grouping: StatsGrouping,
+) -> ServiceResult<Vec<StatsEntry>> {
+    use crate::core::schema::{transaction, transaction_product};
+
+    let transactions = transaction::table
+        .filter(transaction::date.between(from, to))
+        .select((transaction::date, transaction::total))
+        .load::<(NaiveDateTime, Money)>(conn)?;
+
+    let items = transaction_product::table
+        .inner_join(transaction::table.on(transaction::id.eq(transaction_product::transaction)))
+        .filter(transaction::date.between(from, to))
+        .select((
+            transaction::date,
+            transaction_product::product_id,
+            transaction_product::amount,
+        ))
+        .load::<(NaiveDateTime, Uuid, i32)>(conn)?;
+
+    match grouping {
+        StatsGrouping::Product | StatsGrouping::Category => {
+            get_product_stats(conn, items, grouping == StatsGrouping::Category)
+        }
+        StatsGrouping::Day | StatsGrouping::Weekday | StatsGrouping::Hour => {
+            let mut entries: Vec<StatsEntry> = match grouping {
+                StatsGrouping::Day => {
+                    let mut entries = Vec::new();
+                    let mut date = from.date();
+                    while date <= to.date() {
+                        let key = date.format("%Y-%m-%d").to_string();
+                        entries.push(StatsEntry::new(key.clone(), key));
+                        date = date.succ();
+                    }
+                    entries
+                }
+                StatsGrouping::Weekday => WEEKDAY_NAMES
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| StatsEntry::new(i.to_string(), (*name).to_owned()))
+                    .collect(),
+                _ => (0..24)
+                    .map(|h| StatsEntry::new(h.to_string(), format!("{:02}:00", h)))
+                    .collect(),
+            };
+
+            let index = |date: &NaiveDateTime| match grouping {
+                StatsGrouping::Day => (date.date() - from.date()).num_days() as usize,
+                StatsGrouping::Weekday => date.weekday().num_days_from_monday() as usize,
+                _ => date.hour() as usize,
+            };
+
+            for (date, total) in transactions {
+                let entry = &mut entries[index(&date)];
+                if total < 0 {
+                    entry.revenue -= total;
+                } else {
+                    entry.top_ups += total;
+                }
+            }
+            for (date, _, amount) in items {
+                entries[index(&date)].items += amount;
+            }
+
+            Ok(entries)
+        }
+    }
+}
+
+/// Group the sold items by product or by category
+fn get_product_stats(
+    conn: &DbConnection,
+    items: Vec<(NaiveDateTime, Uuid, i32)>,
+    by_category: bool,
+) -> ServiceResult<Vec<StatsEntry>> {
+    let mut products: HashMap<Uuid, Option<Product>> = HashMap::new();
+    let mut entries: HashMap<String, StatsEntry> = HashMap::new();
+
+    for (date, product_id, amount) in items {
+        let product = match products.get(&product_id) {
+            Some(product) => product,
+            None => {
+                let product = match Product::get(conn, &product_id) {
+                    Ok(product) => Some(product),
+                    Err(ServiceError::NotFound) => None,
+                    Err(e) => return Err(e),
+                };
+                products.entry(product_id).or_insert(product)
+            }
+        };
+        let product = match product {
+            Some(product) => product,
+            None => continue,
+        };
+
+        let (key, label) = if by_category {
+            match &product.category {
+                Some(category) => (category.id.to_string(), category.name.clone()),
+                None => (String::new(), "Without category".to_owned()),
+            }
+        } else {
+            (product.id.to_string(), product.name.clone())
+        };
+
+        let entry = entries
+            .entry(key.clone())
+            .or_insert_with(|| StatsEntry::new(key, label));
+        entry.items += amount;
+        entry.revenue += product.get_price_at(&date).unwrap_or(0) * amount;
+    }
+
+    let mut list: Vec<StatsEntry> = entries.into_iter().map(|(_, v)| v).collect();
+    list.sort_by(|e1, e2| {
+        e2.revenue
+            .cmp(&e1.revenue)
+            .then_with(|| e1.label.cmp(&e2.label))
+    });
+
+    Ok(list)
+}
+
+/// Represent a transaction of the dashboard with the name of its account
+#[derive(Debug, Serialize, Clone)]
+pub struct OverviewTransaction {
+    pub transaction: Transaction,
+    pub account_name: String,
+}
+
+/// Represent an account whose credit does not match its transactions
+#[derive(Debug, Serialize)]
+pub struct InvalidAccount {
+    pub account: Account,
+    pub result: ValidationResult,
+}
+
+/// Summary of the current state for the admin dashboard
+#[derive(Debug, Serialize)]
+pub struct Overview {
+    pub total_balance: Money,
+    /// Number of accounts with a credit below zero
+    pub negative_accounts: usize,
+    /// Sum of the credit of all accounts below zero
+    pub negative_accounts_debt: Money,
+    pub today_revenue: Money,
+    pub last_transactions: Vec<OverviewTransaction>,
+    pub invalid_accounts: Vec<InvalidAccount>,
+    pub warnings: Vec<String>,
+}
+
+/// Collect the dashboard overview with the last `transaction_count` transactions
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn get_overview(conn: &DbConnection, transaction_count: i64) -> ServiceResult<Overview> {
+    use crate::core::schema::transaction::dsl;
+
+    let total_balance = match get_total_balance(conn) {
+        Ok(balance) => balance,
+        Err(ServiceError::NotFound) => 0,
+        Err(e) => return Err(e),
+    };
+
+    let accounts = Account::all(conn)?;
+    let account_names: HashMap<Uuid, String> =
+        accounts.iter().map(|a| (a.id, a.name.clone())).collect();
+
+    let negative: Vec<&Account> = accounts.iter().filter(|a| a.credit < 0).collect();
+    let negative_accounts_debt = negative.iter().map(|a| a.credit).sum();
+
+    let today = Local::now().naive_local().date().and_hms(0, 0, 0);
+    let today_revenue: Money = dsl::transaction
+        .filter(dsl::date.ge(today))
+        .filter(dsl::total.lt(0))
+        .select(dsl::total)
+        .load::<Money>(conn)?
+        .into_iter()
+        .map(|total| -total)
+        .sum();
+
+    let last_transactions = dsl::transaction
+        .order(dsl::date.desc())
+        .limit(transaction_count)
+        .load::<Transaction>(conn)?
+        .into_iter()
+        .map(|transaction| OverviewTransaction {
+            account_name: account_names
+                .get(&transaction.account_id)
+                .cloned()
+                .unwrap_or_default(),
+            transaction,
+        })
+        .collect();
+
+    let mut validation = transactions::validate_all(conn)?;
+    let invalid_accounts = accounts
+        .iter()
+        .filter_map(|a| match validation.remove(&a.id) {
+            Some(ValidationResult::Ok) | Some(ValidationResult::NoData) | None => None,
+            Some(result) => Some(InvalidAccount {
+                account: a.clone(),
+                result,
+            }),
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+    for product in Product::all(conn)? {
+        for p in std::iter::once(&product).chain(product.variants.iter()) {
+            if p.active && p.current_price.is_none() {
+                warnings.push(format!("The product '{}' has no current price", p.name));
+            }
+        }
+    }
+    for account in &accounts {
+        if account.credit < account.minimum_credit {
+            warnings.push(format!(
+                "The account '{}' is below its minimum credit",
+                account.name
+            ));
+        }
+    }
+
+    Ok(Overview {
+        total_balance,
+        negative_accounts: negative.len(),
+        negative_accounts_debt,
+        today_revenue,
+        last_transactions,
+        invalid_accounts,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{test_connection, Permission};
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_overview() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let overview = get_overview(&conn, 10)?;
+        assert_eq!(overview.total_balance, 0);
+        assert_eq!(overview.negative_accounts, 0);
+        assert_eq!(overview.today_revenue, 0);
+        assert!(overview.last_transactions.is_empty());
+        assert!(overview.invalid_accounts.is_empty());
+        assert!(overview.warnings.is_empty());
+
+        let mut alice = Account::create(&conn, "Alice", Permission::DEFAULT)?;
+        transactions::execute(&conn, &mut alice, None, None, 1000)?;
+        let payment = transactions::execute(&conn, &mut alice, None, None, -300)?;
+
+        // Credit that was changed without a transaction
+        let mut bob = Account::create(&conn, "Bob", Permission::DEFAULT)?;
+        bob.credit = -200;
+        bob.update(&conn)?;
+
+        Product::create(&conn, "Coffee", None)?;
+
+        let overview = get_overview(&conn, 1)?;
+        assert_eq!(overview.total_balance, 500);
+        assert_eq!(overview.negative_accounts, 1);
+        assert_eq!(overview.negative_accounts_debt, -200);
+        assert_eq!(overview.today_revenue, 300);
+
+        assert_eq!(overview.last_transactions.len(), 1);
+        assert_eq!(overview.last_transactions[0].transaction.id, payment.id);
+        assert_eq!(overview.last_transactions[0].account_name, "Alice");
+
+        assert_eq!(overview.invalid_accounts.len(), 1);
+        assert_eq!(overview.invalid_accounts[0].account.id, bob.id);
+        assert!(matches!(
+            overview.invalid_accounts[0].result,
+            ValidationResult::InvalidSum {
+                expected: 0,
+                actual: -200
+            }
+        ));
+
+        assert_eq!(
+            overview.warnings,
+            vec![
+                "The product 'Coffee' has no current price",
+                "The account 'Bob' is below its minimum credit",
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{Duration, Local, NaiveDateTime};
+use diesel::prelude::*;
+use rand_core::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::core::schema::terminal;
+use crate::core::{generate_uuid, DbConnection, ServiceError, ServiceResult, Uuid};
+
+/// Number of random bytes of a terminal api key
+const KEY_LENGTH: usize = 32;
+
+/// Minimal interval between two updates of `last_seen`, to not write on every request
+const LAST_SEEN_INTERVAL_SECONDS: i64 = 60;
+
+/// Represent a registered payment terminal
+#[derive(
+    Debug,
+    Queryable,
+    Insertable,
+    Identifiable,
+    AsChangeset,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    Clone,
+)]
+#[changeset_options(treat_none_as_null = "true")]
+#[table_name = "terminal"]
+pub struct Terminal {
+    pub id: Uuid,
+    pub name: String,
+    pub location: Option<String>,
+    /// Sha256 hash of the api key, the key itself is only shown once on creation
+    #[serde(skip)]
+    pub key_hash: String,
+    pub created: NaiveDateTime,
+    pub last_seen: Option<NaiveDateTime>,
+    pub revoked: Option<NaiveDateTime>,
+    /// Common name of the client certificate subject that identifies this terminal
+    pub certificate_subject: Option<String>,
+}
+
+impl Terminal {
+    /// Register a new terminal and return it with its plain api key
+    #[tracing::instrument(level = "debug", skip_all, fields(name))]
+    pub fn create(
+        conn: &DbConnection,
+        name: &str,
+        location: Option<String>,
+        certificate_subject: Option<String>,
+    ) -> ServiceResult<(Terminal, String)> {
+        use crate::core::schema::terminal::dsl;
+
+        let key = generate_key();
+
+        let t = Terminal {
+            id: generate_uuid(),
+            name: name.to_owned(),
+            location,
+            key_hash: hash_key(&key),
+            created: Local::now().naive_local(),
+            last_seen: None,
+            revoked: None,
+            certificate_subject,
+        };
+
+        diesel::insert_into(dsl::terminal)
+            .values(&t)
+            .execute(conn)?;
+
+        Ok((t, key))
+    }
+
+    /// Save the current terminal data to the database
+    #[tracing::instrument(level = "debug", skip_all, fields(terminal_id = %self.id))]
+    pub fn update(&self, conn: &DbConnection) -> ServiceResult<()> {
+        use crate::core::schema::terminal::dsl;
+
+        diesel::update(dsl::terminal.find(&self.id))
+            .set(self)
+            .execute(conn)?;
+
+        Ok(())
+    }
+
+    /// Revoke the api key of this terminal, this cannot be undone
+    #[tracing::instrument(level = "debug", skip_all, fields(terminal_id = %self.id))]
+    pub fn revoke(&mut self, conn: &DbConnection) -> ServiceResult<()> {
+        if self.revoked.is_none() {
+            self.revoked = Some(Local::now().naive_local());
+            self.update(conn)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked.is_some()
+    }
+
+    /// List all terminals
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn all(conn: &DbConnection) -> ServiceResult<Vec<Terminal>> {
+        use crate::core::schema::terminal::dsl;
+
+        let results = dsl::terminal
+            .order(dsl::name.asc())
+            .load::<Terminal>(conn)?;
+
+        Ok(results)
+    }
+
+    /// Get a terminal by the `id`
+    #[tracing::instrument(level = "debug", skip_all, fields(terminal_id = %id))]
+    pub fn get(conn: &DbConnection, id: &Uuid) -> ServiceResult<Terminal> {
+        use crate::core::schema::terminal::dsl;
+
+        let mut results = dsl::terminal
+            .filter(dsl::id.eq(id))
+            .load::<Terminal>(conn)?;
+
+        results.pop().ok_or_else(|| ServiceError::NotFound)
+    }
+
+    /// Get the terminal of an api key and update its `last_seen` timestamp
+    ///
+    /// Unknown and revoked keys are rejected.
+    #[tracing::instrument(level = "debug", skip_all)]
+    pub fn authenticate(conn: &DbConnection, key: &str) -> ServiceResult<Terminal> {
+        use crate::core::schema::terminal::dsl;
+
+        let mut results = dsl::terminal
+            .filter(dsl::key_hash.eq(hash_key(key)))
+            .load::<Terminal>(conn)?;
+
+        let terminal = results.pop().ok_or_else(|| ServiceError::Unauthorized)?;
+        terminal.seen(conn)
+    }
+
+    /// Get the terminal of a client certificate subject and update its `last_seen` timestamp
+    ///
+    /// Unknown subjects and revoked terminals are rejected.
+    #[tracing::instrument(level = "debug", skip_all, fields(subject))]
+    pub fn authenticate_certificate(conn: &DbConnection, subject: &str) -> ServiceResult<Terminal> {
+        use crate::core::schema::terminal::dsl;
+
+        let mut results = dsl::terminal
+            .filter(dsl::certificate_subject.eq(subject))
+            .load::<Terminal>(conn)?;
+
+        let terminal = results.pop().ok_or_else(|| ServiceError::Unauthorized)?;
+        terminal.seen(conn)
+    }
+
+    /// Reject revoked terminals and update the `last_seen` timestamp of valid ones
+    fn seen(mut self, conn: &DbConnection) -> ServiceResult<Terminal> {
+        use crate::core::schema::terminal::dsl;
+
+        if self.is_revoked() {
+            return Err(ServiceError::Unauthorized);
+        }
+
+        let now = Local::now().naive_local();
+        let outdated = match self.last_seen {
+            Some(last_seen) => now - last_seen > Duration::seconds(LAST_SEEN_INTERVAL_SECONDS),
+            None => true,
+        };
+        if outdated {
+            self.last_seen = Some(now);
+            diesel::update(dsl::terminal.find(&self.id))
+                .set(dsl::last_seen.eq(&self.last_seen))
+                .execute(conn)?;
+        }
+
+        Ok(self)
+    }
+}
+
+/// Generate a new random api key
+fn generate_key() -> String {
+    let mut data = [0u8; KEY_LENGTH];
+    rand::thread_rng().fill_bytes(&mut data);
+    base64::encode_config(&data, base64::URL_SAFE_NO_PAD)
+}
+
+/// Hash an api key for storage
+///
+/// The keys are long random values, so a fast unsalted hash is sufficient and allows the lookup
+/// by hash.
+fn hash_key(key: &str) -> String {
+    Sha256::digest(key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_connection;
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_authenticate() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let (terminal, key) =
+            Terminal::create(&conn, "Kitchen", Some("Ground floor".to_owned()), None)?;
+        assert_ne!(terminal.key_hash, key);
+        assert!(terminal.last_seen.is_none());
+
+        let authenticated = Terminal::authenticate(&conn, &key)?;
+        assert_eq!(authenticated.id, terminal.id);
+        assert!(Terminal::get(&conn, &terminal.id)?.last_seen.is_some());
+
+        assert!(Terminal::authenticate(&conn, "unknown").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_revoke() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let (mut terminal, key) = Terminal::create(&conn, "Kitchen", None, None)?;
+        let (_, other_key) = Terminal::create(&conn, "Office", None, None)?;
+
+        terminal.revoke(&conn)?;
+
+        assert!(Terminal::get(&conn, &terminal.id)?.is_revoked());
+        assert!(Terminal::authenticate(&conn, &key).is_err());
+        assert!(Terminal::authenticate(&conn, &other_key).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_authenticate_certificate() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let (mut terminal, _) =
+            Terminal::create(&conn, "Kitchen", None, Some("kitchen.terminal".to_owned()))?;
+
+        let authenticated = Terminal::authenticate_certificate(&conn, "kitchen.terminal")?;
+        assert_eq!(authenticated.id, terminal.id);
+        assert!(Terminal::authenticate_certificate(&conn, "office.terminal").is_err());
+
+        terminal.revoke(&conn)?;
+        assert!(Terminal::authenticate_certificate(&conn, "kitchen.terminal").is_err());
+
+        Ok(())
+    }
+}
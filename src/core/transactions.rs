@@ -1,11 +1,12 @@
 use chrono::{Local, NaiveDateTime};
 use diesel::prelude::*;
 use std::collections::HashMap;
-use uuid::Uuid;
 
+use crate::core::metrics;
 use crate::core::schema::transaction;
 use crate::core::{
-    generate_uuid, Account, DbConnection, Money, Product, ServiceError, ServiceResult,
+    generate_uuid, serializable_transaction, Account, DbConnection, Money, Product, ServiceError,
+    ServiceResult, Terminal, Uuid,
 };
 
 /// Represent a transaction
@@ -19,6 +20,8 @@ pub struct Transaction {
     pub before_credit: Money,
     pub after_credit: Money,
     pub date: NaiveDateTime,
+    /// The terminal that created this transaction
+    pub terminal_id: Option<Uuid>,
 }
 
 /// Execute a transaction on the given `account` with the given `total`
@@ -28,12 +31,13 @@ pub struct Transaction {
 /// * 2 Requery the account credit
 /// * 3 Calculate the new credit
 /// * 4 Check if the account minimum_credit allows the new credit
-/// * 5 Create and save the transaction (with optional cashier refernece)
+/// * 5 Create and save the transaction (with optional cashier and terminal refernece)
 /// * 6 Save the new credit to the account
-fn execute_at(
+pub fn execute_at(
     conn: &DbConnection,
     account: &mut Account,
     cashier: Option<&Account>,
+    terminal: Option<&Terminal>,
     total: Money,
     date: NaiveDateTime,
 ) -> ServiceResult<Transaction> {
@@ -52,16 +56,12 @@ fn execute_at(
     let before_credit = account.credit;
     let mut after_credit = account.credit;
 
-    let result = conn.build_transaction().serializable().run(|| {
+    let result = serializable_transaction(conn, || {
         let mut account = Account::get(conn, &account.id)?;
         after_credit = account.credit + total;
 
         if after_credit < account.minimum_credit && after_credit < account.credit {
-            return Err(ServiceError::InternalServerError(
-                "Transaction error",
-                "The transaction can not be performed. Check the account credit and minimum_credit"
-                    .to_owned(),
-            ));
+            return Err(ServiceError::InsufficientCredit);
         }
 
         let a = Transaction {
@@ -72,6 +72,7 @@ fn execute_at(
             before_credit,
             after_credit,
             date,
+            terminal_id: terminal.map(|t| t.id),
         };
         account.credit = after_credit;
 
@@ -98,19 +99,35 @@ fn execute_at(
 /// * 2 Requery the account credit
 /// * 3 Calculate the new credit
 /// * 4 Check if the account minimum_credit allows the new credit
-/// * 5 Create and save the transaction (with optional cashier refernece)
+/// * 5 Create and save the transaction (with optional cashier and terminal refernece)
 /// * 6 Save the new credit to the account
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id, total))]
 pub fn execute(
     conn: &DbConnection,
     account: &mut Account,
     cashier: Option<&Account>,
+    terminal: Option<&Terminal>,
     total: Money,
 ) -> ServiceResult<Transaction> {
-    execute_at(conn, account, cashier, total, Local::now().naive_local())
+    let transaction = execute_at(
+        conn,
+        account,
+        cashier,
+        terminal,
+        total,
+        Local::now().naive_local(),
+    )?;
+
+    if total < 0 {
+        metrics::count_payment();
+    }
+
+    Ok(transaction)
 }
 
 // Pagination reference: https://github.com/diesel-rs/diesel/blob/v1.3.0/examples/postgres/advanced-blog-cli/src/pagination.rs
 /// List all transactions of a account between the given datetimes
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
 pub fn get_by_account(
     conn: &DbConnection,
     account: &Account,
@@ -131,6 +148,7 @@ pub fn get_by_account(
     Ok(results)
 }
 
+#[tracing::instrument(level = "debug", skip_all, fields(account_id = %account.id))]
 pub fn get_by_account_and_id(
     conn: &DbConnection,
     account: &Account,
@@ -171,7 +189,7 @@ pub enum ValidationResult {
 fn validate_account(conn: &DbConnection, account: &Account) -> ServiceResult<ValidationResult> {
     use crate::core::schema::transaction::dsl;
 
-    conn.build_transaction().serializable().run(|| {
+    serializable_transaction(conn, || {
         let account = Account::get(conn, &account.id)?;
 
         let results = dsl::transaction
@@ -211,6 +229,7 @@ fn validate_account(conn: &DbConnection, account: &Account) -> ServiceResult<Val
 }
 
 /// List all accounts with validation erros of their credit to their transactions
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn validate_all(conn: &DbConnection) -> ServiceResult<HashMap<Uuid, ValidationResult>> {
     let accounts = Account::all(conn)?;
 
@@ -227,6 +246,7 @@ pub fn validate_all(conn: &DbConnection) -> ServiceResult<HashMap<Uuid, Validati
 
 impl Transaction {
     /// Assign products with amounts to this transaction
+    #[tracing::instrument(level = "debug", skip_all, fields(transaction_id = %self.id))]
     pub fn add_products(
         &self,
         conn: &DbConnection,
@@ -268,6 +288,7 @@ impl Transaction {
     }
 
     /// Remove products with amounts from this transaction
+    #[tracing::instrument(level = "debug", skip_all, fields(transaction_id = %self.id))]
     pub fn remove_products(
         &self,
         conn: &DbConnection,
@@ -309,6 +330,7 @@ impl Transaction {
     }
 
     /// List assigned products with amounts of this transaction
+    #[tracing::instrument(level = "debug", skip_all, fields(transaction_id = %self.id))]
     pub fn get_products(&self, conn: &DbConnection) -> ServiceResult<Vec<(Product, i32)>> {
         use crate::core::schema::transaction_product::dsl;
 
@@ -323,6 +345,7 @@ impl Transaction {
             .collect())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     pub fn all(conn: &DbConnection) -> ServiceResult<Vec<Transaction>> {
         use crate::core::schema::transaction::dsl;
 
@@ -393,6 +416,7 @@ pub fn generate_transactions(
                     conn,
                     account,
                     None,
+                    None,
                     avg_up,
                     date_time + Duration::seconds(seconds),
                 )?;
@@ -403,6 +427,7 @@ pub fn generate_transactions(
                 conn,
                 account,
                 None,
+                None,
                 -price,
                 date_time + Duration::seconds(seconds),
             )?;
@@ -419,3 +444,48 @@ pub fn generate_transactions(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{test_connection, Permission};
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_execute() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut account = Account::create(&conn, "Test", Permission::DEFAULT)?;
+        execute(&conn, &mut account, None, None, 500)?;
+        let transaction = execute(&conn, &mut account, None, None, -200)?;
+
+        assert_eq!(transaction.before_credit, 500);
+        assert_eq!(transaction.after_credit, 300);
+        assert_eq!(Account::get(&conn, &account.id)?.credit, 300);
+        assert!(matches!(
+            validate_account(&conn, &account)?,
+            ValidationResult::Ok
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+    fn test_execute_minimum_credit() -> ServiceResult<()> {
+        let conn = test_connection();
+
+        let mut account = Account::create(&conn, "Test", Permission::DEFAULT)?;
+        account.minimum_credit = -100;
+        account.update(&conn)?;
+
+        assert!(execute(&conn, &mut account, None, None, -200).is_err());
+        assert_eq!(Account::get(&conn, &account.id)?.credit, 0);
+
+        // Top ups are always allowed
+        execute(&conn, &mut account, None, None, 50)?;
+        assert_eq!(Account::get(&conn, &account.id)?.credit, 50);
+
+        Ok(())
+    }
+}
@@ -1,20 +1,265 @@
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 use sublime_fuzzy as fuzzy;
-use uuid::Uuid;
+
+use crate::core::ServiceResult;
+
+/// Reference type for uuids that can be stored in the current database
+#[cfg(not(feature = "sqlite"))]
+pub use uuid::Uuid;
+
+/// Reference type for uuids that can be stored in the current database
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::Uuid;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::fmt;
+    use std::ops::Deref;
+
+    /// Uuid that can be stored in sqlite
+    ///
+    /// Diesel only supports `uuid::Uuid` for postgres, so sqlite needs its own type.
+    #[derive(
+        Debug,
+        Clone,
+        Copy,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        Default,
+        Serialize,
+        Deserialize,
+        AsExpression,
+        FromSqlRow,
+    )]
+    #[serde(transparent)]
+    #[sql_type = "crate::core::sql_types::Uuid"]
+    pub struct Uuid(uuid::Uuid);
+
+    impl Uuid {
+        pub fn new_v4() -> Uuid {
+            Uuid(uuid::Uuid::new_v4())
+        }
+
+        pub fn parse_str(input: &str) -> Result<Uuid, uuid::parser::ParseError> {
+            uuid::Uuid::parse_str(input).map(Uuid)
+        }
+
+        pub fn encode_buffer() -> [u8; uuid::adapter::Urn::LENGTH] {
+            uuid::Uuid::encode_buffer()
+        }
+    }
+
+    impl Deref for Uuid {
+        type Target = uuid::Uuid;
+
+        fn deref(&self) -> &uuid::Uuid {
+            &self.0
+        }
+    }
+
+    impl fmt::Display for Uuid {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+}
 
 /// Reference type for money values
 pub type Money = i32;
 
 /// Reference type to the current database implementation
+#[cfg(not(feature = "sqlite"))]
 pub type DB = diesel::pg::Pg;
 
+/// Reference type to the current database implementation
+#[cfg(feature = "sqlite")]
+pub type DB = diesel::sqlite::Sqlite;
+
 /// Reference type to the current database connection
+#[cfg(not(feature = "sqlite"))]
 pub type DbConnection = PgConnection;
 
+/// Reference type to the current database connection
+#[cfg(feature = "sqlite")]
+pub type DbConnection = SqliteConnection;
+
 /// Reference type to the threaded pool of the current database connection
 pub type Pool = r2d2::Pool<ConnectionManager<DbConnection>>;
 
+/// Setup every new sqlite connection of the pool
+///
+/// Concurrent writers wait for each other instead of failing immediately.
+#[cfg(feature = "sqlite")]
+#[derive(Debug)]
+struct SqliteConnectionSetup;
+
+#[cfg(feature = "sqlite")]
+impl r2d2::CustomizeConnection<SqliteConnection, r2d2::Error> for SqliteConnectionSetup {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        use diesel::connection::SimpleConnection;
+
+        conn.batch_execute("PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL;")
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Create the connection pool for the given database url
+///
+/// For sqlite the url is the path of the database file.
+pub fn create_pool(database_url: &str) -> ServiceResult<Pool> {
+    let manager = ConnectionManager::<DbConnection>::new(database_url);
+    let builder = r2d2::Pool::builder();
+
+    #[cfg(feature = "sqlite")]
+    let builder = builder.connection_customizer(Box::new(SqliteConnectionSetup));
+
+    Ok(builder.build(manager)?)
+}
+
+/// Run `f` in a transaction that is isolated from all concurrent transactions
+///
+/// Postgres uses the serializable isolation level. Sqlite takes the write lock at the start of the
+/// transaction, so all writing transactions run one after another.
+#[cfg(not(feature = "sqlite"))]
+pub fn serializable_transaction<T, F>(conn: &DbConnection, f: F) -> ServiceResult<T>
+where
+    F: FnOnce() -> ServiceResult<T>,
+{
+    conn.build_transaction().serializable().run(f)
+}
+
+/// Run `f` in a transaction that is isolated from all concurrent transactions
+///
+/// Postgres uses the serializable isolation level. Sqlite takes the write lock at the start of the
+/// transaction, so all writing transactions run one after another.
+#[cfg(feature = "sqlite")]
+pub fn serializable_transaction<T, F>(conn: &DbConnection, f: F) -> ServiceResult<T>
+where
+    F: FnOnce() -> ServiceResult<T>,
+{
+    conn.immediate_transaction(f)
+}
+
+/// Run the reads of `f` on a consistent snapshot of the database
+///
+/// Postgres uses a read only transaction with the repeatable read isolation level. Sqlite sees a
+/// snapshot of the database from the first read of the transaction on.
+#[cfg(not(feature = "sqlite"))]
+pub fn snapshot_transaction<T, F>(conn: &DbConnection, f: F) -> ServiceResult<T>
+where
+    F: FnOnce() -> ServiceResult<T>,
+{
+    conn.build_transaction()
+        .repeatable_read()
+        .read_only()
+        .run(f)
+}
+
+/// Run the reads of `f` on a consistent snapshot of the database
+///
+/// Postgres uses a read only transaction with the repeatable read isolation level. Sqlite sees a
+/// snapshot of the database from the first read of the transaction on.
+#[cfg(feature = "sqlite")]
+pub fn snapshot_transaction<T, F>(conn: &DbConnection, f: F) -> ServiceResult<T>
+where
+    F: FnOnce() -> ServiceResult<T>,
+{
+    conn.transaction(f)
+}
+
+/// Give every postgres test connection its own schema of the throwaway database
+///
+/// Tests run in parallel, so they must not see the rows of each other.
+#[cfg(all(test, not(feature = "sqlite")))]
+#[derive(Debug)]
+struct TestSchema(String);
+
+#[cfg(all(test, not(feature = "sqlite")))]
+impl TestSchema {
+    fn new() -> TestSchema {
+        TestSchema(format!("test_{}", uuid::Uuid::new_v4().to_simple()))
+    }
+
+    fn setup(&self, conn: &PgConnection) -> QueryResult<()> {
+        use diesel::connection::SimpleConnection;
+
+        conn.batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS {0}; SET search_path TO {0};",
+            self.0
+        ))
+    }
+}
+
+#[cfg(all(test, not(feature = "sqlite")))]
+impl r2d2::CustomizeConnection<PgConnection, r2d2::Error> for TestSchema {
+    fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+        self.setup(conn).map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Url of the throwaway postgres database for tests
+///
+/// The database tests are ignored without the sqlite feature, run them with
+/// `cargo test -- --include-ignored`.
+#[cfg(all(test, not(feature = "sqlite")))]
+fn test_database_url() -> String {
+    std::env::var("TEST_DATABASE_URL")
+        .expect("TEST_DATABASE_URL has to be set to run the database tests on postgres")
+}
+
+/// Connect to an empty database for tests
+///
+/// Sqlite uses a new in memory database. Postgres uses a new schema of the throwaway database
+/// `TEST_DATABASE_URL`.
+#[cfg(test)]
+pub fn test_connection() -> DbConnection {
+    #[cfg(feature = "sqlite")]
+    let conn = DbConnection::establish(":memory:").expect("Cannot create sqlite database");
+
+    #[cfg(not(feature = "sqlite"))]
+    let conn = {
+        let conn = DbConnection::establish(&test_database_url())
+            .expect("Cannot connect to TEST_DATABASE_URL");
+        TestSchema::new()
+            .setup(&conn)
+            .expect("Cannot create test schema");
+        conn
+    };
+
+    crate::core::migrations::run(&conn).expect("Cannot run migrations");
+
+    conn
+}
+
+/// Create a pool on an empty database for tests, see `test_connection`
+///
+/// Every connection to `:memory:` opens its own database, so the sqlite pool must not open a
+/// second one.
+#[cfg(test)]
+pub fn test_pool() -> Pool {
+    #[cfg(feature = "sqlite")]
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .build(ConnectionManager::<DbConnection>::new(":memory:"))
+        .expect("Cannot create sqlite database");
+
+    #[cfg(not(feature = "sqlite"))]
+    let pool = r2d2::Pool::builder()
+        .max_size(1)
+        .connection_customizer(Box::new(TestSchema::new()))
+        .build(ConnectionManager::<DbConnection>::new(test_database_url()))
+        .expect("Cannot connect to TEST_DATABASE_URL");
+
+    crate::core::migrations::run(&pool.get().expect("Cannot connect to test database"))
+        .expect("Cannot run migrations");
+
+    pool
+}
+
 /// Generate a new random uuid
 pub fn generate_uuid() -> Uuid {
     Uuid::new_v4()
@@ -56,3 +301,8 @@ pub fn fuzzy_vec_match(search: &str, values: &[String]) -> Option<Vec<String>> {
 
     Some(vec)
 }
+
+/// Compare two secrets in constant time, the time only depends on their length
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
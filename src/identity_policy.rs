@@ -4,14 +4,19 @@ use actix_identity::CookieIdentityPolicy;
 use actix_identity::Identity;
 use actix_identity::IdentityPolicy;
 use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::header;
 use actix_web::{web, Error, FromRequest, HttpRequest};
 use chrono::Duration;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
 use futures::future::{err, ok, Ready};
 use futures::prelude::*;
+use std::net::IpAddr;
 
 use crate::core::{
-    env, Account, DbConnection, Pool, ServiceError, ServiceResult, Session, AUTH_COOKIE_NAME,
+    authentication_totp, config, Account, DbConnection, Pool, ServiceError, ServiceResult, Session,
+    Terminal, AUTH_COOKIE_NAME,
 };
+use crate::tls;
 
 pub enum Action {
     FORBIDDEN,
@@ -26,13 +31,13 @@ macro_rules! login_required {
             if acc.account.permission >= $permission {
                 acc
             } else {
-                return Ok(actix_web::HttpResponse::Forbidden().finish());
+                return Err(crate::core::ServiceError::InsufficientPrivileges);
             }
         } else {
             // no retrieved session is equal to no session -> login
             match $action {
                 Action::FORBIDDEN => {
-                    return Ok(actix_web::HttpResponse::Forbidden().finish());
+                    return Err(crate::core::ServiceError::Unauthorized);
                 }
                 Action::REDIRECT => {
                     return Ok(HttpResponse::Found()
@@ -44,9 +49,9 @@ macro_rules! login_required {
     };
 }
 #[macro_export]
-macro_rules! login_or_client_cert_required {
+macro_rules! login_or_terminal_required {
     ($request:ident, $account:ident, $permission:path, $action:path) => {
-        if crate::identity_policy::is_client_cert_present($request) {
+        if crate::identity_policy::get_terminal(&$request, false)?.is_some() {
             None
         } else {
             if let RetrievedAccount::Acc(acc) = $account {
@@ -54,13 +59,13 @@ macro_rules! login_or_client_cert_required {
                 if acc.account.permission >= $permission {
                     Some(acc)
                 } else {
-                    return Ok(actix_web::HttpResponse::Forbidden().finish());
+                    return Err(crate::core::ServiceError::InsufficientPrivileges);
                 }
             } else {
                 // no retrieved session is equal to no session -> login
                 match $action {
                     Action::FORBIDDEN => {
-                        return Ok(actix_web::HttpResponse::Forbidden().finish());
+                        return Err(crate::core::ServiceError::Unauthorized);
                     }
                     Action::REDIRECT => {
                         return Ok(HttpResponse::Found()
@@ -74,12 +79,14 @@ macro_rules! login_or_client_cert_required {
 }
 
 #[macro_export]
-macro_rules! client_cert_required {
+macro_rules! terminal_required {
     ($request:ident, $action:path) => {
-        if !crate::identity_policy::is_client_cert_present($request) {
+        if let Some(terminal) = crate::identity_policy::get_terminal(&$request, true)? {
+            terminal
+        } else {
             match $action {
                 Action::FORBIDDEN => {
-                    return Ok(actix_web::HttpResponse::Forbidden().finish());
+                    return Err(crate::identity_policy::terminal_required_error());
                 }
                 Action::REDIRECT => {
                     return Ok(HttpResponse::Found()
@@ -91,11 +98,106 @@ macro_rules! client_cert_required {
     };
 }
 
-pub fn is_client_cert_present(request: HttpRequest) -> bool {
-    if let Some(auth_header) = request.headers().get("X-Client-Cert") {
-        auth_header.to_str().unwrap_or_else(|_| "") == env::API_ACCESS_KEY.as_str()
+/// Get the terminal that sent the request
+///
+/// Terminals are identified by their client certificate, see `client_cert_mode`, or by their api
+/// key in the `Authorization: Bearer <key>` header. If `strict` is set and a `client_cert_mode` is
+/// configured, only the client certificate is accepted.
+///
+/// Missing, unknown and revoked credentials result in `None`.
+pub fn get_terminal(request: &HttpRequest, strict: bool) -> ServiceResult<Option<Terminal>> {
+    let config = config::get();
+
+    if let Some(subject) = tls::client_cert_subject(request, &config) {
+        let conn = &get_connection(request)?;
+        return authenticated(Terminal::authenticate_certificate(conn, &subject));
+    }
+    if strict && config.client_cert_required() {
+        return Ok(None);
+    }
+
+    let key = match request.headers().get(header::AUTHORIZATION) {
+        Some(auth_header) => match auth_header.to_str()?.strip_prefix("Bearer ") {
+            Some(key) => key.trim().to_owned(),
+            None => return Ok(None),
+        },
+        None => return Ok(None),
+    };
+
+    let conn = &get_connection(request)?;
+    authenticated(Terminal::authenticate(conn, &key))
+}
+
+/// Ip address of the client that sent the request
+///
+/// Requests of the `trusted_proxies` use their `X-Forwarded-For` header. The header is read from
+/// the right, as proxies append the address they received the request from, and the first
+/// address that is not a trusted proxy is used. Entries left of it are set by the client.
+pub fn client_ip(request: &HttpRequest) -> String {
+    forwarded_client_ip(request, &config::get().trusted_proxies())
+}
+
+fn forwarded_client_ip(request: &HttpRequest, trusted_proxies: &[IpAddr]) -> String {
+    let mut ip = match request.peer_addr() {
+        Some(peer) => peer.ip(),
+        None => return "unknown".to_owned(),
+    };
+
+    if trusted_proxies.contains(&ip) {
+        let forwarded: Vec<&str> = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .filter_map(|header| header.to_str().ok())
+            .flat_map(|header| header.split(','))
+            .collect();
+
+        for entry in forwarded.into_iter().rev() {
+            match entry.trim().parse::<IpAddr>() {
+                Ok(addr) => {
+                    ip = addr;
+                    if !trusted_proxies.contains(&addr) {
+                        break;
+                    }
+                }
+                // A malformed entry cannot be attributed, keep the last trusted hop
+                Err(_) => break,
+            }
+        }
+    }
+
+    ip.to_string()
+}
+
+/// Error of `terminal_required!` for requests without a valid terminal
+pub fn terminal_required_error() -> ServiceError {
+    if config::get().client_cert_required() {
+        ServiceError::ClientCertRequired
     } else {
-        false
+        ServiceError::TerminalRequired
+    }
+}
+
+fn get_connection(
+    request: &HttpRequest,
+) -> ServiceResult<PooledConnection<ConnectionManager<DbConnection>>> {
+    let pool: &web::Data<Pool> = match request.app_data() {
+        Some(pool) => pool,
+        None => {
+            return Err(ServiceError::InternalServerError(
+                "r2d2 error",
+                "Can not extract database from request".to_owned(),
+            ))
+        }
+    };
+
+    Ok(pool.get()?)
+}
+
+fn authenticated(result: ServiceResult<Terminal>) -> ServiceResult<Option<Terminal>> {
+    match result {
+        Ok(terminal) => Ok(Some(terminal)),
+        Err(ServiceError::Unauthorized) => Ok(None),
+        Err(e) => Err(e),
     }
 }
 
@@ -107,15 +209,15 @@ pub struct DbIdentityPolicy {
 impl DbIdentityPolicy {
     /// Create a new instance
     pub fn new() -> DbIdentityPolicy {
-        let secure = env::BASE_URL.as_str().starts_with("https");
+        let config = config::get();
 
         DbIdentityPolicy {
-            cookie_policy: CookieIdentityPolicy::new(env::COOKIE_ENCRYPTION_KEY.as_bytes())
+            cookie_policy: CookieIdentityPolicy::new(config.cookie_encryption_key.as_bytes())
                 .name(AUTH_COOKIE_NAME)
                 .path("/")
-                .domain(env::DOMAIN.as_str())
+                .domain(config.domain.as_str())
                 .max_age_time(Duration::days(1))
-                .secure(secure),
+                .secure(config.secure_cookies()),
         }
     }
 
@@ -143,6 +245,7 @@ impl DbIdentityPolicy {
         let logged_account = LoggedAccount {
             session_id,
             account,
+            second_factor_pending: session.second_factor_pending,
         };
 
         session.refresh();
@@ -207,15 +310,46 @@ impl IdentityPolicy for DbIdentityPolicy {
 pub struct LoggedAccount {
     pub session_id: String,
     pub account: Account,
+    /// The password is verified but the second factor is not, the session grants no access yet
+    #[serde(default)]
+    pub second_factor_pending: bool,
 }
 
 /// Represents an optional for a retrieved account for the middleware to return
+///
+/// Sessions with a pending second factor are retrieved as `Nothing`, see `PendingAccount`.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum RetrievedAccount {
     Acc(LoggedAccount),
     Nothing,
 }
 
+/// Represents an optional account that has to verify its second factor to finish the login
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PendingAccount {
+    Acc(LoggedAccount),
+    Nothing,
+}
+
+/// Parse the logged account of the request identity
+fn logged_account_from_request(
+    req: &HttpRequest,
+    pl: &mut Payload,
+) -> Result<Option<LoggedAccount>, Error> {
+    let request_identity = Identity::from_request(req, pl).now_or_never().unwrap()?;
+
+    match request_identity.identity() {
+        Some(identity) => match serde_json::from_str(&identity) {
+            Ok(account) => Ok(Some(account)),
+            Err(e) => {
+                let srv_err: ServiceError = e.into();
+                Err(srv_err.actix())
+            }
+        },
+        None => Ok(None),
+    }
+}
+
 /// Extract `RetrievedAccount` from http request
 impl FromRequest for RetrievedAccount {
     type Error = Error;
@@ -223,37 +357,60 @@ impl FromRequest for RetrievedAccount {
     type Config = ();
 
     fn from_request(req: &HttpRequest, pl: &mut Payload) -> Self::Future {
-        let request_identity = match Identity::from_request(req, pl).now_or_never().unwrap() {
-            Ok(val) => val,
-            Err(e) => return err(e),
-        };
+        match logged_account_from_request(req, pl) {
+            Ok(Some(account)) if !account.second_factor_pending => {
+                ok(RetrievedAccount::Acc(account))
+            }
+            Ok(_) => ok(RetrievedAccount::Nothing),
+            Err(e) => err(e),
+        }
+    }
+}
 
-        if let Some(identity) = request_identity.identity() {
-            let account: LoggedAccount = match serde_json::from_str(&identity) {
-                Ok(val) => val,
-                Err(e) => {
-                    let srv_err: ServiceError = e.into();
-                    return err(srv_err.actix());
-                }
-            };
-            return ok(RetrievedAccount::Acc(account));
+/// Extract `PendingAccount` from http request
+impl FromRequest for PendingAccount {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, pl: &mut Payload) -> Self::Future {
+        match logged_account_from_request(req, pl) {
+            Ok(Some(account)) if account.second_factor_pending => ok(PendingAccount::Acc(account)),
+            Ok(_) => ok(PendingAccount::Nothing),
+            Err(e) => err(e),
         }
-        ok(RetrievedAccount::Nothing)
     }
 }
 
 /// Helper functions for permission check
 impl LoggedAccount {
     /// Create a new logged account instance
+    ///
+    /// If the account needs a second factor, the session stays pending until
+    /// `verify_second_factor` is called.
     pub fn new(conn: &DbConnection, account: Account) -> ServiceResult<LoggedAccount> {
-        let session = Session::create(&conn, &account.id)?;
+        let second_factor_pending = authentication_totp::is_required(&conn, &account)?;
+        let session = Session::create(&conn, &account.id, second_factor_pending)?;
 
         Ok(LoggedAccount {
             session_id: session.id,
             account,
+            second_factor_pending,
         })
     }
 
+    /// Finish the login after the second factor has been verified
+    ///
+    /// The pending session is replaced by a new one, so only the new session id grants access.
+    pub fn verify_second_factor(&mut self, conn: &DbConnection) -> ServiceResult<()> {
+        Session::get(&conn, &self.session_id)?.delete(&conn)?;
+        let session = Session::create(&conn, &self.account.id, false)?;
+
+        self.session_id = session.id;
+        self.second_factor_pending = false;
+        Ok(())
+    }
+
     /// Save the logged account to the identity storage
     pub fn save(&self, id: Identity) -> ServiceResult<()> {
         let s = serde_json::to_string(self)?;
@@ -271,3 +428,42 @@ impl LoggedAccount {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_forwarded_client_ip() {
+        let proxies: Vec<IpAddr> = vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+
+        // The client sets a fake address, the proxy appends the real one
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4, 203.0.113.7")
+            .to_http_request();
+        assert_eq!(forwarded_client_ip(&request, &proxies), "203.0.113.7");
+
+        // Chained trusted proxies are skipped
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4, 203.0.113.7, 10.0.0.2")
+            .to_http_request();
+        assert_eq!(forwarded_client_ip(&request, &proxies), "203.0.113.7");
+
+        // Untrusted peers cannot set the header
+        let request = TestRequest::default()
+            .peer_addr("203.0.113.8:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4")
+            .to_http_request();
+        assert_eq!(forwarded_client_ip(&request, &proxies), "203.0.113.8");
+
+        // Malformed entries stop at the last trusted hop
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Forwarded-For", "1.2.3.4, garbage")
+            .to_http_request();
+        assert_eq!(forwarded_client_ip(&request, &proxies), "10.0.0.1");
+    }
+}
@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate diesel;
 #[macro_use]
+extern crate diesel_migrations;
+#[macro_use]
 extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
@@ -10,19 +12,30 @@ extern crate handlebars;
 extern crate uuid;
 #[macro_use]
 extern crate hex_literal;
+#[macro_use]
+extern crate tracing;
 
-use clap::App;
-use diesel::r2d2::{self, ConnectionManager};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use std::io::Read;
 
 mod api;
 mod core;
 mod identity_policy;
-mod init_server;
 mod server;
+#[cfg(test)]
+mod tests;
+mod tls;
 mod web;
 
-use crate::core::{authentication_password, env, Account, DbConnection, Pool, ServiceResult};
+use crate::core::config::{self, Config};
+use crate::core::transactions::{self, ValidationResult};
+use crate::core::{
+    authentication_password, authentication_totp, backup, create_pool, migrations, reports,
+    storage, Account, Permission, Pool, ServiceError, ServiceResult, Terminal,
+};
 use server::start_server;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
 #[actix_rt::main]
 async fn main() -> ServiceResult<()> {
@@ -41,31 +54,174 @@ async fn main() -> ServiceResult<()> {
 
 async fn init() -> ServiceResult<()> {
     dotenv::dotenv().ok();
-    std::env::set_var("RUST_LOG", "actix_web=info,actix_server=info");
-    env_logger::init();
-
-    // Setup database connection
-    let manager = ConnectionManager::<DbConnection>::new(env::DATABASE_URL.as_str());
-    let pool = r2d2::Pool::builder().build(manager)?;
 
-    let _matches = App::new("ascii-pay")
+    let matches = App::new("ascii-pay")
         .version("1.0")
         .author("Lars Westermann <lars-westermann@live.de>")
         .author("Felix Wittwer <dev@felixwittwer.de>")
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .help("Config file, defaults to 'config.toml' if it exists")
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(SubCommand::with_name("serve").about("Start the web server (default)"))
+        .subcommand(SubCommand::with_name("migrate").about("Run all pending database migrations"))
+        .subcommand(
+            SubCommand::with_name("create-admin")
+                .about("Create an admin account, the password is read from stdin")
+                .arg(
+                    Arg::with_name("LOGIN")
+                        .help("Username of the new account")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Full name of the new account, defaults to the login")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("create-terminal")
+                .about("Register a payment terminal and print its api key")
+                .arg(
+                    Arg::with_name("NAME")
+                        .help("Name of the new terminal")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("location")
+                        .long("location")
+                        .value_name("LOCATION")
+                        .help("Where the terminal is placed")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("certificate-subject")
+                        .long("certificate-subject")
+                        .value_name("COMMON_NAME")
+                        .help("Common name of the client certificate of the terminal")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("Check the transaction history of all accounts against their balance"),
+        )
+        .subcommand(
+            SubCommand::with_name("send-reports")
+                .about("Send the monthly report to all accounts that opted in"),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export the database and all product images")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("Target file, use '-' for stdout")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reset-password")
+                .about("Create a new invitation link to reset the password of an account")
+                .arg(
+                    Arg::with_name("LOGIN")
+                        .help("Username, mail address or account number of the account")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("stdin")
+                        .long("stdin")
+                        .help("Set the new password from stdin instead of creating a link"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import a backup into an empty database")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("Backup file created by 'export', use '-' for stdin")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Only check the backup without changing the database"),
+                ),
+        )
         .get_matches();
 
-    // Check if admin exists, create otherwise
-    check_admin_user_exisits(&pool).await?;
+    // Load and validate the configuration before anything else uses it
+    let config = Config::load(matches.value_of("config"))?;
+    let warnings = config.validate()?;
+    config::init(config)?;
 
-    // Setup web server
-    start_server(pool).await?;
+    init_logging()?;
+    for warning in &warnings {
+        warn!("{}", warning);
+    }
 
-    Ok(())
+    storage::init()?;
+
+    // Setup database connection
+    let pool = create_pool(&config::get().database_url)?;
+
+    // Refuse to work on a database with an unknown schema
+    match matches.subcommand() {
+        ("migrate", Some(_)) => {}
+        ("serve", _) | ("", _) => check_migrations(&pool, config::get().auto_migrate)?,
+        _ => check_migrations(&pool, false)?,
+    }
+
+    match matches.subcommand() {
+        ("migrate", Some(_)) => run_migrations(&pool),
+        ("create-admin", Some(args)) => create_admin(&pool, args),
+        ("create-terminal", Some(args)) => create_terminal(&pool, args),
+        ("validate", Some(_)) => validate_transactions(&pool),
+        ("send-reports", Some(_)) => send_reports(&pool),
+        ("export", Some(args)) => export_backup(&pool, args),
+        ("import", Some(args)) => import_backup(&pool, args),
+        ("reset-password", Some(args)) => reset_password(&pool, args),
+        _ => serve(pool).await,
+    }
+}
+
+/// Setup the global log subscriber, the log level and format are read from the config
+fn init_logging() -> ServiceResult<()> {
+    let config = config::get();
+    let filter = EnvFilter::try_new(config.log_filter.as_str())
+        .map_err(|e| ServiceError::InternalServerError("Invalid log filter", format!("{}", e)))?;
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+
+    let result = if config.log_format == "text" {
+        builder.try_init()
+    } else {
+        builder.json().try_init()
+    };
+
+    result.map_err(|e| ServiceError::InternalServerError("Logging error", format!("{}", e)))
 }
 
-/// Check if a initial user exists. Otherwise create a new one
-async fn check_admin_user_exisits(pool: &Pool) -> ServiceResult<()> {
-    let conn = &pool.get().unwrap();
+/// Start the web server
+async fn serve(pool: Pool) -> ServiceResult<()> {
+    eprintln!("Configuration:\n{}", config::get().summary());
+
+    check_admin_user_exists(&pool)?;
+    invalidate_admin_sessions(&pool)?;
+
+    start_server(pool).await
+}
+
+/// Check if an admin with a password exists, otherwise nobody can log in
+fn check_admin_user_exists(pool: &Pool) -> ServiceResult<()> {
+    let conn = &pool.get()?;
 
     let admin_with_password_exists = Account::all(&conn)?
         .iter()
@@ -73,11 +229,237 @@ async fn check_admin_user_exisits(pool: &Pool) -> ServiceResult<()> {
         .any(|a| authentication_password::has_password(&conn, a).unwrap_or(false));
 
     if !admin_with_password_exists {
-        println!("You seem to have started the server on an empty database. We'll now create the initial superuser.");
-        init_server::start_server(pool).await?;
+        warn!(
+            "No admin account with a password exists, create one with the 'create-admin' command"
+        );
+    }
+
+    Ok(())
+}
+
+/// Log out admins without a second factor if it is required, their sessions were created before
+fn invalidate_admin_sessions(pool: &Pool) -> ServiceResult<()> {
+    if !config::get().admin_totp_required {
+        return Ok(());
+    }
+
+    let conn = &pool.get()?;
+
+    let deleted = authentication_totp::delete_admin_sessions_without_second_factor(&conn)?;
+    if deleted > 0 {
+        info!(deleted, "Deleted admin sessions without second factor");
+    }
+
+    Ok(())
+}
+
+/// Read a password from the first line of stdin
+fn read_password() -> ServiceResult<String> {
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(&['\n', '\r'][..]);
+
+    if password.is_empty() {
+        return Err(ServiceError::BadRequest(
+            "Empty password",
+            "The password is read from stdin and must not be empty".to_owned(),
+        ));
+    }
+
+    Ok(password.to_owned())
+}
+
+/// Check if the database schema matches this binary, pending migrations are applied if `migrate` is set
+fn check_migrations(pool: &Pool, migrate: bool) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let status = migrations::status(&conn)?;
+    status.check_not_newer()?;
+
+    if migrate {
+        for version in migrations::run(&conn)? {
+            info!(version, "Applied migration");
+        }
+        Ok(())
+    } else {
+        status.check_up_to_date().map_err(|e| match e {
+            ServiceError::InternalServerError("Pending migrations", versions) => {
+                ServiceError::InternalServerError(
+                    "Pending migrations",
+                    format!("{}, apply them with the 'migrate' command", versions),
+                )
+            }
+            e => e,
+        })
+    }
+}
+
+/// Run all pending database migrations
+fn run_migrations(pool: &Pool) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let applied = migrations::run(&conn)?;
+    for version in &applied {
+        eprintln!("Applied migration {}", version);
+    }
+    eprintln!("Database is up to date");
+
+    Ok(())
+}
+
+/// Create a new admin account with a password from stdin
+fn create_admin(pool: &Pool, args: &ArgMatches) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let login = args.value_of("LOGIN").unwrap_or_default();
+    let name = args.value_of("name").unwrap_or(login);
+    let password = read_password()?;
+
+    if Account::find_by_login(&conn, login).is_ok() {
+        return Err(ServiceError::BadRequest(
+            "Account exists",
+            format!("An account with the login '{}' already exists", login),
+        ));
+    }
+
+    let mut account = Account::create(&conn, name, Permission::ADMIN)?;
+    account.username = Some(login.to_owned());
+    account.update(&conn)?;
+    authentication_password::register(&conn, &account, &password)?;
+
+    eprintln!("Created admin account '{}'", login);
+
+    Ok(())
+}
+
+/// Register a new terminal, the api key is printed to stdout
+fn create_terminal(pool: &Pool, args: &ArgMatches) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let name = args.value_of("NAME").unwrap_or_default();
+    let location = args.value_of("location").map(|l| l.to_owned());
+    let certificate_subject = args.value_of("certificate-subject").map(|s| s.to_owned());
+
+    let (_, key) = Terminal::create(&conn, name, location, certificate_subject)?;
+
+    eprintln!(
+        "Created terminal '{}', the api key is only shown once:",
+        name
+    );
+    println!("{}", key);
+
+    Ok(())
+}
+
+/// Validate the transaction history of all accounts, fails if an account is invalid
+fn validate_transactions(pool: &Pool) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let results = transactions::validate_all(&conn)?;
+    let mut invalid = 0;
+
+    for account in Account::all(&conn)? {
+        match results.get(&account.id) {
+            Some(ValidationResult::Ok) | None => {}
+            Some(result) => {
+                invalid += 1;
+                eprintln!("{:<40} {}", account.name, serde_json::to_string(result)?);
+            }
+        }
+    }
+
+    if invalid > 0 {
+        return Err(ServiceError::InternalServerError(
+            "Invalid accounts",
+            format!("{} of {} accounts are invalid", invalid, results.len()),
+        ));
+    }
+
+    eprintln!("All {} accounts are valid", results.len());
+
+    Ok(())
+}
+
+/// Send the monthly reports via mail
+fn send_reports(pool: &Pool) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let count = reports::send_reports(&conn)?;
+    eprintln!("Sent {} reports", count);
+
+    Ok(())
+}
+
+/// Reset the password of an account, either with an invitation link or a password from stdin
+fn reset_password(pool: &Pool, args: &ArgMatches) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let login = args.value_of("LOGIN").unwrap_or_default();
+    let account = Account::find_by_login(&conn, login).map_err(|_| {
+        ServiceError::BadRequest(
+            "Account not found",
+            format!("No account with the login '{}' exists", login),
+        )
+    })?;
+
+    if args.is_present("stdin") {
+        let password = read_password()?;
+        authentication_password::register(&conn, &account, &password)?;
+        eprintln!("Changed password of '{}'", account.name);
+    } else {
+        let link = authentication_password::create_invitation_link(&conn, &account)?;
+        println!("{}/register/{}", config::get().base_url, link);
+    }
+
+    Ok(())
+}
+
+/// Export the database and all product images to a file
+fn export_backup(pool: &Pool, args: &ArgMatches) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let backup = backup::export(&conn)?;
+    let data = serde_json::to_string(&backup)?;
+
+    match args.value_of("FILE") {
+        Some("-") | None => println!("{}", data),
+        Some(file) => {
+            std::fs::write(file, data)?;
+            eprintln!("Exported backup to '{}'", file);
+        }
+    }
+
+    Ok(())
+}
+
+/// Import a backup file into an empty database
+fn import_backup(pool: &Pool, args: &ArgMatches) -> ServiceResult<()> {
+    let conn = &pool.get()?;
+
+    let file = args.value_of("FILE").unwrap_or("-");
+    let data = match file {
+        "-" => {
+            let mut data = String::new();
+            std::io::stdin().read_to_string(&mut data)?;
+            data
+        }
+        file => std::fs::read_to_string(file)?,
+    };
+    let backup: backup::Backup = serde_json::from_str(&data)
+        .map_err(|e| ServiceError::BadRequest("Invalid backup", format!("{}", e)))?;
+
+    let dry_run = args.is_present("dry-run");
+    let summary = backup::import(&conn, &backup, dry_run)?;
+
+    for (table, count) in &summary.tables {
+        eprintln!("{:<40} {:>8}", table, count);
+    }
+    eprintln!("{:<40} {:>8}", "images", summary.images);
 
-        println!("Init server finished, continue to normal server");
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    if dry_run {
+        eprintln!("Backup is valid, nothing was imported (dry run)");
+    } else {
+        eprintln!("Imported backup from '{}'", file);
     }
 
     Ok(())
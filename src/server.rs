@@ -1,15 +1,28 @@
+use std::sync::{Arc, Mutex};
+
+use actix_http::error::DispatchError;
+use actix_http::{HttpService, Protocol};
 use actix_identity::IdentityService;
-use actix_web::{middleware, web, App, HttpServer};
+use actix_service::{fn_service, map_config, pipeline_factory, Service, ServiceFactory};
+use actix_web::dev::{AppConfig, Body, ServiceRequest, ServiceResponse};
+use actix_web::http::uri::Scheme;
+use actix_web::{web, App, Error, HttpServer};
 use chrono::NaiveDateTime;
+use futures::TryFutureExt;
 use handlebars::{Context, Handlebars, Helper, Output, RenderContext, RenderError};
+use tokio::net::TcpStream;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
 
 use crate::api as module_api;
-use crate::core::{env, Pool, ServiceResult};
+use crate::core::{config, Pool, ServiceResult};
 use crate::identity_policy::DbIdentityPolicy;
+use crate::tls::{self, ClientCertificate};
 use crate::web as module_web;
 
 // TODO: REMOVE FOR PRODUCTION!
 use crate::web::proxy as module_proxy;
+use crate::web::proxy::sse::Broadcaster;
 
 /// Helper function for handlebars. Converts cents to euros
 fn currency_helper(
@@ -45,12 +58,8 @@ fn format_datetime_helper(
     Ok(())
 }
 
-/// Start a new actix server with the given database pool
-pub async fn start_server(pool: Pool) -> ServiceResult<()> {
-    // Read config params from env
-
-    let address = format!("{}:{}", env::HOST.as_str(), *env::PORT);
-
+/// Create the handlebars registry with all templates and helper functions
+pub fn create_handlebars() -> Handlebars<'static> {
     let mut handlebars = Handlebars::new();
 
     // Set handlebars template directory
@@ -62,34 +71,123 @@ pub async fn start_server(pool: Pool) -> ServiceResult<()> {
     handlebars.register_helper("currency", Box::new(currency_helper));
     handlebars.register_helper("format_datetime", Box::new(format_datetime_helper));
 
+    handlebars
+}
+
+/// Create the actix app with all middlewares and routes
+///
+/// Used for every worker of the server and by the integration tests.
+pub fn create_app(
+    pool: Pool,
+    handlebars: web::Data<Handlebars<'static>>,
+    broadcaster: Arc<Mutex<Broadcaster>>,
+) -> App<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse<Body>,
+        Error = Error,
+        InitError = (),
+    >,
+    Body,
+> {
+    App::new()
+        // Move database pool
+        .data(pool)
+        // TODO: REMOVE FOR PRODUCTION!
+        .data(broadcaster)
+        // Set handlebars reference
+        .app_data(handlebars)
+        // Count and time all requests for the metrics endpoint
+        .wrap_fn(module_web::metrics::observe_request)
+        // Set identity service for encrypted cookies
+        .wrap(IdentityService::new(DbIdentityPolicy::new()))
+        // Log all requests with their request id
+        .wrap_fn(module_web::logging::log_request)
+        // Register api module
+        .configure(module_api::init)
+        // TODO: REMOVE FOR PRODUCTION!
+        .configure(module_proxy::init)
+        // Register admin ui module
+        .configure(module_web::init)
+}
+
+/// Start a new actix server with the given database pool
+pub async fn start_server(pool: Pool) -> ServiceResult<()> {
+    // Read config params from env
+
+    let config = config::get();
+    let address = format!("{}:{}", config.host, config.port);
+
     // Move handlebars reference to actix
-    let handlebars_ref = web::Data::new(handlebars);
+    let handlebars_ref = web::Data::new(create_handlebars());
 
     // TODO: REMOVE FOR PRODUCTION!
     let broadcaster = module_proxy::setup();
 
-    HttpServer::new(move || {
-        App::new()
-            // Move database pool
-            .data(pool.clone())
-            // TODO: REMOVE FOR PRODUCTION!
-            .data(broadcaster.clone())
-            // Set handlebars reference
-            .app_data(handlebars_ref.clone())
-            // Logger
-            .wrap(middleware::Logger::default())
-            // Set identity service for encrypted cookies
-            .wrap(IdentityService::new(DbIdentityPolicy::new()))
-            // Register api module
-            .configure(module_api::init)
-            // TODO: REMOVE FOR PRODUCTION!
-            .configure(module_proxy::init)
-            // Register admin ui module
-            .configure(module_web::init)
-    })
-    .bind(address)?
-    .run()
-    .await?;
+    let tls_config = match tls::load_server_config(&config)? {
+        Some(tls_config) => tls_config,
+        None => {
+            HttpServer::new(move || {
+                create_app(pool.clone(), handlebars_ref.clone(), broadcaster.clone())
+            })
+            .bind(address)?
+            .run()
+            .await?;
+
+            return Ok(());
+        }
+    };
+
+    // `HttpServer` does not expose the tls session, so the tls connections are accepted manually
+    // to attach the client certificate to every request of the connection.
+    let acceptor = TlsAcceptor::from(tls_config);
+    let authority = address.clone();
+    let mut server = actix_server::Server::build().bind("ascii-pay", address, move || {
+        let acceptor = acceptor.clone();
+        let authority = authority.clone();
+        let app = create_app(pool.clone(), handlebars_ref.clone(), broadcaster.clone()).wrap_fn(
+            move |mut request, service| {
+                tls::set_listener_uri(request.head_mut(), Scheme::HTTPS, &authority);
+                service.call(request)
+            },
+        );
+
+        pipeline_factory(fn_service(move |io: TcpStream| {
+            let peer_addr = io.peer_addr().ok();
+            acceptor
+                .accept(io)
+                .map_ok(move |io| (io, Protocol::Http1, peer_addr))
+                .map_err(DispatchError::Io)
+        }))
+        .and_then(
+            HttpService::build()
+                .on_connect(|io: &TlsStream<TcpStream>| {
+                    ClientCertificate::from_session(io.get_ref().1)
+                })
+                // The scheme and the address are set by `set_listener_uri` instead of the config
+                .finish(map_config(app, |_| AppConfig::default())),
+        )
+    })?;
+
+    if config.http_redirect_port != 0 {
+        let redirect_address = format!("{}:{}", config.host, config.http_redirect_port);
+        let authority = redirect_address.clone();
+        server = server.bind("ascii-pay-redirect", redirect_address, move || {
+            let authority = authority.clone();
+            let app = App::new()
+                .wrap_fn(move |mut request, service| {
+                    tls::set_listener_uri(request.head_mut(), Scheme::HTTP, &authority);
+                    service.call(request)
+                })
+                .default_service(web::to(tls::redirect_to_https));
+            HttpService::build()
+                .finish(map_config(app, |_| AppConfig::default()))
+                .tcp()
+        })?;
+    }
+
+    server.run().await?;
 
     Ok(())
 }
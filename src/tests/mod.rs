@@ -0,0 +1,113 @@
+//! Integration tests that run requests against the complete actix app.
+//!
+//! Every test gets its own empty database, see `test_pool`. With the `sqlite` feature this is an
+//! in memory database, so the tests run without any external service.
+mod products;
+mod terminal;
+
+use actix_http::Request;
+use actix_web::dev::{Body, Service, ServiceResponse};
+use actix_web::http::{header, StatusCode};
+use actix_web::{test, web, Error};
+use chrono::Local;
+use serde_json::Value;
+
+use crate::core::{
+    authentication_barcode, authentication_nfc, config, test_pool, transactions, Account, Money,
+    Permission, Pool, Product, ServiceResult, Terminal,
+};
+use crate::server::{create_app, create_handlebars};
+use crate::web::proxy as module_proxy;
+
+/// Boot the app on a new test database
+pub async fn init_app() -> (
+    Pool,
+    impl Service<Request = Request, Response = ServiceResponse<Body>, Error = Error>,
+) {
+    config::init_test();
+
+    let pool = test_pool();
+    let app = test::init_service(create_app(
+        pool.clone(),
+        web::Data::new(create_handlebars()),
+        module_proxy::setup(),
+    ))
+    .await;
+
+    (pool, app)
+}
+
+/// Send `body` as json to `uri` like the terminal with the api `key`
+pub async fn post_json<S>(app: &mut S, key: &str, uri: &str, body: Value) -> (StatusCode, Value)
+where
+    S: Service<Request = Request, Response = ServiceResponse<Body>, Error = Error>,
+{
+    let request = test::TestRequest::post()
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {}", key))
+        .set_json(&body)
+        .to_request();
+
+    let response = test::call_service(app, request).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Accounts and products the tests work with
+pub struct Fixtures {
+    /// Authenticates with the barcode `alice`, has a credit of 10.00
+    pub alice: Account,
+    /// Authenticates with the nfc card `bob`, the card gets a new key on the first read
+    pub bob: Account,
+    /// Authenticates with the barcode `carol`, has a credit of 1.00 and may not go below 0
+    pub carol: Account,
+    /// Costs 1.50, has the barcode `coffee`
+    pub coffee: Product,
+    /// A registered terminal
+    pub terminal: Terminal,
+    /// The api key of `terminal`
+    pub key: String,
+}
+
+impl Fixtures {
+    pub fn create(pool: &Pool) -> ServiceResult<Fixtures> {
+        let conn = &pool.get()?;
+
+        let mut alice = Account::create(conn, "Alice", Permission::DEFAULT)?;
+        authentication_barcode::register(conn, &alice, "alice")?;
+        transactions::execute(conn, &mut alice, None, None, 1000)?;
+
+        let bob = Account::create(conn, "Bob", Permission::DEFAULT)?;
+        authentication_nfc::register(conn, &bob, "bob", true)?;
+
+        let mut carol = Account::create(conn, "Carol", Permission::DEFAULT)?;
+        authentication_barcode::register(conn, &carol, "carol")?;
+        transactions::execute(conn, &mut carol, None, None, 100)?;
+
+        let mut coffee = Product::create(conn, "Coffee", None)?;
+        coffee.barcode = Some("coffee".to_owned());
+        coffee.update(conn)?;
+        coffee.add_price(conn, Local::now().naive_local(), 150)?;
+
+        let (terminal, key) = Terminal::create(conn, "Kitchen", None, None)?;
+
+        Ok(Fixtures {
+            alice: Account::get(conn, &alice.id)?,
+            bob,
+            carol: Account::get(conn, &carol.id)?,
+            coffee,
+            terminal,
+            key,
+        })
+    }
+}
+
+/// Read the current credit of the account from the database
+///
+/// The test pool has a single connection, so it must not be held during requests.
+pub fn credit(pool: &Pool, account: &Account) -> ServiceResult<Money> {
+    let conn = &pool.get()?;
+    Ok(Account::get(conn, &account.id)?.credit)
+}
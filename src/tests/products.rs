@@ -0,0 +1,128 @@
+//! Product management through the api, eg. by the admin tools of a terminal
+use actix_web::http::{header, StatusCode};
+use actix_web::test;
+use chrono::{NaiveDate, Weekday};
+
+use super::{init_app, post_json, Fixtures};
+use crate::core::{generate_uuid, Availability, Product, ServiceResult};
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_update_keeps_availabilities() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let availability = Availability {
+        id: generate_uuid(),
+        date_start: Some(NaiveDate::from_ymd(2020, 1, 1)),
+        date_end: None,
+        weekdays: vec![Weekday::Mon, Weekday::Fri],
+    };
+    {
+        let conn = &pool.get()?;
+        let mut coffee = Product::get(conn, &fixtures.coffee.id)?;
+        coffee.add_availability(conn, availability.clone())?;
+    }
+    let uri = format!("/api/v1/product/{}", fixtures.coffee.id);
+
+    // Clients that do not know the schedule must not remove it
+    let (status, _) = post_json(
+        &mut app,
+        &fixtures.key,
+        &uri,
+        json!({"id": fixtures.coffee.id, "name": "Espresso"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let coffee = Product::get(&*pool.get()?, &fixtures.coffee.id)?;
+    assert_eq!(coffee.name, "Espresso");
+    assert_eq!(coffee.availabilities, vec![availability]);
+
+    let (status, _) = post_json(
+        &mut app,
+        &fixtures.key,
+        &uri,
+        json!({"id": fixtures.coffee.id, "name": "Espresso", "availabilities": []}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let coffee = Product::get(&*pool.get()?, &fixtures.coffee.id)?;
+    assert!(coffee.availabilities.is_empty());
+
+    // Windows that can never match would hide the product
+    for availability in &[
+        json!({"date_start": "2020-02-01", "date_end": "2020-01-01"}),
+        json!({"weekdays": []}),
+    ] {
+        let (status, body) = post_json(
+            &mut app,
+            &fixtures.key,
+            &uri,
+            json!({"id": fixtures.coffee.id, "name": "Espresso", "availabilities": [availability]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["fields"][0]["field"], "availabilities");
+    }
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_create_with_invalid_parent() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let variant = {
+        let conn = &pool.get()?;
+        let mut variant = Product::create(conn, "Large coffee", None)?;
+        variant.parent = Some(fixtures.coffee.id);
+        variant.update(conn)?;
+        variant
+    };
+
+    // Variants cannot have variants, the new product must not be created
+    let request = test::TestRequest::put()
+        .uri("/api/v1/products")
+        .header(header::AUTHORIZATION, format!("Bearer {}", fixtures.key))
+        .set_json(
+            &json!({"id": generate_uuid(), "name": "Extra large coffee", "parent": variant.id}),
+        )
+        .to_request();
+    let response = test::call_service(&mut app, request).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let products = Product::all(&*pool.get()?)?;
+    assert!(products.iter().all(|p| p.name != "Extra large coffee"));
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_failed_update_changes_nothing() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let tea = Product::create(&*pool.get()?, "Tea", None)?;
+    let uri = format!("/api/v1/product/{}", tea.id);
+
+    // The barcode belongs to the coffee
+    let (status, _) = post_json(
+        &mut app,
+        &fixtures.key,
+        &uri,
+        json!({"id": tea.id, "name": "Green tea", "barcode": "coffee"}),
+    )
+    .await;
+    assert!(!status.is_success());
+
+    let tea = Product::get(&*pool.get()?, &tea.id)?;
+    assert_eq!(tea.name, "Tea");
+    assert_eq!(tea.barcode, None);
+
+    Ok(())
+}
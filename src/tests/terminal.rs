@@ -0,0 +1,303 @@
+//! The flow of a payment terminal: `/identify` -> `/transaction/token` -> `/transaction/payment`
+use actix_web::http::StatusCode;
+use serde_json::Value;
+
+use super::{credit, init_app, post_json, Fixtures};
+use crate::core::authentication_nfc::{create_response, str_to_bytes};
+use crate::core::ServiceResult;
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_barcode_payment() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "barcode", "code": "coffee"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "product");
+    assert_eq!(body["product"]["id"], fixtures.coffee.id.to_string());
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "barcode", "code": "alice"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "account");
+    assert_eq!(body["account"]["id"], fixtures.alice.id.to_string());
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/token",
+        json!({"amount": -150, "method": {"type": "barcode", "code": "alice"}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "authorized");
+    let token = body["token"].clone();
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/payment",
+        json!({
+            "amount": -150,
+            "token": token,
+            "products": {fixtures.coffee.id.to_string(): 1}
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["account"]["credit"], 850);
+    assert_eq!(body["transaction"]["total"], -150);
+    assert_eq!(
+        body["transaction"]["terminal_id"],
+        fixtures.terminal.id.to_string()
+    );
+    assert_eq!(credit(&pool, &fixtures.alice)?, 850);
+
+    // Every token can only be used once
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/payment",
+        json!({"amount": -150, "token": token, "products": {}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_token");
+    assert_eq!(credit(&pool, &fixtures.alice)?, 850);
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_nfc_challenge_response() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    // The first read writes a new key and secret to the card
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "nfc", "id": "bob"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "write-key");
+    let key = body["key"].as_str().unwrap().to_owned();
+    let secret = str_to_bytes(body["secret"].as_str().unwrap())?;
+
+    // Afterwards the card has to answer a challenge
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "nfc", "id": "bob"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "authentication-needed");
+    assert_eq!(body["key"], key);
+    let challenge = body["challenge"].as_str().unwrap().to_owned();
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({
+            "type": "nfc-secret",
+            "id": "bob",
+            "challenge": challenge,
+            "response": create_response(&secret, &challenge)?
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "account");
+    assert_eq!(body["account"]["id"], fixtures.bob.id.to_string());
+
+    // A wrong response is rejected
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({
+            "type": "nfc-secret",
+            "id": "bob",
+            "challenge": challenge,
+            "response": create_response(&[0xFF], &challenge)?
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/token",
+        json!({"amount": 500, "method": {"type": "nfc", "id": "bob"}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "authentication-needed");
+    let challenge = body["challenge"].as_str().unwrap().to_owned();
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/token",
+        json!({
+            "amount": 500,
+            "method": {
+                "type": "nfc-secret",
+                "id": "bob",
+                "challenge": challenge,
+                "response": create_response(&secret, &challenge)?
+            }
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["type"], "authorized");
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/payment",
+        json!({"amount": 500, "token": body["token"], "products": {}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["account"]["credit"], 500);
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_minimum_credit_rejection() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/token",
+        json!({"amount": -150, "method": {"type": "barcode", "code": "carol"}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/payment",
+        json!({
+            "amount": -150,
+            "token": body["token"],
+            "products": {fixtures.coffee.id.to_string(): 1}
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+    assert_eq!(body["code"], "insufficient_credit");
+    assert_eq!(credit(&pool, &fixtures.carol)?, fixtures.carol.credit);
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_terminal_required() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let mut fixtures = Fixtures::create(&pool)?;
+
+    let request = actix_web::test::TestRequest::post()
+        .uri("/api/v1/identify")
+        .set_json(&json!({"type": "barcode", "code": "alice"}))
+        .to_request();
+    let response = actix_web::test::call_service(&mut app, request).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: Value = serde_json::from_slice(&actix_web::test::read_body(response).await)?;
+    assert_eq!(body["code"], "terminal_required");
+
+    let (status, body) = post_json(
+        &mut app,
+        "unknown",
+        "/api/v1/identify",
+        json!({"type": "barcode", "code": "alice"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "terminal_required");
+
+    // A revoked key is rejected immediately
+    {
+        let conn = &pool.get()?;
+        fixtures.terminal.revoke(conn)?;
+    }
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "barcode", "code": "alice"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "terminal_required");
+
+    Ok(())
+}
+
+#[actix_rt::test]
+#[cfg_attr(not(feature = "sqlite"), ignore = "needs TEST_DATABASE_URL")]
+async fn test_invalid_requests() -> ServiceResult<()> {
+    let (pool, mut app) = init_app().await;
+    let fixtures = Fixtures::create(&pool)?;
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "barcode"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "bad_request");
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/identify",
+        json!({"type": "barcode", "code": "unknown"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "not_found");
+
+    let (status, body) = post_json(
+        &mut app,
+        &fixtures.key,
+        "/api/v1/transaction/payment",
+        json!({"amount": -150, "token": "invalid", "products": {}}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_token");
+
+    Ok(())
+}
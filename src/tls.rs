@@ -0,0 +1,407 @@
+//! Tls termination with rustls and the client certificates of the payment terminals.
+//!
+//! The server certificate is reloaded when its files change, eg. after a renewal, so the server
+//! does not need a restart.
+//!
+//! Terminals are identified by the common name of their certificate subject. The subject is
+//! either read from the verified certificate of the tls connection (`client_cert_mode = "tls"`) or
+//! from a header set by a trusted reverse proxy that verified the certificate (`"header"`).
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use actix_web::dev::RequestHead;
+use actix_web::http::uri::{PathAndQuery, Scheme, Uri};
+use actix_web::{http, HttpRequest, HttpResponse};
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use rustls::{
+    AllowAnyAnonymousOrAuthenticatedClient, Certificate, ClientHello, NoClientAuth, PrivateKey,
+    ResolvesServerCert, RootCertStore, ServerConfig, ServerSession, Session,
+};
+
+use crate::core::config::{self, Config};
+use crate::core::{ServiceError, ServiceResult};
+
+/// Interval to check the certificate files for changes
+const RELOAD_INTERVAL_SECONDS: u64 = 10;
+
+/// Client certificate of a tls connection, it is attached to every request of the connection
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+    /// Common name of the certificate subject, `None` without a valid certificate
+    pub subject: Option<String>,
+}
+
+impl ClientCertificate {
+    /// Read the client certificate of a tls session, rustls already verified it against the CA
+    pub fn from_session(session: &ServerSession) -> ClientCertificate {
+        let subject = session
+            .get_peer_certificates()
+            .and_then(|certs| certs.into_iter().next())
+            .and_then(|cert| certificate_common_name(&cert));
+
+        ClientCertificate { subject }
+    }
+}
+
+/// Extract the common name of the subject of a DER encoded certificate
+fn certificate_common_name(cert: &Certificate) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(&cert.0).ok()?;
+    let common_name = cert.subject().iter_common_name().next()?;
+    common_name.as_str().ok().map(|cn| cn.to_owned())
+}
+
+/// Extract the common name of a distinguished name forwarded by a reverse proxy
+///
+/// Supports the RFC 4514 form `CN=kitchen,O=ascii`, the OpenSSL form `/O=ascii/CN=kitchen` and a
+/// plain common name.
+pub fn common_name(subject: &str) -> Option<String> {
+    let subject = subject.trim();
+    if !subject.contains('=') {
+        return if subject.is_empty() {
+            None
+        } else {
+            Some(subject.to_owned())
+        };
+    }
+
+    let separator = if subject.starts_with('/') { '/' } else { ',' };
+    subject
+        .split(separator)
+        .filter_map(|part| {
+            let mut attribute = part.splitn(2, '=');
+            match (attribute.next(), attribute.next()) {
+                (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case("CN") => {
+                    Some(value.trim().to_owned())
+                }
+                _ => None,
+            }
+        })
+        .find(|value| !value.is_empty())
+}
+
+/// Get the common name of the client certificate of a request
+///
+/// In `header` mode the header is only trusted if the request comes from one of the
+/// `trusted_proxies`.
+pub fn client_cert_subject(request: &HttpRequest, config: &Config) -> Option<String> {
+    match config.client_cert_mode.as_str() {
+        "tls" => request
+            .extensions()
+            .get::<ClientCertificate>()
+            .and_then(|cert| cert.subject.clone()),
+        "header" => {
+            let peer = request.peer_addr()?;
+            if !config.trusted_proxies().contains(&peer.ip()) {
+                return None;
+            }
+
+            let header = request.headers().get(config.client_cert_header.as_str())?;
+            common_name(header.to_str().ok()?)
+        }
+        _ => None,
+    }
+}
+
+fn invalid_file(file: &str, reason: &str) -> ServiceError {
+    ServiceError::InternalServerError(
+        "Invalid tls configuration",
+        format!("Cannot load '{}': {}", file, reason),
+    )
+}
+
+fn open(file: &str) -> ServiceResult<BufReader<File>> {
+    File::open(file)
+        .map(BufReader::new)
+        .map_err(|e| invalid_file(file, &e.to_string()))
+}
+
+fn load_private_key(file: &str) -> ServiceResult<PrivateKey> {
+    let mut keys = pemfile::pkcs8_private_keys(&mut open(file)?)
+        .map_err(|_| invalid_file(file, "invalid pkcs8 private key"))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut open(file)?)
+            .map_err(|_| invalid_file(file, "invalid rsa private key"))?;
+    }
+
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| invalid_file(file, "no private key found"))
+}
+
+/// Server certificate that follows changes of its files
+pub struct ReloadingCertificate {
+    cert_file: String,
+    key_file: String,
+    current: RwLock<(Option<SystemTime>, CertifiedKey)>,
+}
+
+impl ReloadingCertificate {
+    /// Load the certificate chain and private key
+    pub fn load(cert_file: &str, key_file: &str) -> ServiceResult<ReloadingCertificate> {
+        let modified = last_modified(&[cert_file, key_file]);
+        let key = load_certified_key(cert_file, key_file)?;
+
+        Ok(ReloadingCertificate {
+            cert_file: cert_file.to_owned(),
+            key_file: key_file.to_owned(),
+            current: RwLock::new((modified, key)),
+        })
+    }
+
+    /// Load the files again if they changed since the last load, returns whether they changed
+    ///
+    /// The current certificate stays active if the new files are invalid, eg. if only one of
+    /// them has been replaced yet. They are loaded again with the next check.
+    pub fn reload(&self) -> ServiceResult<bool> {
+        let modified = last_modified(&[&self.cert_file, &self.key_file]);
+        if modified
+            == self
+                .current
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .0
+        {
+            return Ok(false);
+        }
+
+        let key = load_certified_key(&self.cert_file, &self.key_file)?;
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = (modified, key);
+
+        Ok(true)
+    }
+
+    /// Check the files for changes in a background thread
+    pub fn watch(self: Arc<Self>) {
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(RELOAD_INTERVAL_SECONDS));
+            match self.reload() {
+                Ok(true) => info!("Reloaded tls certificate '{}'", self.cert_file),
+                Ok(false) => {}
+                Err(e) => error!("Cannot reload tls certificate: {}", e),
+            }
+        });
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertificate {
+    fn resolve(&self, _: ClientHello) -> Option<CertifiedKey> {
+        let current = self.current.read().unwrap_or_else(PoisonError::into_inner);
+        Some(current.1.clone())
+    }
+}
+
+/// Latest modification time of the files, `None` if one of them is missing
+fn last_modified(files: &[&str]) -> Option<SystemTime> {
+    files
+        .iter()
+        .map(|file| fs::metadata(file).and_then(|m| m.modified()).ok())
+        .collect::<Option<Vec<_>>>()?
+        .into_iter()
+        .max()
+}
+
+fn load_certified_key(cert_file: &str, key_file: &str) -> ServiceResult<CertifiedKey> {
+    let certs = pemfile::certs(&mut open(cert_file)?)
+        .map_err(|_| invalid_file(cert_file, "invalid certificate"))?;
+    if certs.is_empty() {
+        return Err(invalid_file(cert_file, "no certificate found"));
+    }
+
+    let key = load_private_key(key_file)?;
+    let key = sign::any_supported_type(&key)
+        .map_err(|_| invalid_file(key_file, "unsupported private key"))?;
+
+    let certified_key = CertifiedKey::new(certs, Arc::new(key));
+    certified_key
+        .cross_check_end_entity_cert(None)
+        .map_err(|e| invalid_file(cert_file, &e.to_string()))?;
+
+    Ok(certified_key)
+}
+
+/// Create the rustls configuration of the server, `None` if tls is disabled
+///
+/// In `tls` mode clients may present a certificate of the `tls_client_ca_file`. Connections
+/// without a certificate are still accepted for the browser, the terminal routes reject them.
+pub fn load_server_config(config: &Config) -> ServiceResult<Option<Arc<ServerConfig>>> {
+    if !config.tls_enabled() {
+        return Ok(None);
+    }
+
+    let certificate = Arc::new(ReloadingCertificate::load(
+        &config.tls_cert_file,
+        &config.tls_key_file,
+    )?);
+
+    let mut server_config = if config.client_cert_mode == "tls" {
+        let mut roots = RootCertStore::empty();
+        let (valid, _) = roots
+            .add_pem_file(&mut open(&config.tls_client_ca_file)?)
+            .map_err(|_| invalid_file(&config.tls_client_ca_file, "invalid certificate"))?;
+        if valid == 0 {
+            return Err(invalid_file(
+                &config.tls_client_ca_file,
+                "no CA certificate found",
+            ));
+        }
+        ServerConfig::new(AllowAnyAnonymousOrAuthenticatedClient::new(roots))
+    } else {
+        ServerConfig::new(NoClientAuth::new())
+    };
+
+    server_config.cert_resolver = certificate.clone();
+    server_config.set_protocols(&[b"http/1.1".to_vec()]);
+
+    certificate.watch();
+
+    Ok(Some(Arc::new(server_config)))
+}
+
+/// Make the uri of a request of a custom listener absolute with `scheme` and `authority`
+///
+/// `HttpServer` passes the scheme and the address of the listener to the app with the `AppConfig`,
+/// which cannot be created outside of actix-web 2.0 (`AppConfig::new` is crate private). The
+/// `ConnectionInfo` reads the absolute uri in place of the `AppConfig`, so the `Forwarded`,
+/// `X-Forwarded-*` and `Host` headers still take precedence. Absolute uris of the client are kept.
+pub fn set_listener_uri(head: &mut RequestHead, scheme: Scheme, authority: &str) {
+    if head.uri.scheme().is_some() {
+        return;
+    }
+
+    let mut parts = head.uri.clone().into_parts();
+    parts.scheme = Some(scheme);
+    parts.authority = authority.parse().ok();
+    if parts.path_and_query.is_none() {
+        parts.path_and_query = Some(PathAndQuery::from_static("/"));
+    }
+
+    if let Ok(uri) = Uri::from_parts(parts) {
+        head.uri = uri;
+    }
+}
+
+/// Target of a plain http request on `base_url`, only the path and query are kept
+fn https_location(base_url: &str, request: &HttpRequest) -> String {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+
+    format!("{}{}", base_url.trim_end_matches('/'), path)
+}
+
+/// Handler of the `http_redirect_port` listener, redirects every request to https
+///
+/// The host of the request is ignored, so the listener cannot be used as an open redirect.
+pub async fn redirect_to_https(request: HttpRequest) -> HttpResponse {
+    let location = https_location(&config::get().base_url, &request);
+
+    HttpResponse::PermanentRedirect()
+        .header(http::header::LOCATION, location)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_set_listener_uri() {
+        use actix_web::dev::{AppConfig, ConnectionInfo};
+
+        let mut request = TestRequest::with_uri("/api/v1/products?all=true").to_srv_request();
+        set_listener_uri(request.head_mut(), Scheme::HTTPS, "0.0.0.0:8443");
+        assert_eq!(request.path(), "/api/v1/products");
+        assert_eq!(request.query_string(), "all=true");
+        {
+            let info = ConnectionInfo::get(request.head(), &AppConfig::default());
+            assert_eq!(info.scheme(), "https");
+            assert_eq!(info.host(), "0.0.0.0:8443");
+        }
+
+        let mut request = TestRequest::with_uri("/")
+            .header(http::header::HOST, "pay.example.org")
+            .to_srv_request();
+        set_listener_uri(request.head_mut(), Scheme::HTTPS, "0.0.0.0:8443");
+        let info = ConnectionInfo::get(request.head(), &AppConfig::default());
+        assert_eq!(info.scheme(), "https");
+        assert_eq!(info.host(), "pay.example.org");
+    }
+
+    #[test]
+    fn test_common_name() {
+        assert_eq!(
+            common_name("CN=kitchen,O=ascii"),
+            Some("kitchen".to_owned())
+        );
+        assert_eq!(
+            common_name("O=ascii, CN = kitchen"),
+            Some("kitchen".to_owned())
+        );
+        assert_eq!(
+            common_name("/O=ascii/CN=kitchen"),
+            Some("kitchen".to_owned())
+        );
+        assert_eq!(common_name("kitchen"), Some("kitchen".to_owned()));
+        assert_eq!(common_name("O=ascii"), None);
+        assert_eq!(common_name(""), None);
+    }
+
+    #[test]
+    fn test_header_subject() {
+        let config = Config {
+            client_cert_mode: "header".to_owned(),
+            trusted_proxies: "10.0.0.1, ::1".to_owned(),
+            ..Config::default()
+        };
+
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Client-Cert-Subject", "CN=kitchen,O=ascii")
+            .to_http_request();
+        assert_eq!(
+            client_cert_subject(&request, &config),
+            Some("kitchen".to_owned())
+        );
+
+        // Only trusted proxies may set the header
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.2:4000".parse().unwrap())
+            .header("X-Client-Cert-Subject", "CN=kitchen,O=ascii")
+            .to_http_request();
+        assert_eq!(client_cert_subject(&request, &config), None);
+
+        let config = Config {
+            client_cert_mode: "none".to_owned(),
+            ..config
+        };
+        let request = TestRequest::default()
+            .peer_addr("10.0.0.1:4000".parse().unwrap())
+            .header("X-Client-Cert-Subject", "CN=kitchen,O=ascii")
+            .to_http_request();
+        assert_eq!(client_cert_subject(&request, &config), None);
+    }
+
+    #[test]
+    fn test_https_location() {
+        let request = TestRequest::with_uri("/admin/accounts?search=alice")
+            .header("Host", "evil.example.com")
+            .to_http_request();
+        assert_eq!(
+            https_location("https://pay.example.com/", &request),
+            "https://pay.example.com/admin/accounts?search=alice"
+        );
+
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(
+            https_location("https://pay.example.com", &request),
+            "https://pay.example.com/"
+        );
+    }
+}
@@ -1,13 +1,12 @@
 use crate::core::{
     authentication_barcode, authentication_nfc, authentication_password, fuzzy_vec_match, Account,
-    Money, Permission, Pool, ServiceError, ServiceResult,
+    Money, Permission, Pool, ServiceError, ServiceResult, Session, Uuid,
 };
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
 use crate::web::utils::{EmptyToNone, HbData, IsJson, Search};
 use actix_web::{http, web, HttpRequest, HttpResponse};
 use handlebars::Handlebars;
-use uuid::Uuid;
 
 use std::collections::HashMap;
 
@@ -280,11 +279,17 @@ pub async fn post_account_edit(
     server_account.mail = new_mail;
     server_account.username = account.username.empty_to_none();
     server_account.account_number = account.account_number.empty_to_none();
+    let promoted = !server_account.permission.is_admin() && account.permission.is_admin();
     server_account.permission = account.permission;
     server_account.minimum_credit = (account.minimum_credit * 100.0) as Money;
 
     server_account.update(&conn)?;
 
+    // Sessions of the old permission did not need a second factor
+    if promoted {
+        Session::delete_by_account(&conn, &server_account.id, None)?;
+    }
+
     let mut reauth = false;
 
     for (key, value) in &account.extra {
@@ -454,7 +459,7 @@ pub async fn delete_get(
 ) -> ServiceResult<HttpResponse> {
     let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, "/admin/accounts")
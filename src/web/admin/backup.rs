@@ -0,0 +1,30 @@
+use crate::core::{backup, Permission, Pool, ServiceResult};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_required;
+use actix_web::{http, web, HttpResponse};
+use chrono::Local;
+
+/// GET route for `/admin/backup`
+///
+/// Downloads a full backup of the database and all product images
+pub async fn get_backup(
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    // The images are loaded from the storage backend, which may block on the network
+    let pool = pool.clone();
+    let backup = web::block(move || backup::export(&*pool.get()?)).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"ascii-pay-backup_{}.json\"",
+                Local::now().format("%Y-%m-%d_%H-%M-%S")
+            ),
+        )
+        .body(serde_json::to_string(&backup)?))
+}
@@ -0,0 +1,126 @@
+use crate::core::{catalogue, Permission, Pool, ServiceError, ServiceResult};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_required;
+use crate::web::utils::HbData;
+use actix_multipart::Multipart;
+use actix_web::{http, web, HttpRequest, HttpResponse};
+use futures::prelude::*;
+use handlebars::Handlebars;
+
+/// Helper to deserialize the export format
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+}
+
+/// Helper to deserialize a previewed import
+#[derive(Debug, Deserialize)]
+pub struct FormImport {
+    pub data: String,
+}
+
+/// GET route for `/admin/products/export`
+///
+/// Exports the product catalogue as csv or, with `format=json`, as json
+pub async fn get_products_export(
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+    query: web::Query<ExportQuery>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let entries = catalogue::export(&conn)?;
+
+    let (body, content_type, extension) = match query.format.as_deref() {
+        Some("json") => (
+            serde_json::to_string_pretty(&entries)?,
+            "application/json",
+            "json",
+        ),
+        _ => (catalogue::to_csv(&entries)?, "text/csv", "csv"),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"products.{}\"", extension),
+        )
+        .body(body))
+}
+
+/// GET route for `/admin/products/import`
+pub async fn get_products_import(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .render(&hb, "admin_product_import")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/admin/products/import`
+///
+/// Shows a preview of the uploaded catalogue without changing any product
+pub async fn post_products_import(
+    hb: web::Data<Handlebars<'_>>,
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+    mut payload: Multipart,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
+
+    let mut data: Vec<u8> = Vec::new();
+    while let Some(item) = payload.next().await {
+        let mut field = item?;
+
+        while let Some(chunk) = field.next().await {
+            data.extend_from_slice(&chunk?);
+        }
+    }
+
+    let data = String::from_utf8(data).map_err(|_| {
+        ServiceError::BadRequest(
+            "Invalid catalogue",
+            "The catalogue has to be utf-8 encoded".to_owned(),
+        )
+    })?;
+
+    let conn = &pool.get()?;
+
+    let diff = catalogue::diff(&conn, catalogue::parse(&data)?)?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("diff", &diff)
+        .with_data("data", &data)
+        .render(&hb, "admin_product_import")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/admin/products/import/apply`
+pub async fn post_products_import_apply(
+    pool: web::Data<Pool>,
+    logged_account: RetrievedAccount,
+    import: web::Form<FormImport>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let diff = catalogue::diff(&conn, catalogue::parse(&import.data)?)?;
+    catalogue::apply(&conn, &diff)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/admin/products")
+        .finish())
+}
@@ -1,5 +1,6 @@
 use crate::core::{
-    fuzzy_vec_match, Category, Money, Permission, Pool, ServiceError, ServiceResult,
+    fuzzy_vec_match, Category, CategoryNode, Money, Permission, Pool, ServiceError, ServiceResult,
+    Uuid,
 };
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
@@ -8,7 +9,6 @@ use actix_web::{http, web, HttpRequest, HttpResponse};
 use chrono::NaiveDateTime;
 use handlebars::Handlebars;
 use std::collections::HashMap;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FormCategory {
@@ -19,6 +19,8 @@ pub struct FormCategory {
     pub validity_start: NaiveDateTime,
     #[serde(rename = "price-value-create")]
     pub value: f32,
+    #[serde(default = "std::string::String::new")]
+    pub parent: String,
     #[serde(flatten)]
     pub extra: HashMap<String, String>,
 }
@@ -29,6 +31,9 @@ pub struct SearchCategory {
     pub category: Category,
     pub name_search: String,
     pub current_price_search: String,
+    /// Depth of this category in the category tree
+    pub depth: usize,
+    pub children: Vec<SearchCategory>,
 }
 
 impl SearchCategory {
@@ -54,8 +59,45 @@ impl SearchCategory {
             category,
             current_price_search: result.pop().expect(""),
             name_search: result.pop().expect(""),
+            depth: 0,
+            children: vec![],
         })
     }
+
+    /// Wrap a category tree
+    ///
+    /// A category is kept if it or any of its subcategories matches the search.
+    pub fn wrap_tree(node: CategoryNode, search: &str, depth: usize) -> Option<SearchCategory> {
+        let children: Vec<SearchCategory> = node
+            .children
+            .into_iter()
+            .filter_map(|c| SearchCategory::wrap_tree(c, search, depth + 1))
+            .collect();
+
+        let mut result = match SearchCategory::wrap(node.category.clone(), search) {
+            Some(result) => result,
+            None if !children.is_empty() => SearchCategory::wrap(node.category, "")?,
+            None => return None,
+        };
+
+        result.depth = depth;
+        result.children = children;
+
+        Some(result)
+    }
+
+    /// Flatten a category tree to a list in depth-first order
+    pub fn flatten(list: Vec<SearchCategory>) -> Vec<SearchCategory> {
+        let mut result = Vec::new();
+
+        for mut category in list {
+            let children = std::mem::replace(&mut category.children, Vec::new());
+            result.push(category);
+            result.extend(SearchCategory::flatten(children));
+        }
+
+        result
+    }
 }
 
 /// GET route for `/admin/categories`
@@ -77,9 +119,9 @@ pub async fn get_categories(
     };
 
     let lower_search = search.trim().to_ascii_lowercase();
-    let search_categories: Vec<SearchCategory> = Category::all(&conn)?
+    let search_categories: Vec<SearchCategory> = Category::tree(&conn)?
         .into_iter()
-        .filter_map(|c| SearchCategory::wrap(c, &lower_search))
+        .filter_map(|c| SearchCategory::wrap_tree(c, &lower_search, 0))
         .collect();
 
     if request.is_json() {
@@ -88,7 +130,7 @@ pub async fn get_categories(
         let body = HbData::new(&request)
             .with_account(logged_account)
             .with_data("search", &search)
-            .with_data("categories", &search_categories)
+            .with_data("categories", &SearchCategory::flatten(search_categories))
             .render(&hb, "admin_category_list")?;
 
         Ok(HttpResponse::Ok().body(body))
@@ -109,9 +151,15 @@ pub async fn get_category_edit(
 
     let category = Category::get(&conn, &Uuid::parse_str(&category_id)?)?;
 
+    let all_parents: Vec<Category> = Category::all(&conn)?
+        .into_iter()
+        .filter(|c| c.id != category.id && !c.ancestors.iter().any(|a| a.id == category.id))
+        .collect();
+
     let body = HbData::new(&request)
         .with_account(logged_account)
         .with_data("category", &category)
+        .with_data("parents", &all_parents)
         .render(&hb, "admin_category_edit")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -138,6 +186,11 @@ pub async fn post_category_edit(
     let mut server_category = Category::get(&conn, &Uuid::parse_str(&category_id)?)?;
 
     server_category.name = category.name.clone();
+    server_category.parent = if category.parent == "" {
+        None
+    } else {
+        Some(Uuid::parse_str(&category.parent)?)
+    };
 
     server_category.update(&conn)?;
 
@@ -170,12 +223,18 @@ pub async fn post_category_edit(
 pub async fn get_category_create(
     hb: web::Data<Handlebars<'_>>,
     logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
     let logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
+    let conn = &pool.get()?;
+
+    let all_parents = Category::all(&conn)?;
+
     let body = HbData::new(&request)
         .with_account(logged_account)
+        .with_data("parents", &all_parents)
         .render(&hb, "admin_category_create")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -193,6 +252,11 @@ pub async fn post_category_create(
 
     let mut server_category = Category::create(&conn, &category.name)?;
 
+    if category.parent != "" {
+        server_category.parent = Some(Uuid::parse_str(&category.parent)?);
+        server_category.update(&conn)?;
+    }
+
     if category.value != 0.0 {
         server_category.add_price(
             &conn,
@@ -217,7 +281,7 @@ pub async fn get_category_delete(
 ) -> ServiceResult<HttpResponse> {
     let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, "/admin/categories")
@@ -1,23 +1,64 @@
-use crate::core::{Permission, Pool, ServiceResult};
+use crate::core::{stats, Category, Permission, Pool, ServiceResult};
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
-use crate::web::utils::HbData;
+use crate::web::utils::{HbData, IsJson};
 use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, Local};
 use handlebars::Handlebars;
 
+/// Helper to deserialize dashboard queries
+#[derive(Debug, Deserialize)]
+pub struct DashboardQuery {
+    /// Number of recent transactions to show, defaults to 10
+    pub transactions: Option<i64>,
+}
+
 /// GET route for `/admin` if user is logged in
+///
+/// Returns the overview as json if requested with `application/json`
 pub async fn get_dashboard(
     pool: web::Data<Pool>,
     hb: web::Data<Handlebars<'_>>,
     logged_account: RetrievedAccount,
+    query: web::Query<DashboardQuery>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
-    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+    let action = request.redirect_type();
+    let logged_account = login_required!(logged_account, Permission::ADMIN, action);
+
+    let conn = &pool.get()?;
+
+    let overview = stats::get_overview(&conn, query.transactions.unwrap_or(10).max(0))?;
+
+    if request.is_json() {
+        return Ok(HttpResponse::Ok().json(&overview));
+    }
+
+    let to = Local::now().naive_local();
+    let from = to - Duration::days(30);
+
+    #[derive(Serialize)]
+    struct SoldCategory {
+        category: Category,
+        amount: i32,
+    }
 
-    let _conn = &pool.get()?;
+    let sold_categories: Vec<SoldCategory> = stats::get_sold_categories(&conn, &from, &to)?
+        .into_iter()
+        .map(|(category, amount)| SoldCategory { category, amount })
+        .collect();
 
     let body = HbData::new(&request)
         .with_account(logged_account)
+        .with_data("overview", &overview)
+        .with_data(
+            "stats",
+            &json!({
+                "from": from.format("%Y-%m-%d").to_string(),
+                "to": to.format("%Y-%m-%d").to_string(),
+            }),
+        )
+        .with_data("sold_categories", &sold_categories)
         .render(&hb, "admin_dashboard")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -0,0 +1,66 @@
+use crate::core::{auth_failures, Permission, Pool, ServiceResult, Uuid};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_required;
+use crate::web::utils::HbData;
+use actix_web::{http, web, HttpRequest, HttpResponse};
+use handlebars::Handlebars;
+
+/// Number of failed authentications in the audit list
+const AUDIT_LENGTH: i64 = 100;
+
+/// GET route for `/admin/lockouts`
+pub async fn get_lockouts(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let lockouts = auth_failures::lockouts(&conn)?;
+    let failures = auth_failures::latest(&conn, AUDIT_LENGTH)?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("lockouts", &lockouts)
+        .with_data("failures", &failures)
+        .render(&hb, "admin_lockout_list")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// GET route for `/admin/lockout/account/{account_id}`
+pub async fn get_unlock_account(
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    account_id: web::Path<String>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    auth_failures::unlock_account(&conn, &Uuid::parse_str(&account_id)?)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/admin/lockouts")
+        .finish())
+}
+
+/// GET route for `/admin/lockout/ip/{ip}`
+pub async fn get_unlock_ip(
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    ip: web::Path<String>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    auth_failures::unlock_ip(&conn, &ip)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/admin/lockouts")
+        .finish())
+}
@@ -1,114 +1,153 @@
 pub mod accounts;
+pub mod backup;
+pub mod catalogue;
 pub mod categories;
 pub mod cron;
 pub mod dashboard;
+pub mod lockouts;
 pub mod products;
 pub mod terminal;
+pub mod terminals;
 pub mod transactions;
 
 use actix_web::web;
 
+use crate::web::metrics::{resource, scope};
+
 /// Setup routes for admin ui
 pub fn init(config: &mut web::ServiceConfig) {
     config.service(
-        web::scope("/admin")
-            .service(web::resource("").route(web::get().to(dashboard::get_dashboard)))
+        scope("/admin")
+            .service(resource("").route(web::get().to(dashboard::get_dashboard)))
+            .service(resource("/backup").route(web::get().to(backup::get_backup)))
             // Setup account mangement related routes
-            .service(web::resource("/accounts").route(web::get().to(accounts::get_accounts)))
+            .service(resource("/accounts").route(web::get().to(accounts::get_accounts)))
             .service(
-                web::resource("/account/create")
+                resource("/account/create")
                     .route(web::post().to(accounts::post_account_create))
                     .route(web::get().to(accounts::get_account_create)),
             )
             .service(
-                web::resource("/account/delete/{account_id}")
-                    .route(web::get().to(accounts::delete_get)),
+                resource("/account/delete/{account_id}").route(web::get().to(accounts::delete_get)),
             )
             .service(
-                web::resource("/account/invite/{account_id}")
-                    .route(web::get().to(accounts::invite_get)),
+                resource("/account/invite/{account_id}").route(web::get().to(accounts::invite_get)),
             )
             .service(
-                web::resource("/account/revoke/{account_id}")
-                    .route(web::get().to(accounts::revoke_get)),
+                resource("/account/revoke/{account_id}").route(web::get().to(accounts::revoke_get)),
             )
             .service(
-                web::resource("/account/remove-nfc/{account_id}")
+                resource("/account/remove-nfc/{account_id}")
                     .route(web::get().to(accounts::remove_nfc_get)),
             )
             .service(
-                web::resource("/account/remove-barcode/{account_id}")
+                resource("/account/remove-barcode/{account_id}")
                     .route(web::get().to(accounts::remove_barcode_get)),
             )
             .service(
-                web::resource("/account/{account_id}")
+                resource("/account/{account_id}")
                     .route(web::post().to(accounts::post_account_edit))
                     .route(web::get().to(accounts::get_account_edit)),
             )
             // Setup product mangement related routes
-            .service(web::resource("/products").route(web::get().to(products::get_products)))
+            .service(resource("/products").route(web::get().to(products::get_products)))
+            .service(
+                resource("/products/export").route(web::get().to(catalogue::get_products_export)),
+            )
+            .service(
+                resource("/products/import")
+                    .route(web::post().to(catalogue::post_products_import))
+                    .route(web::get().to(catalogue::get_products_import)),
+            )
+            .service(
+                resource("/products/import/apply")
+                    .route(web::post().to(catalogue::post_products_import_apply)),
+            )
             .service(
-                web::resource("/product/create")
+                resource("/product/create")
                     .route(web::post().to(products::post_product_create))
                     .route(web::get().to(products::get_product_create)),
             )
             .service(
-                web::resource("/product/delete/{product_id}")
+                resource("/product/delete/{product_id}")
                     .route(web::get().to(products::get_product_delete)),
             )
             .service(
-                web::resource("/product/remove-image/{product_id}")
+                resource("/product/remove-image/{product_id}")
                     .route(web::get().to(products::get_product_remove_image)),
             )
             .service(
-                web::resource("/product/upload-image/{product_id}")
+                resource("/product/upload-image/{product_id}")
                     .route(web::post().to(products::post_product_upload_image)),
             )
             .service(
-                web::resource("/product/{product_id}")
+                resource("/product/{product_id}")
                     .route(web::post().to(products::post_product_edit))
                     .route(web::get().to(products::get_product_edit)),
             )
             // Setup categories mangement related routes
-            .service(web::resource("/categories").route(web::get().to(categories::get_categories)))
+            .service(resource("/categories").route(web::get().to(categories::get_categories)))
             .service(
-                web::resource("/category/create")
+                resource("/category/create")
                     .route(web::post().to(categories::post_category_create))
                     .route(web::get().to(categories::get_category_create)),
             )
             .service(
-                web::resource("/category/delete/{category_id}")
+                resource("/category/delete/{category_id}")
                     .route(web::get().to(categories::get_category_delete)),
             )
             .service(
-                web::resource("/category/{category_id}")
+                resource("/category/{category_id}")
                     .route(web::post().to(categories::post_category_edit))
                     .route(web::get().to(categories::get_category_edit)),
             )
             // Setup transaction mangement related routes
             .service(
-                web::resource("/transactions/generate/{account_id}")
+                resource("/transactions/generate/{account_id}")
                     .route(web::post().to(transactions::post_transaction_generate_random))
                     .route(web::get().to(transactions::get_transaction_generate_random)),
             )
             .service(
-                web::resource("/transactions/validate")
+                resource("/transactions/validate")
                     .route(web::get().to(transactions::get_transactions_validate)),
             )
             .service(
-                web::resource("/transactions/{account_id}")
+                resource("/transactions/{account_id}")
                     .route(web::get().to(transactions::get_transactions)),
             )
             .service(
-                web::resource("/transaction/execute/{account_id}")
+                resource("/transaction/execute/{account_id}")
                     .route(web::post().to(transactions::post_execute_transaction)),
             )
             .service(
-                web::resource("/transaction/{account_id}/{transaction_id}")
+                resource("/transaction/{account_id}/{transaction_id}")
                     .route(web::get().to(transactions::get_transaction_details)),
             )
-            .service(web::resource("/terminal").route(web::get().to(terminal::get_terminal)))
+            .service(resource("/terminal").route(web::get().to(terminal::get_terminal)))
+            // Setup terminal registry related routes
+            .service(resource("/terminals").route(web::get().to(terminals::get_terminals)))
+            .service(
+                resource("/terminal/create")
+                    .route(web::post().to(terminals::post_terminal_create))
+                    .route(web::get().to(terminals::get_terminal_create)),
+            )
+            .service(
+                resource("/terminal/revoke/{terminal_id}")
+                    .route(web::get().to(terminals::get_terminal_revoke)),
+            )
+            .service(
+                resource("/terminal/{terminal_id}")
+                    .route(web::post().to(terminals::post_terminal_edit))
+                    .route(web::get().to(terminals::get_terminal_edit)),
+            )
+            // Setup lockout related routes
+            .service(resource("/lockouts").route(web::get().to(lockouts::get_lockouts)))
+            .service(
+                resource("/lockout/account/{account_id}")
+                    .route(web::get().to(lockouts::get_unlock_account)),
+            )
+            .service(resource("/lockout/ip/{ip}").route(web::get().to(lockouts::get_unlock_ip)))
             // Setup cronjob routes
-            .service(web::resource("/cron/reports").route(web::get().to(cron::send_reports))),
+            .service(resource("/cron/reports").route(web::get().to(cron::send_reports))),
     );
 }
@@ -1,19 +1,17 @@
 use crate::core::{
-    fuzzy_vec_match, Category, DbConnection, Money, Permission, Pool, Product, ServiceError,
-    ServiceResult,
+    all_weekdays, config, fuzzy_vec_match, generate_uuid, Availability, Category, Money,
+    Permission, Pool, Product, ServiceError, ServiceResult, Uuid,
 };
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
 use crate::web::utils::{HbData, IsJson, Search};
 use actix_multipart::Multipart;
 use actix_web::{http, web, HttpRequest, HttpResponse};
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::Connection;
 use futures::prelude::*;
 use handlebars::Handlebars;
 use std::collections::HashMap;
-use std::io::Write;
-
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FormProduct {
@@ -26,10 +24,88 @@ pub struct FormProduct {
     #[serde(rename = "price-value-create")]
     pub value: f32,
     pub barcode: String,
+    #[serde(default = "std::string::String::new")]
+    pub parent: String,
+    pub active: Option<String>,
+    #[serde(default)]
+    pub sort_order: i32,
+    pub quick_key: Option<String>,
+    #[serde(default = "std::string::String::new")]
+    pub color: String,
+    #[serde(default = "std::string::String::new")]
+    pub icon: String,
     #[serde(flatten)]
     pub extra: HashMap<String, String>,
 }
 
+impl FormProduct {
+    /// Check if the active switch is set
+    pub fn is_active(&self) -> bool {
+        self.active == Some("on".to_owned())
+    }
+
+    /// Apply the terminal layout settings (sort order, quick key, color and icon) to the product
+    pub fn apply_layout(&self, product: &mut Product) {
+        let non_empty = |s: &str| {
+            let s = s.trim();
+            if s.is_empty() {
+                None
+            } else {
+                Some(s.to_owned())
+            }
+        };
+
+        product.sort_order = self.sort_order;
+        product.quick_key = self.quick_key == Some("on".to_owned());
+        product.color = non_empty(&self.color);
+        product.icon = non_empty(&self.icon);
+    }
+
+    /// Read a new availability window from the form, if any of its fields are set
+    ///
+    /// The product is available on all weekdays if no weekday is selected.
+    pub fn new_availability(&self) -> Option<Availability> {
+        let parse_date = |key: &str| {
+            self.extra
+                .get(key)
+                .and_then(|v| NaiveDate::parse_from_str(v, "%Y-%m-%d").ok())
+        };
+
+        let date_start = parse_date("availability-start-create");
+        let date_end = parse_date("availability-end-create");
+        let weekdays: Vec<_> = all_weekdays()
+            .into_iter()
+            .filter(|d| {
+                self.extra.contains_key(&format!(
+                    "availability-weekday-{}",
+                    d.num_days_from_monday()
+                ))
+            })
+            .collect();
+
+        if date_start.is_none() && date_end.is_none() && weekdays.is_empty() {
+            return None;
+        }
+
+        Some(Availability {
+            id: generate_uuid(),
+            date_start,
+            date_end,
+            weekdays: if weekdays.is_empty() {
+                all_weekdays()
+            } else {
+                weekdays
+            },
+        })
+    }
+}
+
+/// Helper to deserialize the optional parent product for new variants
+#[derive(Debug, Deserialize)]
+pub struct ParentQuery {
+    pub parent: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct SearchProduct {
     #[serde(flatten)]
@@ -127,11 +203,16 @@ pub async fn get_product_edit(
     let product = Product::get(&conn, &Uuid::parse_str(&product_id)?)?;
 
     let all_categories = Category::all(&conn)?;
+    let all_parents: Vec<Product> = Product::all(&conn)?
+        .into_iter()
+        .filter(|p| p.id != product.id)
+        .collect();
 
     let body = HbData::new(&request)
         .with_account(logged_account)
         .with_data("product", &product)
         .with_data("categories", &all_categories)
+        .with_data("parents", &all_parents)
         .render(&hb, "admin_product_edit")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -155,44 +236,76 @@ pub async fn post_product_edit(
 
     let conn = &pool.get()?;
 
-    let mut server_product = Product::get(&conn, &Uuid::parse_str(&product_id)?)?;
+    // A failing barcode, price or availability must not leave a partial update behind
+    conn.transaction::<_, ServiceError, _>(|| {
+        let mut server_product = Product::get(&conn, &Uuid::parse_str(&product_id)?)?;
 
-    let category = if product.category == "" {
-        None
-    } else {
-        Some(Category::get(&conn, &Uuid::parse_str(&product.category)?)?)
-    };
+        let category = if product.category == "" {
+            None
+        } else {
+            Some(Category::get(&conn, &Uuid::parse_str(&product.category)?)?)
+        };
 
-    server_product.name = product.name.clone();
-    server_product.category = category;
+        server_product.name = product.name.clone();
+        server_product.category = category;
+        server_product.parent = if product.parent == "" {
+            None
+        } else {
+            Some(Uuid::parse_str(&product.parent)?)
+        };
+        server_product.active = product.is_active();
+        product.apply_layout(&mut server_product);
 
-    server_product.barcode = if product.barcode.trim().is_empty() {
-        None
-    } else {
-        Some(product.barcode.trim().to_owned())
-    };
+        server_product.barcode = if product.barcode.trim().is_empty() {
+            None
+        } else {
+            Some(product.barcode.trim().to_owned())
+        };
 
-    server_product.update(&conn)?;
+        server_product.update(&conn)?;
 
-    let mut delete_indeces = product
-        .extra
-        .keys()
-        .filter_map(|k| k.trim_start_matches("delete-price-").parse::<usize>().ok())
-        .collect::<Vec<usize>>();
+        let mut delete_indeces = product
+            .extra
+            .keys()
+            .filter_map(|k| k.trim_start_matches("delete-price-").parse::<usize>().ok())
+            .collect::<Vec<usize>>();
 
-    delete_indeces.sort_by(|a, b| b.cmp(a));
+        delete_indeces.sort_by(|a, b| b.cmp(a));
 
-    for index in delete_indeces.iter() {
-        server_product.remove_price(&conn, server_product.prices[*index].validity_start)?;
-    }
+        for index in delete_indeces.iter() {
+            server_product.remove_price(&conn, server_product.prices[*index].validity_start)?;
+        }
 
-    if product.value != 0.0 {
-        server_product.add_price(
-            &conn,
-            product.validity_start,
-            (product.value * 100.0) as Money,
-        )?;
-    }
+        let delete_availabilities = product
+            .extra
+            .keys()
+            .filter(|k| k.starts_with("delete-availability-"))
+            .filter_map(|k| {
+                k.trim_start_matches("delete-availability-")
+                    .parse::<usize>()
+                    .ok()
+            })
+            .filter_map(|index| server_product.availabilities.get(index).map(|a| a.id))
+            .collect::<Vec<Uuid>>();
+
+        for id in delete_availabilities.iter() {
+            server_product.remove_availability(&conn, id)?;
+        }
+
+        if let Some(availability) = product.new_availability() {
+            server_product.add_availability(&conn, availability)?;
+        }
+
+        if product.value != 0.0 {
+            server_product.add_price(
+                &conn,
+                product.validity_start,
+                (product.value * 100.0) as Money,
+            )?;
+        }
+
+        Ok(())
+    })?;
 
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, "/admin/products")
@@ -204,16 +317,20 @@ pub async fn get_product_create(
     hb: web::Data<Handlebars<'_>>,
     logged_account: RetrievedAccount,
     pool: web::Data<Pool>,
+    query: web::Query<ParentQuery>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
     let logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
     let conn = &pool.get()?;
 
     let all_categories = Category::all(&conn)?;
+    let all_parents = Product::all(&conn)?;
 
     let body = HbData::new(&request)
         .with_account(logged_account)
         .with_data("categories", &all_categories)
+        .with_data("parents", &all_parents)
+        .with_data("parent", &query.parent)
         .render(&hb, "admin_product_create")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -229,29 +346,46 @@ pub async fn post_product_create(
 
     let conn = &pool.get()?;
 
-    let category = if product.category == "" {
-        None
-    } else {
-        Some(Category::get(&conn, &Uuid::parse_str(&product.category)?)?)
-    };
+    // An invalid parent must not leave the new product behind
+    let server_product = conn.transaction::<_, ServiceError, _>(|| {
+        let category = if product.category == "" {
+            None
+        } else {
+            Some(Category::get(&conn, &Uuid::parse_str(&product.category)?)?)
+        };
 
-    let mut server_product = Product::create(&conn, &product.name, category)?;
+        let mut server_product = Product::create(&conn, &product.name, category)?;
 
-    if product.value != 0.0 {
-        server_product.add_price(
-            &conn,
-            product.validity_start,
-            (product.value * 100.0) as Money,
-        )?;
-    }
+        server_product.parent = if product.parent == "" {
+            None
+        } else {
+            Some(Uuid::parse_str(&product.parent)?)
+        };
+        server_product.active = product.is_active();
+        product.apply_layout(&mut server_product);
+
+        if product.value != 0.0 {
+            server_product.add_price(
+                &conn,
+                product.validity_start,
+                (product.value * 100.0) as Money,
+            )?;
+        }
 
-    server_product.barcode = if product.barcode.trim().is_empty() {
-        None
-    } else {
-        Some(product.barcode.trim().to_owned())
-    };
+        server_product.barcode = if product.barcode.trim().is_empty() {
+            None
+        } else {
+            Some(product.barcode.trim().to_owned())
+        };
+
+        server_product.update(&conn)?;
 
-    server_product.update(&conn)?;
+        if let Some(availability) = product.new_availability() {
+            server_product.add_availability(&conn, availability)?;
+        }
+
+        Ok(server_product)
+    })?;
 
     Ok(HttpResponse::Found()
         .header(
@@ -269,7 +403,7 @@ pub async fn get_product_delete(
 ) -> ServiceResult<HttpResponse> {
     let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
-    println!("Delete is not supported!");
+    warn!("Delete is not supported!");
 
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, "/admin/products")
@@ -284,11 +418,15 @@ pub async fn get_product_remove_image(
 ) -> ServiceResult<HttpResponse> {
     let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
-    let conn = &pool.get()?;
-
-    let mut product = Product::get(&conn, &Uuid::parse_str(&product_id)?)?;
+    let id = Uuid::parse_str(&product_id)?;
 
-    product.remove_image(&conn)?;
+    // The storage backend may block on the file system or the network
+    let pool = pool.clone();
+    web::block(move || {
+        let conn = &pool.get()?;
+        Product::get(&conn, &id)?.remove_image(&conn)
+    })
+    .await?;
 
     Ok(HttpResponse::Found()
         .header(
@@ -307,54 +445,51 @@ pub async fn post_product_upload_image(
 ) -> ServiceResult<HttpResponse> {
     let _logged_account = login_required!(logged_account, Permission::MEMBER, Action::REDIRECT);
 
-    let conn = &pool.get()?;
-    let mut product = Product::get(&conn, &Uuid::parse_str(&product_id)?)?;
+    let product = Product::get(&*pool.get()?, &Uuid::parse_str(&product_id)?)?;
     let location = format!("/admin/product/{}", &product_id);
 
-    save_file(multipart, &conn, &mut product).await?;
+    save_file(multipart, &pool, product.id).await?;
     Ok(HttpResponse::Found()
         .header(http::header::LOCATION, location)
         .finish())
 }
 
-const ALLOWED_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "svg"];
-
-/// Read the multipart stream and save content to file
+/// Read the multipart stream and save the uploaded image
+///
+/// The upload is aborted as soon as it exceeds the maximal image size. Decoding and resizing the
+/// image runs on the thread pool for blocking tasks, so it does not stall the async worker.
 async fn save_file(
     mut payload: Multipart,
-    conn: &DbConnection,
-    product: &mut Product,
+    pool: &web::Data<Pool>,
+    product_id: Uuid,
 ) -> ServiceResult<()> {
     // iterate over multipart stream
     while let Some(item) = payload.next().await {
         let mut field = item?;
 
-        // verify the file content type
-        let file_extension = field
-            .content_type()
-            .subtype()
-            .as_str()
-            .to_ascii_lowercase()
-            .to_owned();
-
-        if !ALLOWED_EXTENSIONS.iter().any(|s| s == &file_extension) {
-            return Err(ServiceError::InternalServerError(
-                "Unsupported",
-                "".to_owned(),
-            ));
-        }
-
-        let mut file = product.set_image(&conn, &file_extension)?;
+        let mut data: Vec<u8> = Vec::new();
 
         // Field in turn is stream of *Bytes* object
         while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            let mut pos = 0;
-            while pos < data.len() {
-                let bytes_written = file.write(&data[pos..])?;
-                pos += bytes_written;
+            data.extend_from_slice(&chunk?);
+
+            if data.len() > config::get().image_max_size {
+                return Err(ServiceError::BadRequest(
+                    "Image too large",
+                    format!("At most {} bytes are allowed", config::get().image_max_size),
+                ));
             }
         }
+
+        if !data.is_empty() {
+            let pool = pool.clone();
+            web::block(move || {
+                let conn = &pool.get()?;
+                let mut product = Product::get(&conn, &product_id)?;
+                product.set_image(&conn, &data)
+            })
+            .await?;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,179 @@
+use crate::core::{DbConnection, Permission, Pool, ServiceError, ServiceResult, Terminal, Uuid};
+use crate::identity_policy::{Action, RetrievedAccount};
+use crate::login_required;
+use crate::web::utils::HbData;
+use actix_web::{http, web, HttpRequest, HttpResponse};
+use handlebars::Handlebars;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormTerminal {
+    pub name: String,
+    #[serde(default = "std::string::String::new")]
+    pub location: String,
+    #[serde(default = "std::string::String::new")]
+    pub certificate_subject: String,
+}
+
+impl FormTerminal {
+    /// Check the form values, the certificate subject must not be used by another terminal
+    fn validate(&self, conn: &DbConnection, terminal_id: Option<&Uuid>) -> ServiceResult<()> {
+        if self.name.trim().is_empty() {
+            return Err(ServiceError::invalid_field(
+                "name",
+                "The terminal needs a name".to_owned(),
+            ));
+        }
+        if let Some(subject) = optional(&self.certificate_subject) {
+            let in_use = Terminal::all(conn)?.into_iter().any(|t| {
+                Some(&t.id) != terminal_id && t.certificate_subject == Some(subject.clone())
+            });
+            if in_use {
+                return Err(ServiceError::invalid_field(
+                    "certificate_subject",
+                    "The certificate subject is used by another terminal".to_owned(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn optional(value: &str) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        value => Some(value.to_owned()),
+    }
+}
+
+/// GET route for `/admin/terminals`
+pub async fn get_terminals(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let terminals = Terminal::all(&conn)?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("terminals", &terminals)
+        .render(&hb, "admin_terminal_list")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// GET route for `/admin/terminal/create`
+pub async fn get_terminal_create(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .render(&hb, "admin_terminal_create")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/admin/terminal/create`
+///
+/// The api key of the new terminal is only shown in this response.
+pub async fn post_terminal_create(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    terminal: web::Form<FormTerminal>,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    terminal.validate(&conn, None)?;
+
+    let (terminal, key) = Terminal::create(
+        &conn,
+        terminal.name.trim(),
+        optional(&terminal.location),
+        optional(&terminal.certificate_subject),
+    )?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("terminal", &terminal)
+        .with_data("key", &key)
+        .render(&hb, "admin_terminal_create")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// GET route for `/admin/terminal/{terminal_id}`
+pub async fn get_terminal_edit(
+    hb: web::Data<Handlebars<'_>>,
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    terminal_id: web::Path<String>,
+    request: HttpRequest,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let terminal = Terminal::get(&conn, &Uuid::parse_str(&terminal_id)?)?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("terminal", &terminal)
+        .render(&hb, "admin_terminal_edit")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/admin/terminal/{terminal_id}`
+pub async fn post_terminal_edit(
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    terminal: web::Form<FormTerminal>,
+    terminal_id: web::Path<String>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let mut server_terminal = Terminal::get(&conn, &Uuid::parse_str(&terminal_id)?)?;
+    terminal.validate(&conn, Some(&server_terminal.id))?;
+
+    server_terminal.name = terminal.name.trim().to_owned();
+    server_terminal.location = optional(&terminal.location);
+    server_terminal.certificate_subject = optional(&terminal.certificate_subject);
+    server_terminal.update(&conn)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/admin/terminals")
+        .finish())
+}
+
+/// GET route for `/admin/terminal/revoke/{terminal_id}`
+pub async fn get_terminal_revoke(
+    logged_account: RetrievedAccount,
+    pool: web::Data<Pool>,
+    terminal_id: web::Path<String>,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::ADMIN, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let mut terminal = Terminal::get(&conn, &Uuid::parse_str(&terminal_id)?)?;
+    terminal.revoke(&conn)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/admin/terminals")
+        .finish())
+}
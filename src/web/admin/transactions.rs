@@ -1,6 +1,6 @@
 use crate::core::{
-    transactions, Account, DbConnection, Money, Permission, Pool, Product, ServiceResult,
-    Transaction,
+    transactions, Account, DbConnection, Money, Permission, Pool, Product, ServiceResult, Terminal,
+    Transaction, Uuid,
 };
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
@@ -9,8 +9,6 @@ use actix_web::{http, web, HttpRequest, HttpResponse};
 use chrono::{Duration, Local, NaiveDateTime};
 use handlebars::Handlebars;
 
-use uuid::Uuid;
-
 /// Helper to deserialize from-to queries
 #[derive(Deserialize, Serialize)]
 pub struct FromToQuery {
@@ -145,6 +143,7 @@ pub async fn post_execute_transaction(
             &conn,
             &mut account,
             Some(&logged_account.account),
+            None,
             (execute_form.total * 100.0) as Money,
         )?;
     }
@@ -179,10 +178,16 @@ pub async fn get_transaction_details(
 
     let products = TransactionProduct::vec_to_transaction_product(products);
 
+    let terminal = match &transaction.terminal_id {
+        Some(terminal_id) => Some(Terminal::get(&conn, terminal_id)?),
+        None => None,
+    };
+
     let body = HbData::new(&request)
         .with_account(logged_account)
         .with_data("account", &account)
         .with_data("transaction", &transaction)
+        .with_data("terminal", &terminal)
         .with_data("products", &products)
         .render(&hb, "admin_transaction_details")?;
 
@@ -3,40 +3,54 @@ pub mod settings;
 
 use actix_web::web;
 
+use crate::web::metrics::resource;
+
 /// Setup routes for admin ui
 pub fn init(config: &mut web::ServiceConfig) {
     config
-        .service(web::resource("").route(web::get().to(overview::get_overview)))
+        .service(resource("").route(web::get().to(overview::get_overview)))
         .service(
-            web::resource("/transaction/{transaction_id}")
+            resource("/transaction/{transaction_id}")
                 .route(web::get().to(overview::get_transaction_details)),
         )
         .service(
-            web::resource("/settings/change-password")
+            resource("/settings/change-password")
                 .route(web::post().to(settings::post_change_password))
                 .route(web::get().to(settings::get_change_password)),
         )
         .service(
-            web::resource("/settings/revoke-password")
+            resource("/settings/revoke-password")
                 .route(web::post().to(settings::post_revoke_password))
                 .route(web::get().to(settings::get_revoke_password)),
         )
         .service(
-            web::resource("/settings/revoke-qr")
+            resource("/settings/revoke-qr")
                 .route(web::post().to(settings::post_revoke_qr))
                 .route(web::get().to(settings::get_revoke_qr)),
         )
         .service(
-            web::resource("/settings/revoke-nfc")
+            resource("/settings/revoke-nfc")
                 .route(web::post().to(settings::post_revoke_nfc))
                 .route(web::get().to(settings::get_revoke_nfc)),
         )
         .service(
-            web::resource("/settings/theme/{theme}")
-                .route(web::get().to(settings::get_theme)),
+            resource("/settings/totp")
+                .route(web::post().to(settings::post_totp))
+                .route(web::get().to(settings::get_totp)),
+        )
+        .service(
+            resource("/settings/totp/recovery-codes")
+                .route(web::post().to(settings::post_totp_recovery_codes))
+                .route(web::get().to(settings::get_totp_recovery_codes)),
+        )
+        .service(
+            resource("/settings/totp/disable")
+                .route(web::post().to(settings::post_totp_disable))
+                .route(web::get().to(settings::get_totp_disable)),
         )
+        .service(resource("/settings/theme/{theme}").route(web::get().to(settings::get_theme)))
         .service(
-            web::resource("/settings")
+            resource("/settings")
                 .route(web::post().to(settings::post_settings))
                 .route(web::get().to(settings::get_settings)),
         );
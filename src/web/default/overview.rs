@@ -1,4 +1,4 @@
-use crate::core::{transactions, Permission, Pool, ServiceResult};
+use crate::core::{transactions, Permission, Pool, ServiceResult, Uuid};
 use crate::identity_policy::{Action, RetrievedAccount};
 use crate::login_required;
 use crate::web::admin::transactions::{
@@ -8,7 +8,6 @@ use crate::web::utils::HbData;
 use actix_web::{web, HttpRequest, HttpResponse};
 use chrono::{Duration, Local, NaiveDateTime};
 use handlebars::Handlebars;
-use uuid::Uuid;
 
 /// Helper to deserialize from-to queries
 #[derive(Deserialize, Serialize)]
@@ -1,8 +1,8 @@
 use crate::core::{
-    authentication_barcode, authentication_nfc, authentication_password, Account, Permission, Pool,
-    ServiceResult,
+    auth_failures, authentication_barcode, authentication_nfc, authentication_password,
+    authentication_totp, Account, DbConnection, Permission, Pool, ServiceError, ServiceResult,
 };
-use crate::identity_policy::{Action, RetrievedAccount};
+use crate::identity_policy::{client_ip, Action, RetrievedAccount};
 use crate::login_required;
 use crate::web::utils::{EmptyToNone, HbData};
 use actix_web::{http, web, HttpRequest, HttpResponse};
@@ -33,6 +33,11 @@ impl FormRevoke {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormTotpCode {
+    pub code: String,
+}
+
 /// GET route for `/settings`
 pub async fn get_settings(
     pool: web::Data<Pool>,
@@ -50,6 +55,9 @@ pub async fn get_settings(
     let has_nfc_card = !authentication_nfc::get_nfcs(&conn, &logged_account.account)?.is_empty();
     let has_mail_address = logged_account.account.mail.is_some();
     let receives_monthly_report = logged_account.account.receives_monthly_report;
+    let has_totp = authentication_totp::is_enabled(&conn, &logged_account.account)?;
+    let totp_mandatory = authentication_totp::is_mandatory(&logged_account.account);
+    let recovery_codes = authentication_totp::count_recovery_codes(&conn, &logged_account.account)?;
 
     let body = HbData::new(&request)
         .with_account(logged_account)
@@ -58,6 +66,9 @@ pub async fn get_settings(
         .with_data("has_nfc_card", &has_nfc_card)
         .with_data("has_mail_address", &has_mail_address)
         .with_data("receives_monthly_report", &receives_monthly_report)
+        .with_data("has_totp", &has_totp)
+        .with_data("totp_mandatory", &totp_mandatory)
+        .with_data("recovery_codes", &recovery_codes)
         .render(&hb, "default_settings")?;
 
     // TODO: Checkbox is not checked although checking it works already
@@ -237,6 +248,200 @@ pub async fn post_revoke_nfc(
         .finish())
 }
 
+/// GET route for `/settings/totp`
+pub async fn get_totp(
+    pool: web::Data<Pool>,
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    if authentication_totp::is_enabled(&conn, &logged_account.account)? {
+        return Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/settings")
+            .finish());
+    }
+
+    let enrollment = authentication_totp::enroll(&conn, &logged_account.account)?;
+
+    let body = HbData::new(&request)
+        .with_data("enrollment", &enrollment)
+        .with_data("error", &request.query_string().contains("error"))
+        .render(&hb, "default_settings_totp")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/settings/totp`
+pub async fn post_totp(
+    pool: web::Data<Pool>,
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    params: web::Form<FormTotpCode>,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    let recovery_codes = match authentication_totp::confirm(
+        &conn,
+        &logged_account.account,
+        &logged_account.session_id,
+        &params.code,
+    ) {
+        Ok(recovery_codes) => recovery_codes,
+        Err(ServiceError::NotFound) => {
+            return Ok(HttpResponse::Found()
+                .header(http::header::LOCATION, "/settings/totp?error")
+                .finish())
+        }
+        Err(e) => return Err(e),
+    };
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("recovery_codes", &recovery_codes)
+        .with_data("continue", &"/settings")
+        .render(&hb, "default_settings_totp_recovery_codes")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// GET route for `/settings/totp/recovery-codes`
+pub async fn get_totp_recovery_codes(
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let _logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    let body = HbData::new(&request)
+        .with_data("error", &request.query_string().contains("error"))
+        .with_data("locked", &request.query_string().contains("locked"))
+        .render(&hb, "default_settings_totp_regenerate")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/settings/totp/recovery-codes`
+pub async fn post_totp_recovery_codes(
+    pool: web::Data<Pool>,
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    params: web::Form<FormTotpCode>,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    if let Some(response) = verify_totp_code(
+        &conn,
+        &request,
+        &logged_account.account,
+        &params.code,
+        "/settings/totp/recovery-codes",
+    )? {
+        return Ok(response);
+    }
+
+    let recovery_codes =
+        authentication_totp::regenerate_recovery_codes(&conn, &logged_account.account)?;
+
+    let body = HbData::new(&request)
+        .with_account(logged_account)
+        .with_data("recovery_codes", &recovery_codes)
+        .with_data("continue", &"/settings")
+        .render(&hb, "default_settings_totp_recovery_codes")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// GET route for `/settings/totp/disable`
+pub async fn get_totp_disable(
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    if authentication_totp::is_mandatory(&logged_account.account) {
+        return Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/settings")
+            .finish());
+    }
+
+    let body = HbData::new(&request)
+        .with_data("error", &request.query_string().contains("error"))
+        .with_data("locked", &request.query_string().contains("locked"))
+        .render(&hb, "default_settings_totp_disable")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/settings/totp/disable`
+pub async fn post_totp_disable(
+    pool: web::Data<Pool>,
+    request: HttpRequest,
+    params: web::Form<FormTotpCode>,
+    logged_account: RetrievedAccount,
+) -> ServiceResult<HttpResponse> {
+    let logged_account = login_required!(logged_account, Permission::DEFAULT, Action::REDIRECT);
+
+    let conn = &pool.get()?;
+
+    if authentication_totp::is_mandatory(&logged_account.account) {
+        return Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/settings")
+            .finish());
+    }
+
+    if let Some(response) = verify_totp_code(
+        &conn,
+        &request,
+        &logged_account.account,
+        &params.code,
+        "/settings/totp/disable",
+    )? {
+        return Ok(response);
+    }
+
+    authentication_totp::disable(&conn, &logged_account.account)?;
+
+    Ok(HttpResponse::Found()
+        .header(http::header::LOCATION, "/settings")
+        .finish())
+}
+
+/// Verify the second factor before it is changed, return the redirect back to `page` on failure
+fn verify_totp_code(
+    conn: &DbConnection,
+    request: &HttpRequest,
+    account: &Account,
+    code: &str,
+    page: &str,
+) -> ServiceResult<Option<HttpResponse>> {
+    let result =
+        auth_failures::authenticate_second_factor(conn, &client_ip(request), account, code);
+
+    let query = match result {
+        Ok(()) => return Ok(None),
+        Err(ServiceError::TooManyRequests(_)) => "locked",
+        Err(ServiceError::NotFound) => "error",
+        Err(e) => return Err(e),
+    };
+
+    Ok(Some(
+        HttpResponse::Found()
+            .header(http::header::LOCATION, format!("{}?{}", page, query))
+            .finish(),
+    ))
+}
+
 /// GET route for `/settings/theme/{theme}`
 pub async fn get_theme(
     theme: web::Path<String>,
@@ -0,0 +1,81 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::Error;
+use futures::future::Future;
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+/// Header to pass the request id from a reverse proxy and back to the client
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Use the request id of the reverse proxy if it looks sane, otherwise generate a new one
+fn request_id(request: &ServiceRequest) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| {
+            !value.is_empty()
+                && value.len() <= 64
+                && value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map(|value| value.to_owned())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Middleware function to log all requests
+///
+/// Every request is handled in a `request` span with its request id,
+/// so all log entries of handlers and `core` calls can be assigned to their request.
+/// The span is logged with its status and duration when the request is finished.
+pub fn log_request<S>(
+    request: ServiceRequest,
+    service: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let request_id = request_id(&request);
+
+    let span = info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        path = %request.path(),
+        peer = %request.connection_info().remote().unwrap_or("-"),
+        status = tracing::field::Empty,
+    );
+
+    let response = {
+        let _entered = span.enter();
+        service.call(request)
+    };
+
+    async move {
+        match response.await {
+            Ok(mut response) => {
+                let status = response.status();
+                Span::current().record("status", status.as_u16());
+
+                if status.is_server_error() {
+                    error!(status = status.as_u16(), "Request failed");
+                }
+
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    response
+                        .headers_mut()
+                        .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+                }
+
+                Ok(response)
+            }
+            Err(e) => {
+                error!(error = %e, "Request failed");
+                Err(e)
+            }
+        }
+    }
+    .instrument(span)
+}
@@ -1,5 +1,7 @@
-use crate::core::{authentication_password, Pool, ServiceResult};
-use crate::identity_policy::{LoggedAccount, RetrievedAccount};
+use crate::core::{
+    auth_failures, authentication_password, authentication_totp, Pool, ServiceError, ServiceResult,
+};
+use crate::identity_policy::{client_ip, LoggedAccount, PendingAccount, RetrievedAccount};
 use crate::web::utils::HbData;
 use actix_identity::Identity;
 use actix_web::{http, web, HttpRequest, HttpResponse};
@@ -11,6 +13,11 @@ pub struct LoginForm {
     password: String,
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct SecondFactorForm {
+    code: String,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RegisterForm {
     password: String,
@@ -21,9 +28,17 @@ pub struct RegisterForm {
 pub async fn get_login(
     hb: web::Data<Handlebars<'_>>,
     request: HttpRequest,
+    pending_account: PendingAccount,
 ) -> ServiceResult<HttpResponse> {
+    if let PendingAccount::Acc(_) = pending_account {
+        return Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/login/second-factor")
+            .finish());
+    }
+
     let body = HbData::new(&request)
         .with_data("error", &request.query_string().contains("error"))
+        .with_data("locked", &request.query_string().contains("locked"))
         .render(&hb, "login")?;
 
     Ok(HttpResponse::Ok().body(body))
@@ -34,28 +49,155 @@ pub async fn post_login(
     pool: web::Data<Pool>,
     id: Identity,
     params: web::Form<LoginForm>,
+    request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
     let conn = &pool.get()?;
 
-    let login_result = authentication_password::get(conn, &params.username, &params.password);
+    let login_result = auth_failures::authenticate_password(
+        conn,
+        &client_ip(&request),
+        &params.username,
+        &params.password,
+    );
     match login_result {
         Ok(account) => {
-            LoggedAccount::new(&conn, account)?.save(id)?;
+            let logged_account = LoggedAccount::new(&conn, account)?;
+            let location = if logged_account.second_factor_pending {
+                "/login/second-factor"
+            } else {
+                "/"
+            };
+            logged_account.save(id)?;
 
             Ok(HttpResponse::Found()
-                .header(http::header::LOCATION, "/")
+                .header(http::header::LOCATION, location)
                 .finish())
         }
+        Err(ServiceError::TooManyRequests(_)) => Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/login?locked")
+            .finish()),
         Err(_) => Ok(HttpResponse::Found()
             .header(http::header::LOCATION, "/login?error")
             .finish()),
     }
 }
 
+/// GET route for `/login/second-factor` if the password of the user is verified
+///
+/// Accounts that must use a second factor but have none set up enroll here.
+pub async fn get_second_factor(
+    pool: web::Data<Pool>,
+    hb: web::Data<Handlebars<'_>>,
+    request: HttpRequest,
+    pending_account: PendingAccount,
+) -> ServiceResult<HttpResponse> {
+    let pending_account = match pending_account {
+        PendingAccount::Acc(acc) => acc,
+        PendingAccount::Nothing => {
+            return Ok(HttpResponse::Found()
+                .header(http::header::LOCATION, "/login")
+                .finish())
+        }
+    };
+
+    let conn = &pool.get()?;
+
+    let enrollment = if authentication_totp::is_enabled(&conn, &pending_account.account)? {
+        None
+    } else {
+        Some(authentication_totp::enroll(
+            &conn,
+            &pending_account.account,
+        )?)
+    };
+
+    let body = HbData::new(&request)
+        .with_data("enrollment", &enrollment)
+        .with_data("error", &request.query_string().contains("error"))
+        .with_data("locked", &request.query_string().contains("locked"))
+        .render(&hb, "login_second_factor")?;
+
+    Ok(HttpResponse::Ok().body(body))
+}
+
+/// POST route for `/login/second-factor`
+pub async fn post_second_factor(
+    pool: web::Data<Pool>,
+    hb: web::Data<Handlebars<'_>>,
+    id: Identity,
+    params: web::Form<SecondFactorForm>,
+    request: HttpRequest,
+    pending_account: PendingAccount,
+) -> ServiceResult<HttpResponse> {
+    let mut pending_account = match pending_account {
+        PendingAccount::Acc(acc) => acc,
+        PendingAccount::Nothing => {
+            return Ok(HttpResponse::Found()
+                .header(http::header::LOCATION, "/login")
+                .finish())
+        }
+    };
+
+    let conn = &pool.get()?;
+
+    if !authentication_totp::is_enabled(&conn, &pending_account.account)? {
+        // Finish the enrollment, the new recovery codes are only shown once
+        let recovery_codes = match authentication_totp::confirm(
+            &conn,
+            &pending_account.account,
+            &pending_account.session_id,
+            &params.code,
+        ) {
+            Ok(recovery_codes) => recovery_codes,
+            Err(ServiceError::NotFound) => {
+                return Ok(HttpResponse::Found()
+                    .header(http::header::LOCATION, "/login/second-factor?error")
+                    .finish())
+            }
+            Err(e) => return Err(e),
+        };
+
+        pending_account.verify_second_factor(&conn)?;
+        pending_account.save(id)?;
+
+        let body = HbData::new(&request)
+            .with_data("recovery_codes", &recovery_codes)
+            .with_data("continue", &"/")
+            .render(&hb, "default_settings_totp_recovery_codes")?;
+
+        return Ok(HttpResponse::Ok().body(body));
+    }
+
+    let result = auth_failures::authenticate_second_factor(
+        conn,
+        &client_ip(&request),
+        &pending_account.account,
+        &params.code,
+    );
+    match result {
+        Ok(()) => {
+            pending_account.verify_second_factor(&conn)?;
+            pending_account.save(id)?;
+
+            Ok(HttpResponse::Found()
+                .header(http::header::LOCATION, "/")
+                .finish())
+        }
+        Err(ServiceError::TooManyRequests(_)) => Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/login/second-factor?locked")
+            .finish()),
+        Err(ServiceError::NotFound) => Ok(HttpResponse::Found()
+            .header(http::header::LOCATION, "/login/second-factor?error")
+            .finish()),
+        Err(e) => Err(e),
+    }
+}
+
 /// GET route for `/logout`
 pub async fn get_logout(
     pool: web::Data<Pool>,
     logged_account: RetrievedAccount,
+    pending_account: PendingAccount,
     id: Identity,
 ) -> ServiceResult<HttpResponse> {
     let conn = &pool.get()?;
@@ -63,6 +205,8 @@ pub async fn get_logout(
     // TODO: Check implications of this -> any cleanup needed?
     if let RetrievedAccount::Acc(acc) = logged_account {
         acc.forget(conn, id)?;
+    } else if let PendingAccount::Acc(acc) = pending_account {
+        acc.forget(conn, id)?;
     }
 
     Ok(HttpResponse::Found()
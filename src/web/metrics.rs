@@ -0,0 +1,238 @@
+use crate::core::{config, constant_time_eq, metrics, Pool, ServiceError, ServiceResult};
+use actix_files as fs;
+use actix_service::ServiceFactory;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use actix_web::{http, web, Error, HttpMessage, HttpRequest, HttpResponse, Resource, Scope};
+use futures::future::Future;
+use std::time::Instant;
+
+/// Marker for requests that are handled by the default service
+pub struct Unmatched;
+
+/// Path of the scopes that matched a request
+struct ScopePattern(String);
+
+/// Route pattern of the service that handled a request, eg. `/admin/product/{product_id}`
+struct RoutePattern(String);
+
+/// Append `path` to the pattern of the enclosing scopes
+fn join_pattern(request: &ServiceRequest, path: &str) -> String {
+    let extensions = request.extensions();
+    let scope = extensions
+        .get::<ScopePattern>()
+        .map(|scope| scope.0.as_str())
+        .unwrap_or("");
+
+    format!("{}{}", scope.trim_end_matches('/'), path)
+}
+
+/// Create a scope that adds its path to the route pattern of the request metrics
+///
+/// actix-web 2.0 has no `HttpRequest::match_pattern`, so the scopes and resources record their
+/// patterns themselves. Use `resource` for the resources of the scope.
+pub fn scope(
+    path: &str,
+) -> Scope<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let path = path.to_owned();
+    web::scope(&path).wrap_fn(move |request, service| {
+        let pattern = ScopePattern(join_pattern(&request, &path));
+        request.extensions_mut().insert(pattern);
+        service.call(request)
+    })
+}
+
+/// Create a resource that is counted under its route pattern by the request metrics
+pub fn resource(
+    path: &str,
+) -> Resource<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let path = path.to_owned();
+    web::resource(&path).wrap_fn(move |request, service| {
+        let pattern = RoutePattern(join_pattern(&request, &path));
+        request.extensions_mut().insert(pattern);
+        service.call(request)
+    })
+}
+
+/// Create a service for the static files of `directory`
+///
+/// All files are counted under the pattern `<path>/{filename}` by the request metrics.
+pub fn files(
+    path: &str,
+    directory: &str,
+) -> Scope<
+    impl ServiceFactory<
+        Config = (),
+        Request = ServiceRequest,
+        Response = ServiceResponse,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let path = path.to_owned();
+    web::scope(&path)
+        .wrap_fn(move |request, service| {
+            let pattern = RoutePattern(join_pattern(&request, &format!("{}/{{filename}}", path)));
+            request.extensions_mut().insert(pattern);
+            service.call(request)
+        })
+        .service(fs::Files::new("", directory))
+}
+
+/// Route pattern of the matched resource, or `unmatched` if no resource of `resource` or `files`
+/// matched
+fn route_label(request: &HttpRequest) -> String {
+    let extensions = request.extensions();
+    if extensions.get::<Unmatched>().is_some() {
+        return "unmatched".to_owned();
+    }
+
+    match extensions.get::<RoutePattern>() {
+        Some(pattern) if pattern.0.is_empty() => "/".to_owned(),
+        Some(pattern) => pattern.0.clone(),
+        None => "unmatched".to_owned(),
+    }
+}
+
+/// Middleware function to count and time all requests
+///
+/// Requests are labeled with the route pattern of the matched resource, eg.
+/// `/admin/product/{product_id}`.
+pub fn observe_request<S>(
+    request: ServiceRequest,
+    service: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let start = Instant::now();
+    let method = request.method().to_string();
+    let response = service.call(request);
+
+    async move {
+        let response = response.await?;
+
+        let status = response.status();
+        metrics::observe_request(
+            &method,
+            &route_label(response.request()),
+            status.as_u16(),
+            start.elapsed().as_secs_f64(),
+        );
+
+        Ok(response)
+    }
+}
+
+/// Check the header `Authorization: Bearer <token>` of the request in constant time
+pub fn is_authorized(request: &HttpRequest, token: &str) -> bool {
+    let expected = format!("Bearer {}", token);
+
+    request
+        .headers()
+        .get(http::header::AUTHORIZATION)
+        .map(|header| constant_time_eq(header.as_bytes(), expected.as_bytes()))
+        .unwrap_or(false)
+}
+
+/// GET route for `/metrics`
+///
+/// Returns the metrics in the prometheus text format.
+/// This function expects the header `Authorization: Bearer <token>` with the `metrics_token` of the
+/// configuration, the route is disabled if it is empty.
+pub async fn get_metrics(
+    request: HttpRequest,
+    pool: web::Data<Pool>,
+) -> ServiceResult<HttpResponse> {
+    let metrics_token = config::get().metrics_token.as_str();
+    if metrics_token.is_empty() {
+        return Err(ServiceError::NotFound);
+    }
+
+    if !is_authorized(&request, metrics_token) {
+        return Err(ServiceError::Unauthorized);
+    }
+
+    let body = metrics::render(&pool)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+
+    async fn get_label(request: HttpRequest) -> HttpResponse {
+        HttpResponse::Ok().body(route_label(&request))
+    }
+
+    async fn get_404(request: HttpRequest) -> HttpResponse {
+        request.extensions_mut().insert(Unmatched);
+        HttpResponse::NotFound().body(route_label(&request))
+    }
+
+    #[actix_rt::test]
+    async fn test_route_label() {
+        let mut app = test::init_service(
+            App::new()
+                .service(resource("/events").to(get_label))
+                .service(
+                    scope("/admin")
+                        .service(resource("").to(get_label))
+                        .service(resource("/product/{product_id}").to(get_label)),
+                )
+                .service(
+                    scope("/")
+                        .service(resource("").to(get_label))
+                        .service(resource("/login").to(get_label))
+                        .default_service(web::to(get_404)),
+                ),
+        )
+        .await;
+
+        for (uri, label) in &[
+            ("/events", "/events"),
+            ("/admin", "/admin"),
+            ("/admin/product/9a3e5c1c", "/admin/product/{product_id}"),
+            ("/admin/product/product", "/admin/product/{product_id}"),
+            ("/", "/"),
+            ("/login", "/login"),
+            ("/login/9a3e5c1c", "unmatched"),
+        ] {
+            let request = test::TestRequest::with_uri(uri).to_request();
+            let body = test::read_response(&mut app, request).await;
+            assert_eq!(body, label.as_bytes(), "{}", uri);
+        }
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let request = test::TestRequest::default()
+            .header(http::header::AUTHORIZATION, "Bearer secret")
+            .to_http_request();
+        assert!(is_authorized(&request, "secret"));
+        assert!(!is_authorized(&request, "secreT"));
+        assert!(!is_authorized(&request, "secret2"));
+
+        let request = test::TestRequest::default().to_http_request();
+        assert!(!is_authorized(&request, "secret"));
+    }
+}
@@ -1,15 +1,17 @@
 pub mod admin;
 pub mod default;
+pub mod logging;
 pub mod login;
+pub mod metrics;
 pub mod utils;
 
 // TODO: REMOVE FOR PRODUCTION!
 pub mod proxy;
 
-use crate::core::ServiceResult;
+use crate::core::{config, health, images, Pool, ServiceError, ServiceResult};
+use crate::web::metrics::{files, resource, scope};
 use crate::web::utils::HbData;
-use actix_files as fs;
-use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::{http, web, HttpRequest, HttpResponse};
 use handlebars::Handlebars;
 
 /// Setup routes for admin ui
@@ -17,21 +19,29 @@ pub fn init(config: &mut web::ServiceConfig) {
     admin::init(config);
 
     config.service(
-        web::scope("/")
+        scope("/")
             // Setup static routes
-            .service(fs::Files::new("/stylesheets", "static/stylesheets/"))
-            .service(fs::Files::new("/javascripts", "static/javascripts/"))
-            .service(fs::Files::new("/images", "static/images/"))
-            .service(fs::Files::new("/product/image", "img/"))
+            .service(files("/stylesheets", "static/stylesheets/"))
+            .service(files("/javascripts", "static/javascripts/"))
+            .service(files("/images", "static/images/"))
+            .service(resource("/product/image/{name}").route(web::get().to(get_product_image)))
+            .service(resource("/metrics").route(web::get().to(metrics::get_metrics)))
+            .service(resource("/health").route(web::get().to(get_health)))
+            .service(resource("/ready").route(web::get().to(get_ready)))
             // Setup login routes
             .service(
-                web::resource("/login")
+                resource("/login")
                     .route(web::post().to(login::post_login))
                     .route(web::get().to(login::get_login)),
             )
-            .service(web::resource("/logout").route(web::get().to(login::get_logout)))
             .service(
-                web::resource("/register/{invitation_id}")
+                resource("/login/second-factor")
+                    .route(web::post().to(login::post_second_factor))
+                    .route(web::get().to(login::get_second_factor)),
+            )
+            .service(resource("/logout").route(web::get().to(login::get_logout)))
+            .service(
+                resource("/register/{invitation_id}")
                     .route(web::post().to(login::post_register))
                     .route(web::get().to(login::get_register)),
             )
@@ -45,7 +55,58 @@ pub async fn get_404(
     hb: web::Data<Handlebars<'_>>,
     request: HttpRequest,
 ) -> ServiceResult<HttpResponse> {
+    request.extensions_mut().insert(metrics::Unmatched);
+
     let body = HbData::new(&request).render(&hb, "404")?;
 
     Ok(HttpResponse::Ok().body(body))
 }
+
+/// GET route for `/product/image/{name}`
+///
+/// Serves product images and thumbnails from the configured storage backend.
+/// Image names are unique per upload, so they can be cached forever.
+pub async fn get_product_image(name: web::Path<String>) -> ServiceResult<HttpResponse> {
+    // The storage backend may block on the file system or the network
+    let name = name.into_inner();
+    let (data, mime_type) = web::block(move || images::load_image(&name)).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(mime_type)
+        .header(
+            http::header::CACHE_CONTROL,
+            "public, max-age=31536000, immutable",
+        )
+        .body(data))
+}
+
+/// GET route for `/health`
+///
+/// Always succeeds while the process is able to handle requests.
+pub async fn get_health() -> ServiceResult<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
+}
+
+/// GET route for `/ready`
+///
+/// Checks the database, migrations and image storage.
+/// Responds with `503 Service Unavailable` and the failed checks if the server is not ready.
+/// The reasons of failed checks are only shown with the header `Authorization: Bearer <token>`
+/// of the `metrics_token` of the configuration.
+pub async fn get_ready(pool: web::Data<Pool>, request: HttpRequest) -> ServiceResult<HttpResponse> {
+    // The checks block on the database and the storage backend
+    let pool = pool.clone();
+    let mut readiness =
+        web::block(move || Ok::<_, ServiceError>(health::check_readiness(&pool))).await?;
+
+    let metrics_token = config::get().metrics_token.as_str();
+    if metrics_token.is_empty() || !metrics::is_authorized(&request, metrics_token) {
+        readiness.hide_messages();
+    }
+
+    if readiness.ready {
+        Ok(HttpResponse::Ok().json(&readiness))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(&readiness))
+    }
+}
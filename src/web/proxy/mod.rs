@@ -12,9 +12,9 @@ use actix_web::{web, HttpResponse};
 use std::sync::{Arc, Mutex};
 
 use crate::api::transactions::Token;
-use crate::core::{Account, Money, Pool, Product, ServiceResult};
+use crate::core::{Account, Money, Pool, Product, ServiceResult, Uuid};
+use crate::web::metrics::{resource, scope};
 use sse::Broadcaster;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(tag = "type", content = "content")]
@@ -44,14 +44,7 @@ pub enum Message {
 }
 
 pub fn setup() -> Arc<Mutex<Broadcaster>> {
-    println!("---------------------------------------------------");
-    println!("|                                                 |");
-    println!("|             WARNING! - proxy demo               |");
-    println!("| Not for production! Only for frontend debugging |");
-    println!("|     Allows payments without authentication!     |");
-    println!("|      TODO: Remove proxy mod and references      |");
-    println!("|                                                 |");
-    println!("---------------------------------------------------");
+    warn!("Proxy demo enabled, allows payments without authentication! Not for production, only for frontend debugging");
     let broadcaster = sse::Broadcaster::create();
 
     Broadcaster::spawn_ping(broadcaster.clone());
@@ -61,28 +54,24 @@ pub fn setup() -> Arc<Mutex<Broadcaster>> {
 
 pub fn init(config: &mut web::ServiceConfig) {
     config
-        .service(web::resource("/events").to(sse::new_client))
+        .service(resource("/events").to(sse::new_client))
+        .service(resource("/request-payment-token").route(web::post().to(request_payment_token)))
+        .service(resource("/reauthenticate").route(web::get().to(request_reauthentication)))
         .service(
-            web::resource("/request-payment-token").route(web::post().to(request_payment_token)),
-        )
-        .service(web::resource("/reauthenticate").route(web::get().to(request_reauthentication)))
-        .service(
-            web::scope("/proxy-demo")
-                .service(web::resource("/account/{account_id}").route(web::get().to(get_account)))
-                .service(web::resource("/product/{product_id}").route(web::get().to(get_product)))
-                .service(web::resource("/qr-code/{code}").route(web::get().to(get_qr_code)))
+            scope("/proxy-demo")
+                .service(resource("/account/{account_id}").route(web::get().to(get_account)))
+                .service(resource("/product/{product_id}").route(web::get().to(get_product)))
+                .service(resource("/qr-code/{code}").route(web::get().to(get_qr_code)))
                 .service(
-                    web::resource("/nfc-card/{id}/{name}/{writeable}")
+                    resource("/nfc-card/{id}/{name}/{writeable}")
                         .route(web::get().to(get_nfc_card)),
                 )
+                .service(resource("/remove-nfc-card").route(web::get().to(get_remove_nfc_card)))
                 .service(
-                    web::resource("/remove-nfc-card").route(web::get().to(get_remove_nfc_card)),
-                )
-                .service(
-                    web::resource("/payment_token/{account_id}/{amount}")
+                    resource("/payment_token/{account_id}/{amount}")
                         .route(web::get().to(get_payment_token)),
                 )
-                .service(web::resource("/timeout").route(web::get().to(get_timeout))),
+                .service(resource("/timeout").route(web::get().to(get_timeout))),
         );
 }
 
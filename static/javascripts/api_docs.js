@@ -0,0 +1,205 @@
+// Minimal viewer for the OpenAPI document of `/api/v1/openapi.json`
+
+function element(tag, text, classes) {
+    let node = document.createElement(tag);
+    if (text) {
+        node.textContent = text;
+    }
+    for (let c of classes || []) {
+        node.classList.add(c);
+    }
+    return node;
+}
+
+function schemaName(ref) {
+    return ref.replace("#/components/schemas/", "");
+}
+
+// Render a schema as short type description, references link to their schema section
+function renderSchema(schema) {
+    let span = element("span");
+    if (!schema) {
+        return span;
+    }
+
+    if (schema["$ref"]) {
+        let name = schemaName(schema["$ref"]);
+        let link = element("a", name);
+        link.href = "#schema-" + name;
+        span.appendChild(link);
+    } else if (schema.type === "array") {
+        span.appendChild(document.createTextNode("["));
+        span.appendChild(renderSchema(schema.items));
+        span.appendChild(document.createTextNode("]"));
+    } else if (schema.allOf && schema.allOf.length === 1) {
+        span.appendChild(renderSchema(schema.allOf[0]));
+    } else if (schema.enum) {
+        span.appendChild(document.createTextNode(schema.enum.map(e => JSON.stringify(e)).join(" | ")));
+    } else {
+        let type = schema.type || "object";
+        if (schema.format) {
+            type += " (" + schema.format + ")";
+        }
+        span.appendChild(document.createTextNode(type));
+    }
+
+    if (schema.nullable) {
+        span.appendChild(document.createTextNode(" | null"));
+    }
+    return span;
+}
+
+// Render the properties of an object schema as table
+function renderProperties(schema) {
+    let table = element("table", null, ["table", "table-striped"]);
+    let required = schema.required || [];
+
+    for (let name in schema.properties || {}) {
+        let property = schema.properties[name];
+        let row = element("tr");
+
+        let cell = element("td");
+        cell.appendChild(element("code", name));
+        if (required.includes(name)) {
+            cell.appendChild(element("span", " required", ["text-error"]));
+        }
+        row.appendChild(cell);
+
+        cell = element("td");
+        cell.appendChild(renderSchema(property));
+        row.appendChild(cell);
+
+        row.appendChild(element("td", property.description || ""));
+        table.appendChild(row);
+    }
+
+    return table;
+}
+
+// Render a complete schema of the components section
+function renderSchemaSection(name, schema) {
+    let section = element("div", null, ["card", "card-top-padding"]);
+    section.id = "schema-" + name;
+
+    let header = element("div", null, ["card-header"]);
+    header.appendChild(element("div", name, ["card-title", "h5"]));
+    if (schema.description) {
+        header.appendChild(element("div", schema.description, ["card-subtitle", "text-gray"]));
+    }
+    section.appendChild(header);
+
+    let body = element("div", null, ["card-body"]);
+    let parts = schema.oneOf || schema.allOf || [schema];
+    if (schema.oneOf) {
+        body.appendChild(element("p", "One of:"));
+    }
+    for (let part of parts) {
+        if (part.properties) {
+            body.appendChild(renderProperties(part));
+        } else {
+            let p = element("p");
+            p.appendChild(renderSchema(part));
+            body.appendChild(p);
+        }
+    }
+    section.appendChild(body);
+
+    return section;
+}
+
+// Render a single operation of a path
+function renderOperation(path, method, operation, pathParameters) {
+    let section = element("div", null, ["card", "card-top-padding"]);
+
+    let header = element("div", null, ["card-header"]);
+    let title = element("div", null, ["card-title", "h5"]);
+    title.appendChild(element("span", method.toUpperCase(), ["label", "label-primary"]));
+    title.appendChild(document.createTextNode(" "));
+    title.appendChild(element("code", path));
+    header.appendChild(title);
+    header.appendChild(element("div", operation.summary || "", ["card-subtitle"]));
+    section.appendChild(header);
+
+    let body = element("div", null, ["card-body"]);
+    if (operation.description) {
+        body.appendChild(element("p", operation.description));
+    }
+
+    let parameters = (pathParameters || []).concat(operation.parameters || []);
+    if (parameters.length > 0) {
+        body.appendChild(element("h6", "Parameters"));
+        let schema = { properties: {}, required: [] };
+        for (let parameter of parameters) {
+            let property = Object.assign({}, parameter.schema);
+            property.description = "in " + parameter.in + (parameter.description ? ": " + parameter.description : "");
+            schema.properties[parameter.name] = property;
+            if (parameter.required) {
+                schema.required.push(parameter.name);
+            }
+        }
+        body.appendChild(renderProperties(schema));
+    }
+
+    if (operation.requestBody) {
+        body.appendChild(element("h6", "Request body"));
+        let p = element("p");
+        p.appendChild(renderSchema(operation.requestBody.content["application/json"].schema));
+        body.appendChild(p);
+    }
+
+    body.appendChild(element("h6", "Responses"));
+    let table = element("table", null, ["table"]);
+    for (let status in operation.responses) {
+        let response = operation.responses[status];
+        let row = element("tr");
+        row.appendChild(element("td", status));
+        row.appendChild(element("td", response.description));
+        let cell = element("td");
+        if (response.content) {
+            cell.appendChild(renderSchema(response.content["application/json"].schema));
+        }
+        row.appendChild(cell);
+        table.appendChild(row);
+    }
+    body.appendChild(table);
+    section.appendChild(body);
+
+    return section;
+}
+
+function renderDocument(doc) {
+    let root = document.getElementById("api-docs");
+    root.innerHTML = "";
+
+    root.appendChild(element("p", doc.info.description));
+
+    for (let tag of doc.tags) {
+        root.appendChild(element("h2", tag.name));
+        if (tag.description) {
+            root.appendChild(element("p", tag.description));
+        }
+
+        for (let path in doc.paths) {
+            let item = doc.paths[path];
+            for (let method in item) {
+                if (method !== "parameters" && item[method].tags.includes(tag.name)) {
+                    root.appendChild(renderOperation(path, method, item[method], item.parameters));
+                }
+            }
+        }
+    }
+
+    root.appendChild(element("h2", "Schemas"));
+    for (let name in doc.components.schemas) {
+        root.appendChild(renderSchemaSection(name, doc.components.schemas[name]));
+    }
+}
+
+window.addEventListener("load", () => {
+    fetch("/api/v1/openapi.json")
+        .then(response => response.json())
+        .then(renderDocument)
+        .catch(error => {
+            document.getElementById("api-docs").textContent = "Cannot load the api documentation: " + error;
+        });
+});
@@ -0,0 +1,153 @@
+class Color {
+    constructor(name) {
+        let value = getComputedStyle(document.documentElement)
+            .getPropertyValue(name).trim();
+
+        if (value.length == 4) {
+            this.red = parseInt(value.substr(1, 1), 16) * 16;
+            this.green = parseInt(value.substr(2, 1), 16) * 16;
+            this.blue = parseInt(value.substr(3, 1), 16) * 16;
+        } else {
+            this.red = parseInt(value.substr(1, 2), 16);
+            this.green = parseInt(value.substr(3, 2), 16);
+            this.blue = parseInt(value.substr(5, 2), 16);
+        }
+    }
+
+    toString(alpha) {
+        return 'rgba(' + [this.red, this.green, this.blue, alpha || 1.0].join(', ') + ')';
+    }
+}
+
+function format_currency(value) {
+    return value.toFixed(2) + "€";
+}
+
+let charts = {};
+
+function render_stats_diagram(canvas, entries) {
+    let group = canvas.dataset.group;
+
+    // Product and category statistics can get long, only show the best selling groups
+    if (group === "product" || group === "category") {
+        entries = entries.slice(0, 15);
+    }
+
+    let primaryColor = new Color('--primary-color');
+    let successColor = new Color('--success-color');
+    let gridColor = new Color('--border-color');
+    let textColor = new Color('--gray-color-dark');
+
+    Chart.defaults.global.defaultFontColor = textColor.toString(0.3);
+
+    let datasets = [
+        {
+            label: "Revenue",
+            yAxisID: "money",
+            borderColor: primaryColor.toString(),
+            backgroundColor: primaryColor.toString(0.6),
+            data: entries.map(e => e.revenue / 100)
+        }
+    ];
+    if (group !== "product" && group !== "category") {
+        datasets.push({
+            label: "Top-ups",
+            yAxisID: "money",
+            borderColor: successColor.toString(),
+            backgroundColor: successColor.toString(0.6),
+            data: entries.map(e => e.top_ups / 100)
+        });
+    }
+    datasets.push({
+        label: "Items",
+        type: "line",
+        yAxisID: "items",
+        fill: false,
+        lineTension: 0,
+        borderColor: textColor.toString(0.6),
+        backgroundColor: textColor.toString(0.6),
+        data: entries.map(e => e.items)
+    });
+
+    if (charts[group]) {
+        charts[group].destroy();
+    }
+
+    let gridLines = {
+        color: gridColor.toString(),
+        zeroLineColor: textColor.toString()
+    };
+
+    charts[group] = new Chart(canvas.getContext('2d'), {
+        type: "bar",
+        data: {
+            labels: entries.map(e => e.label),
+            datasets: datasets
+        },
+        options: {
+            animation: false,
+            scales: {
+                xAxes: [
+                    {
+                        gridLines: gridLines
+                    }
+                ],
+                yAxes: [
+                    {
+                        id: "money",
+                        position: "left",
+                        gridLines: gridLines,
+                        ticks: {
+                            beginAtZero: true,
+                            callback: format_currency
+                        }
+                    },
+                    {
+                        id: "items",
+                        position: "right",
+                        gridLines: {
+                            drawOnChartArea: false
+                        },
+                        ticks: {
+                            beginAtZero: true,
+                            precision: 0
+                        }
+                    }
+                ]
+            },
+            tooltips: {
+                mode: "index",
+                callbacks: {
+                    label: function (tooltipItem, data) {
+                        let dataset = data.datasets[tooltipItem.datasetIndex];
+                        let value = dataset.yAxisID === "money" ? format_currency(tooltipItem.yLabel) : tooltipItem.yLabel;
+                        return dataset.label + ": " + value;
+                    }
+                }
+            },
+            maintainAspectRatio: false
+        }
+    });
+}
+
+function load_stats() {
+    let form = document.getElementById("stats-range");
+    let from = form.elements["from"].value;
+    let to = form.elements["to"].value;
+
+    for (let canvas of document.querySelectorAll(".stats-diagram canvas")) {
+        let url = `/api/v1/stats?group=${canvas.dataset.group}&from=${from}&to=${to}`;
+        fetch(url, { credentials: "same-origin" })
+            .then(response => response.json())
+            .then(entries => render_stats_diagram(canvas, entries))
+            .catch(console.error);
+    }
+}
+
+window.addEventListener('DOMContentLoaded', () => {
+    document.getElementById("stats-range").addEventListener("submit", (event) => {
+        event.preventDefault();
+        load_stats();
+    });
+    load_stats();
+});
@@ -3,7 +3,8 @@ function generateRow(json) {
 
     cell = document.createElement("td");
     row.appendChild(cell);
-    cell.innerHTML = json.name_search;
+    cell.style.paddingLeft = json.depth + ".5rem";
+    cell.innerHTML = (json.depth > 0 ? "&#8627; " : "") + json.name_search;
 
     cell = document.createElement("td");
     row.appendChild(cell);
@@ -26,9 +27,14 @@ function updateTable(json) {
         tbody.removeChild(tbody.firstChild);
     }
 
+    appendRows(tbody, json);
+}
+
+function appendRows(tbody, json) {
     for (line of json) {
         row = generateRow(line);
         tbody.appendChild(row);
+        appendRows(tbody, line.children);
     }
 }
 
@@ -1,3 +1,14 @@
+function generateUnavailableLabel() {
+    label = document.createElement("span");
+    label.classList.add("label");
+    label.textContent = "unavailable";
+
+    span = document.createElement("span");
+    span.appendChild(document.createTextNode(" "));
+    span.appendChild(label);
+    return span;
+}
+
 function generateRow(json) {
     row = document.createElement("tr");
 
@@ -7,12 +18,15 @@ function generateRow(json) {
         img = document.createElement("img");
         cell.appendChild(img);
         img.classList.add("img-responsive");
-        img.src = "/product/image/" + json.image;
+        img.src = json.image_urls.small || json.image_urls.original;
     }
 
     cell = document.createElement("td");
     row.appendChild(cell);
     cell.innerHTML = json.name_search;
+    if (!json.available) {
+        cell.appendChild(generateUnavailableLabel());
+    }
 
     cell = document.createElement("td");
     row.appendChild(cell);
@@ -32,6 +46,38 @@ function generateRow(json) {
     return row;
 }
 
+function generateVariantRow(json) {
+    row = document.createElement("tr");
+
+    cell = document.createElement("td");
+    row.appendChild(cell);
+
+    cell = document.createElement("td");
+    row.appendChild(cell);
+    cell.textContent = "\u21b3 " + json.name;
+    if (!json.available) {
+        cell.appendChild(generateUnavailableLabel());
+    }
+
+    cell = document.createElement("td");
+    row.appendChild(cell);
+
+    cell = document.createElement("td");
+    row.appendChild(cell);
+    if (json.current_price) {
+        cell.textContent = (json.current_price / 100).toFixed(2) + "€";
+    }
+
+    cell = document.createElement("td");
+    row.appendChild(cell);
+    link = document.createElement("a");
+    cell.appendChild(link);
+    link.textContent = "Edit";
+    link.href = "/admin/product/" + json.id;
+
+    return row;
+}
+
 function updateTable(json) {
     tbody = document.getElementById("search-results");
 
@@ -42,6 +88,11 @@ function updateTable(json) {
     for (line of json) {
         row = generateRow(line);
         tbody.appendChild(row);
+
+        for (variant of line.variants) {
+            row = generateVariantRow(variant);
+            tbody.appendChild(row);
+        }
     }
 }
 